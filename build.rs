@@ -0,0 +1,4 @@
+fn main() {
+    // Cargo only tells build scripts the target, pass it on so `info` can print it
+    println!("cargo:rustc-env=TARGET={}", std::env::var("TARGET").unwrap());
+}
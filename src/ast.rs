@@ -1,11 +1,12 @@
 use std::fmt::{Display, Formatter};
 use std::rc::Rc;
 use crate::span::Span;
+use crate::symbol::Symbol;
 
 
 pub struct AST {
-    span: Span,
-    kind: ASTKind
+    pub(crate) span: Span,
+    pub(crate) kind: ASTKind
 }
 
 impl AST {
@@ -15,22 +16,530 @@ impl AST {
             kind
         }
     }
+
+    pub fn span(&self) -> &Span {
+        &self.span
+    }
+
+    pub fn kind(&self) -> &ASTKind {
+        &self.kind
+    }
+
+    /// Compare two trees by shape and contents only, ignoring every span.
+    pub fn structurally_eq(&self, other: &AST) -> bool {
+        fn all_eq(lhs: &[Rc<AST>], rhs: &[Rc<AST>]) -> bool {
+            lhs.len() == rhs.len() && lhs.iter().zip(rhs).all(|(lhs, rhs)| lhs.structurally_eq(rhs))
+        }
+        fn option_eq(lhs: &Option<Rc<AST>>, rhs: &Option<Rc<AST>>) -> bool {
+            match (lhs, rhs) {
+                (Some(lhs), Some(rhs)) => lhs.structurally_eq(rhs),
+                (None, None) => true,
+                _ => false
+            }
+        }
+        match (&self.kind, &other.kind) {
+            (ASTKind::StringLiteral(lhs), ASTKind::StringLiteral(rhs)) => lhs == rhs,
+            (ASTKind::InterpolatedString(lhs), ASTKind::InterpolatedString(rhs)) =>
+                lhs.len() == rhs.len() && lhs.iter().zip(rhs).all(|(lhs, rhs)| match (lhs, rhs) {
+                    (StringPart::Literal(lhs), StringPart::Literal(rhs)) => lhs == rhs,
+                    (StringPart::Expr(lhs), StringPart::Expr(rhs)) => lhs.structurally_eq(rhs),
+                    _ => false
+                }),
+            (ASTKind::IntegerLiteral(lhs), ASTKind::IntegerLiteral(rhs)) => lhs == rhs,
+            (ASTKind::FloatLiteral(lhs), ASTKind::FloatLiteral(rhs)) => lhs.to_bits() == rhs.to_bits(),
+            (ASTKind::BoolLiteral(lhs), ASTKind::BoolLiteral(rhs)) => lhs == rhs,
+            (ASTKind::Identifier(lhs), ASTKind::Identifier(rhs)) => lhs == rhs,
+            (ASTKind::Grouping(lhs), ASTKind::Grouping(rhs)) => lhs.structurally_eq(rhs),
+            (ASTKind::Unary { op, operand }, ASTKind::Unary { op: other_op, operand: other_operand }) =>
+                op == other_op && operand.structurally_eq(other_operand),
+            (ASTKind::Binary { op, lhs, rhs, .. }, ASTKind::Binary { op: other_op, lhs: other_lhs, rhs: other_rhs, .. }) =>
+                op == other_op && lhs.structurally_eq(other_lhs) && rhs.structurally_eq(other_rhs),
+            (ASTKind::Block(lhs), ASTKind::Block(rhs)) => all_eq(lhs, rhs),
+            (ASTKind::If { cond, then_branch, else_branch }, ASTKind::If { cond: other_cond, then_branch: other_then, else_branch: other_else }) =>
+                cond.structurally_eq(other_cond) && then_branch.structurally_eq(other_then) && option_eq(else_branch, other_else),
+            (ASTKind::While { cond, body }, ASTKind::While { cond: other_cond, body: other_body }) =>
+                cond.structurally_eq(other_cond) && body.structurally_eq(other_body),
+            (ASTKind::Let { name, value }, ASTKind::Let { name: other_name, value: other_value })
+            | (ASTKind::Assign { name, value }, ASTKind::Assign { name: other_name, value: other_value }) =>
+                name == other_name && value.structurally_eq(other_value),
+            (ASTKind::Function { name, params, body }, ASTKind::Function { name: other_name, params: other_params, body: other_body }) =>
+                name == other_name && params == other_params && body.structurally_eq(other_body),
+            (ASTKind::Call { callee, args }, ASTKind::Call { callee: other_callee, args: other_args }) =>
+                callee.structurally_eq(other_callee) && all_eq(args, other_args),
+            (ASTKind::ArrayLiteral(lhs), ASTKind::ArrayLiteral(rhs)) => all_eq(lhs, rhs),
+            (ASTKind::RecordLiteral(lhs), ASTKind::RecordLiteral(rhs)) =>
+                lhs.len() == rhs.len() && lhs.iter().zip(rhs).all(|((lhs_name, lhs), (rhs_name, rhs))| lhs_name == rhs_name && lhs.structurally_eq(rhs)),
+            (ASTKind::Index { object, index }, ASTKind::Index { object: other_object, index: other_index }) =>
+                object.structurally_eq(other_object) && index.structurally_eq(other_index),
+            (ASTKind::Member { object, name }, ASTKind::Member { object: other_object, name: other_name }) =>
+                name == other_name && object.structurally_eq(other_object),
+            (ASTKind::Range { start, end }, ASTKind::Range { start: other_start, end: other_end }) =>
+                option_eq(start, other_start) && option_eq(end, other_end),
+            (ASTKind::Cast { expr, target }, ASTKind::Cast { expr: other_expr, target: other_target }) =>
+                target == other_target && expr.structurally_eq(other_expr),
+            (ASTKind::Match { scrutinee, arms }, ASTKind::Match { scrutinee: other_scrutinee, arms: other_arms }) =>
+                scrutinee.structurally_eq(other_scrutinee)
+                    && arms.len() == other_arms.len()
+                    && arms.iter().zip(other_arms).all(|((lhs_pattern, lhs), (rhs_pattern, rhs))| lhs_pattern == rhs_pattern && lhs.structurally_eq(rhs)),
+            (ASTKind::Return(lhs), ASTKind::Return(rhs)) => option_eq(lhs, rhs),
+            (ASTKind::Import(lhs), ASTKind::Import(rhs)) => lhs == rhs,
+            (ASTKind::UnitLiteral, ASTKind::UnitLiteral)
+            | (ASTKind::Wildcard, ASTKind::Wildcard)
+            | (ASTKind::Break, ASTKind::Break)
+            | (ASTKind::Continue, ASTKind::Continue) => true,
+            _ => false
+        }
+    }
+
+    /// An indented tree with one node per line, showing each node's kind and span.
+    pub fn pretty_print(&self) -> String {
+        let mut out = String::new();
+        self.write_tree(&mut out, 0);
+        out
+    }
+
+    fn write_tree(&self, out: &mut String, depth: usize) {
+        let header = match &self.kind {
+            ASTKind::StringLiteral(val) => format!("StringLiteral {:?}", val),
+            ASTKind::InterpolatedString(_) => "InterpolatedString".to_string(),
+            ASTKind::IntegerLiteral(val) => format!("IntegerLiteral {}", val),
+            ASTKind::FloatLiteral(val) => format!("FloatLiteral {:?}", val),
+            ASTKind::BoolLiteral(val) => format!("BoolLiteral {}", val),
+            ASTKind::UnitLiteral => "UnitLiteral".to_string(),
+            ASTKind::Identifier(name) => format!("Identifier {}", name),
+            ASTKind::Grouping(_) => "Grouping".to_string(),
+            ASTKind::Unary { op, .. } => format!("Unary {}", op),
+            ASTKind::Binary { op, .. } => format!("Binary {}", op),
+            ASTKind::Block(_) => "Block".to_string(),
+            ASTKind::If { .. } => "If".to_string(),
+            ASTKind::While { .. } => "While".to_string(),
+            ASTKind::Let { name, .. } => format!("Let {}", name),
+            ASTKind::Assign { name, .. } => format!("Assign {}", name),
+            ASTKind::Function { name, params, .. } => format!("Function {}({})", name, params.join(", ")),
+            ASTKind::Call { .. } => "Call".to_string(),
+            ASTKind::ArrayLiteral(_) => "ArrayLiteral".to_string(),
+            ASTKind::RecordLiteral(fields) => format!("RecordLiteral {}", fields.iter().map(|(name, _)| &**name).collect::<Vec<_>>().join(", ")),
+            ASTKind::Index { .. } => "Index".to_string(),
+            ASTKind::Member { name, .. } => format!("Member {}", name),
+            ASTKind::Range { .. } => "Range".to_string(),
+            ASTKind::Cast { target, .. } => format!("Cast {}", target),
+            ASTKind::Match { arms, .. } => format!("Match {}", arms.iter().map(|(pattern, _)| pattern.to_string()).collect::<Vec<_>>().join(", ")),
+            ASTKind::Return(_) => "Return".to_string(),
+            ASTKind::Import(path) => format!("Import {:?}", path),
+            ASTKind::Wildcard => "Wildcard".to_string(),
+            ASTKind::Break => "Break".to_string(),
+            ASTKind::Continue => "Continue".to_string(),
+        };
+        out.push_str(&format!("{}{} @ {}..{}\n", "  ".repeat(depth), header, self.span.start, self.span.end));
+        for child in self.children() {
+            child.write_tree(out, depth + 1);
+        }
+    }
+
+    /// The nodes directly below this one, in source order.
+    pub fn children(&self) -> Vec<&Rc<AST>> {
+        match &self.kind {
+            ASTKind::InterpolatedString(parts) => parts.iter().filter_map(|part| match part {
+                StringPart::Expr(expr) => Some(expr),
+                StringPart::Literal(_) => None
+            }).collect(),
+            ASTKind::Grouping(inner) => vec![inner],
+            ASTKind::Unary { operand, .. } => vec![operand],
+            ASTKind::Binary { lhs, rhs, .. } => vec![lhs, rhs],
+            ASTKind::Block(nodes) | ASTKind::ArrayLiteral(nodes) => nodes.iter().collect(),
+            ASTKind::If { cond, then_branch, else_branch } => [Some(cond), Some(then_branch), else_branch.as_ref()].into_iter().flatten().collect(),
+            ASTKind::While { cond, body } => vec![cond, body],
+            ASTKind::Let { value, .. } | ASTKind::Assign { value, .. } => vec![value],
+            ASTKind::Function { body, .. } => vec![body],
+            ASTKind::Call { callee, args } => std::iter::once(callee).chain(args).collect(),
+            ASTKind::RecordLiteral(fields) => fields.iter().map(|(_, value)| value).collect(),
+            ASTKind::Index { object, index } => vec![object, index],
+            ASTKind::Member { object, .. } => vec![object],
+            ASTKind::Range { start, end } => start.iter().chain(end).collect(),
+            ASTKind::Cast { expr, .. } => vec![expr],
+            ASTKind::Match { scrutinee, arms } => std::iter::once(scrutinee).chain(arms.iter().map(|(_, body)| body)).collect(),
+            ASTKind::Return(value) => value.iter().collect(),
+            ASTKind::StringLiteral(_)
+            | ASTKind::IntegerLiteral(_)
+            | ASTKind::FloatLiteral(_)
+            | ASTKind::BoolLiteral(_)
+            | ASTKind::UnitLiteral
+            | ASTKind::Identifier(_)
+            | ASTKind::Wildcard
+            | ASTKind::Import(_)
+            | ASTKind::Break
+            | ASTKind::Continue => vec![]
+        }
+    }
+
+    /// The deepest node whose span contains the byte at `offset`, like the node under an editor's
+    /// cursor. Only nodes from this node's own file are considered, not ones spliced in by an import.
+    pub fn node_at(&self, offset: usize) -> Option<&AST> {
+        if !self.span.contains(offset) {
+            return None;
+        }
+        self.children().into_iter()
+            .filter(|child| child.span.same_file(&self.span))
+            .find_map(|child| child.node_at(offset))
+            .or(Some(self))
+    }
 }
 
 pub enum ASTKind {
     StringLiteral(String),
+    /// `"hello ${name}"`, split into its literal text and embedded expressions in source order.
+    InterpolatedString(Vec<StringPart>),
     IntegerLiteral(isize),
     FloatLiteral(f64),
-    Add(Rc<AST>, Rc<AST>)
+    BoolLiteral(bool),
+    /// `()`, the value of anything that has no other value to give.
+    UnitLiteral,
+    Identifier(Symbol),
+    /// A bare `_`, which discards whatever would be bound to it.
+    Wildcard,
+    /// An expression in parentheses, kept so the source can be reproduced exactly.
+    Grouping(Rc<AST>),
+    Unary {
+        op: UnaryOp,
+        operand: Rc<AST>
+    },
+    Binary {
+        op: BinaryOp,
+        op_span: Span,
+        lhs: Rc<AST>,
+        rhs: Rc<AST>
+    },
+    Block(Vec<Rc<AST>>),
+    If {
+        cond: Rc<AST>,
+        then_branch: Rc<AST>,
+        else_branch: Option<Rc<AST>>
+    },
+    While {
+        cond: Rc<AST>,
+        body: Rc<AST>
+    },
+    Let {
+        name: Symbol,
+        value: Rc<AST>
+    },
+    Assign {
+        name: Symbol,
+        value: Rc<AST>
+    },
+    Function {
+        name: Symbol,
+        params: Vec<Symbol>,
+        body: Rc<AST>
+    },
+    Call {
+        callee: Rc<AST>,
+        args: Vec<Rc<AST>>
+    },
+    ArrayLiteral(Vec<Rc<AST>>),
+    /// `{ x: 1, y: 2 }`, with the fields in source order.
+    RecordLiteral(Vec<(Symbol, Rc<AST>)>),
+    Index {
+        object: Rc<AST>,
+        index: Rc<AST>
+    },
+    Member {
+        object: Rc<AST>,
+        name: Symbol
+    },
+    Range {
+        start: Option<Rc<AST>>,
+        end: Option<Rc<AST>>
+    },
+    Cast {
+        expr: Rc<AST>,
+        target: TypeName
+    },
+    Match {
+        scrutinee: Rc<AST>,
+        arms: Vec<(Pattern, Rc<AST>)>
+    },
+    Return(Option<Rc<AST>>),
+    /// `import "path"`, replaced by the statements of that file before the program runs.
+    Import(String),
+    Break,
+    Continue
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BinaryOp {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Eq,
+    NotEq,
+    Less,
+    LessEq,
+    Greater,
+    GreaterEq,
+    And,
+    Or
+}
+
+impl BinaryOp {
+    pub fn is_arithmetic(self) -> bool {
+        matches!(self, BinaryOp::Add | BinaryOp::Sub | BinaryOp::Mul | BinaryOp::Div)
+    }
+
+    /// `&&` and `||` only evaluate their right-hand side when it can change the result.
+    pub fn is_logical(self) -> bool {
+        matches!(self, BinaryOp::And | BinaryOp::Or)
+    }
+
+    pub fn is_comparison(self) -> bool {
+        matches!(self, BinaryOp::Eq | BinaryOp::NotEq | BinaryOp::Less | BinaryOp::LessEq | BinaryOp::Greater | BinaryOp::GreaterEq)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum UnaryOp {
+    Neg
+}
+
+impl Display for UnaryOp {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            UnaryOp::Neg => write!(f, "-")
+        }
+    }
+}
+
+/// One piece of an interpolated string.
+pub enum StringPart {
+    /// Text taken as written, with its escapes already resolved.
+    Literal(String),
+    /// The expression inside a `${...}`, shown with `Display`.
+    Expr(Rc<AST>)
+}
+
+/// What a `match` arm compares its value against.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Pattern {
+    Int(isize),
+    Float(f64),
+    Str(String),
+    Bool(bool),
+    /// `_`, which matches anything.
+    Wildcard
+}
+
+impl Display for Pattern {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Pattern::Int(val) => write!(f, "{}", val),
+            Pattern::Float(val) => write!(f, "{:?}", val),
+            Pattern::Str(val) => write!(f, "{:?}", val),
+            Pattern::Bool(val) => write!(f, "{}", val),
+            Pattern::Wildcard => write!(f, "_")
+        }
+    }
+}
+
+/// A type that a value can be converted to with `as`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TypeName {
+    Int,
+    Float,
+    String
+}
+
+impl Display for TypeName {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TypeName::Int => write!(f, "int"),
+            TypeName::Float => write!(f, "float"),
+            TypeName::String => write!(f, "string")
+        }
+    }
+}
+
+impl Display for BinaryOp {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            BinaryOp::Add => write!(f, "+"),
+            BinaryOp::Sub => write!(f, "-"),
+            BinaryOp::Mul => write!(f, "*"),
+            BinaryOp::Div => write!(f, "/"),
+            BinaryOp::Eq => write!(f, "=="),
+            BinaryOp::NotEq => write!(f, "!="),
+            BinaryOp::Less => write!(f, "<"),
+            BinaryOp::LessEq => write!(f, "<="),
+            BinaryOp::Greater => write!(f, ">"),
+            BinaryOp::GreaterEq => write!(f, ">="),
+            BinaryOp::And => write!(f, "&&"),
+            BinaryOp::Or => write!(f, "||")
+        }
+    }
 }
 
 impl Display for AST {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         match &self.kind {
             ASTKind::StringLiteral(val) => write!(f, "{:?}", val),
+            ASTKind::InterpolatedString(parts) => {
+                write!(f, "\"")?;
+                for part in parts {
+                    match part {
+                        StringPart::Literal(text) => write!(f, "{}", text.escape_debug().to_string().replace("${", "\\${"))?,
+                        StringPart::Expr(expr) => write!(f, "${{{}}}", expr)?
+                    }
+                }
+                write!(f, "\"")
+            }
             ASTKind::IntegerLiteral(val) => write!(f, "{}", val),
             ASTKind::FloatLiteral(val) => write!(f, "{}", val),
-            ASTKind::Add(lhs, rhs) => write!(f, "{} + {}", lhs, rhs)
+            ASTKind::BoolLiteral(val) => write!(f, "{}", val),
+            ASTKind::UnitLiteral => write!(f, "()"),
+            ASTKind::Identifier(name) => write!(f, "{}", name),
+            ASTKind::Unary { op, operand } => write!(f, "{}{}", op, operand),
+            ASTKind::Binary { op, lhs, rhs, .. } => write!(f, "{} {} {}", lhs, op, rhs),
+            ASTKind::Block(statements) => {
+                write!(f, "{{")?;
+                for statement in statements {
+                    write!(f, " {};", statement)?;
+                }
+                write!(f, " }}")
+            }
+            ASTKind::If { cond, then_branch, else_branch } => {
+                write!(f, "if {} {}", cond, then_branch)?;
+                if let Some(else_branch) = else_branch {
+                    write!(f, " else {}", else_branch)?;
+                }
+                Ok(())
+            }
+            ASTKind::While { cond, body } => write!(f, "while {} {}", cond, body),
+            ASTKind::Let { name, value } => write!(f, "let {} = {}", name, value),
+            ASTKind::Assign { name, value } => write!(f, "{} = {}", name, value),
+            ASTKind::Function { name, params, body } => write!(f, "fn {}({}) {}", name, params.join(", "), body),
+            ASTKind::Call { callee, args } => {
+                write!(f, "{}(", callee)?;
+                for (i, arg) in args.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}", arg)?;
+                }
+                write!(f, ")")
+            }
+            ASTKind::ArrayLiteral(elements) => {
+                write!(f, "[")?;
+                for (i, element) in elements.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}", element)?;
+                }
+                write!(f, "]")
+            }
+            ASTKind::RecordLiteral(fields) => {
+                write!(f, "{{ ")?;
+                for (i, (name, value)) in fields.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}: {}", name, value)?;
+                }
+                write!(f, " }}")
+            }
+            ASTKind::Index { object, index } => write!(f, "{}[{}]", object, index),
+            ASTKind::Member { object, name } => write!(f, "{}.{}", object, name),
+            ASTKind::Cast { expr, target } => write!(f, "{} as {}", expr, target),
+            ASTKind::Match { scrutinee, arms } => {
+                write!(f, "match {} {{ ", scrutinee)?;
+                for (i, (pattern, body)) in arms.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{} => {}", pattern, body)?;
+                }
+                write!(f, " }}")
+            }
+            ASTKind::Range { start, end } => {
+                if let Some(start) = start {
+                    write!(f, "{}", start)?;
+                }
+                write!(f, "..")?;
+                if let Some(end) = end {
+                    write!(f, "{}", end)?;
+                }
+                Ok(())
+            }
+            ASTKind::Return(Some(value)) => write!(f, "return {}", value),
+            ASTKind::Return(None) => write!(f, "return"),
+            ASTKind::Import(path) => write!(f, "import {:?}", path),
+            ASTKind::Wildcard => write!(f, "_"),
+            ASTKind::Grouping(inner) => write!(f, "({})", inner),
+            ASTKind::Break => write!(f, "break"),
+            ASTKind::Continue => write!(f, "continue"),
         }
     }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    fn parse(source: &str) -> std::rc::Rc<super::AST> {
+        let (ast, reports) = crate::parse(Arc::from("test"), source);
+        assert!(reports.is_empty());
+        ast.unwrap()
+    }
+
+    #[test]
+    fn pretty_print_indents_each_child() {
+        assert_eq!(parse("let x = -(1 + y);").pretty_print(), "\
+Block @ 0..17
+  Let x @ 0..15
+    Unary - @ 8..15
+      Grouping @ 9..15
+        Binary + @ 10..14
+          IntegerLiteral 1 @ 10..10
+          Identifier y @ 14..14
+");
+    }
+
+    #[test]
+    fn display_stays_on_one_line() {
+        assert_eq!(parse("let x = -(1 + y);").to_string(), "{ let x = -(1 + y); }");
+    }
+
+    #[test]
+    fn structural_equality_ignores_spans_and_layout() {
+        let a = parse("let x = (1 + 2) * y;");
+        let b = parse("let   x =\n  (1+2)*y ;");
+        assert!(a.structurally_eq(&b));
+    }
+
+    #[test]
+    fn structural_equality_sees_different_trees() {
+        assert!(!parse("1 + 2 * 3;").structurally_eq(&parse("(1 + 2) * 3;")));
+        assert!(!parse("let x = 1;").structurally_eq(&parse("let y = 1;")));
+    }
+
+    #[test]
+    fn display_keeps_every_grouping() {
+        assert_eq!(parse("((1 + 2)) * 3;").to_string(), "{ ((1 + 2)) * 3; }");
+    }
+
+    #[test]
+    fn node_at_finds_the_deepest_node() {
+        let ast = parse("1 + 22;");
+        let node = ast.node_at(5).unwrap();
+        assert!(matches!(node.kind(), super::ASTKind::IntegerLiteral(22)));
+        assert!(matches!(ast.node_at(2).unwrap().kind(), super::ASTKind::Binary { .. }));
+        assert!(matches!(ast.node_at(0).unwrap().kind(), super::ASTKind::IntegerLiteral(1)));
+    }
+
+    #[test]
+    fn node_at_outside_the_tree_is_none() {
+        let ast = parse("let x = 1;");
+        assert!(ast.node_at(100).is_none());
+        let super::ASTKind::Block(statements) = ast.kind() else { unreachable!() };
+        assert!(statements[0].node_at(9).is_none());
+    }
+}
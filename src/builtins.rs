@@ -0,0 +1,113 @@
+use std::io::Write;
+use ariadne::Color;
+use crate::environment::Environment;
+use crate::error::{ErrorReport, ErrorReportKind, Label, Result};
+use crate::span::Span;
+use crate::symbol::Symbol;
+use crate::value::{NativeFunction, Value};
+
+const BUILTINS: &[NativeFunction] = &[
+    NativeFunction { name: "len", arity: 1, function: len },
+    NativeFunction { name: "print", arity: 1, function: print },
+    NativeFunction { name: "println", arity: 1, function: println },
+    NativeFunction { name: "type", arity: 1, function: type_of },
+];
+
+pub fn register(environment: &mut Environment) {
+    for builtin in BUILTINS {
+        environment.define(Symbol::from(builtin.name), Value::NativeFunction(builtin.clone()));
+    }
+}
+
+fn type_error(function: &str, expected: &str, value: &Value, span: &Span) -> ErrorReport {
+    ErrorReport::new(ErrorReportKind::TypeError, span.clone(), format!("`{}` expects {} but got {}", function, expected, value.type_name()))
+        .with_label(Label::new(span.clone()).with_message(format!("This has type {}", value.type_name())).with_color(Color::Red))
+}
+
+fn len(_out: &mut dyn Write, args: &[(Value, Span)]) -> Result<Value> {
+    match &args[0] {
+        (Value::Str(val), _) => Ok(Value::Int(val.chars().count() as isize)),
+        (Value::Array(values), _) => Ok(Value::Int(values.borrow().len() as isize)),
+        (value, span) => Err(type_error("len", "a string or array", value, span))
+    }
+}
+
+fn type_of(_out: &mut dyn Write, args: &[(Value, Span)]) -> Result<Value> {
+    Ok(Value::Str(args[0].0.type_name().to_string()))
+}
+
+fn print(out: &mut dyn Write, args: &[(Value, Span)]) -> Result<Value> {
+    let (value, span) = &args[0];
+    write!(out, "{}", value).map_err(|error| output_error(error, span))?;
+    Ok(Value::Unit)
+}
+
+fn println(out: &mut dyn Write, args: &[(Value, Span)]) -> Result<Value> {
+    let (value, span) = &args[0];
+    writeln!(out, "{}", value).map_err(|error| output_error(error, span))?;
+    Ok(Value::Unit)
+}
+
+fn output_error(error: std::io::Error, span: &Span) -> ErrorReport {
+    ErrorReport::new(ErrorReportKind::RuntimeError, span.clone(), "Output Error".to_string())
+        .with_label(Label::new(span.clone()).with_message(format!("Could not write this: {}", error)).with_color(Color::Red))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use super::*;
+
+    fn arg(value: Value) -> (Value, Span) {
+        (value, Span::new(0, 0, Arc::from("test")))
+    }
+
+    #[test]
+    fn len_counts_chars() {
+        assert!(matches!(len(&mut Vec::new(), &[arg(Value::Str("hi".to_string()))]), Ok(Value::Int(2))));
+        assert!(matches!(len(&mut Vec::new(), &[arg(Value::Str("né".to_string()))]), Ok(Value::Int(2))));
+    }
+
+    #[test]
+    fn len_of_a_non_string_is_a_type_error() {
+        let error = len(&mut Vec::new(), &[arg(Value::Int(1))]).unwrap_err();
+        assert_eq!(error.title(), "TypeError[E0005]: `len` expects a string or array but got int");
+    }
+
+    #[test]
+    fn print_writes_the_value_and_returns_unit() {
+        let mut out = Vec::new();
+        assert!(matches!(print(&mut out, &[arg(Value::Str("hi".to_string()))]), Ok(Value::Unit)));
+        assert_eq!(out, b"hi");
+    }
+
+    #[test]
+    fn builtins_are_registered_globally() {
+        let mut globals = Environment::new();
+        register(&mut globals);
+        for builtin in BUILTINS {
+            assert!(matches!(globals.get(builtin.name), Some(Value::NativeFunction(_))), "{} is missing", builtin.name);
+        }
+    }
+
+    #[test]
+    fn type_names_every_kind_of_value() {
+        let cases = [
+            ("type(1)", "int"),
+            ("type(1.5)", "float"),
+            ("type(\"s\")", "string"),
+            ("type(true)", "bool"),
+            ("type([1])", "array"),
+            ("type({ a: 1 })", "record"),
+            ("fn f() { 1 } type(f)", "function"),
+            ("type(len)", "function"),
+            ("type(1..2)", "range"),
+            ("type(())", "unit")
+        ];
+        for (source, name) in cases {
+            let (value, reports) = crate::eval(Arc::from("test"), source);
+            assert!(reports.is_empty(), "{}", source);
+            assert_eq!(value.unwrap().to_string(), name, "{}", source);
+        }
+    }
+}
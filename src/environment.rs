@@ -0,0 +1,57 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+use crate::symbol::Symbol;
+use crate::value::Value;
+
+pub struct Environment {
+    values: HashMap<Symbol, Value>,
+    parent: Option<Rc<RefCell<Environment>>>
+}
+
+impl Environment {
+    pub fn new() -> Self {
+        Environment {
+            values: HashMap::new(),
+            parent: None
+        }
+    }
+
+    pub fn with_parent(parent: Rc<RefCell<Environment>>) -> Self {
+        Environment {
+            values: HashMap::new(),
+            parent: Some(parent)
+        }
+    }
+
+    pub fn define(&mut self, name: Symbol, value: Value) {
+        self.values.insert(name, value);
+    }
+
+    /// Update the variable in the innermost scope defining it, returning false if no scope does.
+    pub fn assign(&mut self, name: &str, value: Value) -> bool {
+        match self.values.get_mut(name) {
+            Some(slot) => {
+                *slot = value;
+                true
+            }
+            None => match &self.parent {
+                Some(parent) => parent.borrow_mut().assign(name, value),
+                None => false
+            }
+        }
+    }
+
+    pub fn get(&self, name: &str) -> Option<Value> {
+        match self.values.get(name) {
+            Some(value) => Some(value.clone()),
+            None => self.parent.as_ref().and_then(|parent| parent.borrow().get(name))
+        }
+    }
+}
+
+impl Default for Environment {
+    fn default() -> Self {
+        Self::new()
+    }
+}
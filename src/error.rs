@@ -1,6 +1,6 @@
-use std::cmp::Ordering;
-use std::fmt::format;
-use ariadne::{Color, Config, LabelAttach, Report, ReportBuilder, ReportKind};
+use std::collections::HashMap;
+use std::sync::Arc;
+use ariadne::{Cache, Color, Config, Report, ReportKind, Source};
 use clap::ValueEnum;
 use crate::span::{Span};
 
@@ -17,41 +17,201 @@ pub enum ErrorLevel {
     /// Show most information.
     Normal,
     // Show extra information.
-    Debug
+    Debug,
+    /// Print each report as a JSON object, one per line.
+    Json
 }
 
-#[derive(Clone)]
+/// Every source file a run has touched, so reports can point into any of them.
+#[derive(Default)]
+pub struct SourceCache {
+    sources: HashMap<Arc<str>, Source>
+}
+
+impl SourceCache {
+    pub fn new() -> Self {
+        SourceCache {
+            sources: HashMap::new()
+        }
+    }
+
+    pub fn insert(&mut self, filename: Arc<str>, contents: &str) {
+        // EOF spans sit one past the last char, so give them a blank column to point at
+        self.sources.insert(filename, Source::from(format!("{} ", contents)));
+    }
+}
+
+impl Cache<Arc<str>> for SourceCache {
+    type Storage = String;
+
+    fn fetch(&mut self, id: &Arc<str>) -> std::result::Result<&Source, Box<dyn std::fmt::Debug + '_>> {
+        self.sources.get(id).ok_or_else(|| Box::new(format!("No source loaded for {}", id)) as Box<dyn std::fmt::Debug>)
+    }
+
+    fn display<'a>(&self, id: &'a Arc<str>) -> Option<Box<dyn std::fmt::Display + 'a>> {
+        Some(Box::new(id.clone()))
+    }
+}
+
+#[derive(PartialEq, Copy, Clone, Debug)]
+pub enum Severity {
+    Error,
+    Warning,
+    Advice
+}
+
+impl Severity {
+    fn report_kind(self) -> ReportKind<'static> {
+        match self {
+            Severity::Error => ReportKind::Error,
+            Severity::Warning => ReportKind::Warning,
+            Severity::Advice => ReportKind::Advice
+        }
+    }
+
+    fn color(self) -> Color {
+        match self {
+            Severity::Error => Color::Red,
+            Severity::Warning => Color::Yellow,
+            Severity::Advice => Color::BrightBlue
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Label {
+    span: Span,
+    message: Option<String>,
+    color: Option<Color>,
+    order: i32
+}
+
+impl Label {
+    pub fn new(span: Span) -> Self {
+        Label {
+            span,
+            message: None,
+            color: None,
+            order: 0
+        }
+    }
+
+    pub fn with_message<M: ToString>(mut self, message: M) -> Self {
+        self.message = Some(message.to_string());
+        self
+    }
+
+    pub fn with_color(mut self, color: Color) -> Self {
+        self.color = Some(color);
+        self
+    }
+
+    pub fn with_order(mut self, order: i32) -> Self {
+        self.order = order;
+        self
+    }
+
+    pub fn span(&self) -> &Span {
+        &self.span
+    }
+
+    pub fn message(&self) -> Option<&str> {
+        self.message.as_deref()
+    }
+
+    fn to_ariadne_label(&self) -> ariadne::Label<Span> {
+        let mut label = ariadne::Label::new(self.span.clone()).with_order(self.order);
+        if let Some(message) = &self.message {
+            label = label.with_message(message);
+        }
+        if let Some(color) = self.color {
+            label = label.with_color(color);
+        }
+        label
+    }
+
+    fn to_json(&self) -> String {
+        let message = self.message.as_deref().map_or("null".to_string(), json_string);
+        // `end` is exclusive here, as editors expect, unlike the inclusive end spans keep
+        format!("{{\"start\":{},\"end\":{},\"message\":{}}}", self.span.start, self.span.end + 1, message)
+    }
+}
+
+#[derive(Clone, Debug)]
 pub struct ErrorReport {
+    pub severity: Severity,
     span: Span,
     title: String,
-    labels: Vec<ariadne::Label<Span>>,
-    debug_labels: Vec<ariadne::Label<Span>>,
+    labels: Vec<Label>,
+    debug_labels: Vec<Label>,
     note: Option<String>,
+    help: Option<String>,
 }
 
 impl ErrorReport {
     pub fn new(kind: ErrorReportKind, span: Span, message: String) -> Self {
-        let title = if kind != ErrorReportKind::Custom {
-            // span does not store a line or column, printing indexes will confuse most IDEs
-            format!("{:?}: {}", kind, message)
-        } else {
-            message
+        let title = match kind.code() {
+            Some(code) => format!("{:?}[{}]: {}", kind, code, message),
+            None => message
         };
         ErrorReport {
+            severity: Severity::Error,
             span,
             title,
             labels: Vec::new(),
             debug_labels: Vec::new(),
             note: None,
+            help: None,
         }
     }
 
-    pub fn with_label(mut self, label: ariadne::Label<Span>) -> Self {
+    pub fn with_severity(mut self, severity: Severity) -> Self {
+        self.severity = severity;
+        self
+    }
+
+    pub fn is_error(&self) -> bool {
+        self.severity == Severity::Error
+    }
+
+    pub fn title(&self) -> &str {
+        &self.title
+    }
+
+    pub fn span(&self) -> &Span {
+        &self.span
+    }
+
+    pub fn labels(&self) -> &[Label] {
+        &self.labels
+    }
+
+    pub fn note(&self) -> Option<&str> {
+        self.note.as_deref()
+    }
+
+    pub fn help(&self) -> Option<&str> {
+        self.help.as_deref()
+    }
+
+    /// Orders reports by where they point in the source, so they can be emitted top-to-bottom.
+    pub fn cmp_location(&self, other: &ErrorReport) -> std::cmp::Ordering {
+        (&self.span.filename, self.span.start, self.span.end)
+            .cmp(&(&other.span.filename, other.span.start, other.span.end))
+    }
+
+    pub fn with_label(mut self, label: Label) -> Self {
         self.labels.push(label);
         self
     }
 
-    pub fn with_debug_label(mut self, label: ariadne::Label<Span>) -> Self {
+    /// Point at a related location, like where something was first defined. Secondary labels are
+    /// drawn after the primary ones.
+    pub fn with_secondary_label<M: ToString>(self, span: Span, message: M) -> Self {
+        self.with_label(Label::new(span).with_message(message).with_color(Color::BrightBlue).with_order(1))
+    }
+
+    pub fn with_debug_label(mut self, label: Label) -> Self {
         self.debug_labels.push(label);
         self
     }
@@ -61,30 +221,84 @@ impl ErrorReport {
         self
     }
 
-    pub fn to_ariadne_report(&self, level: ErrorLevel) -> Report<Span> {
+    /// Unlike a note, which states a fact, help suggests what to change.
+    pub fn with_help(mut self, help: String) -> Self {
+        self.help = Some(help);
+        self
+    }
+
+    /// Render with the given ariadne `Config`, compact output additionally turns on compact mode.
+    pub fn to_ariadne_report(&self, level: ErrorLevel, config: Config) -> Report<'_, Span> {
         let mut report = match level {
             ErrorLevel::Silent => unreachable!("Cannot make a silent ariadne report."),
-            ErrorLevel::Compact => {
-                let report_kind = ReportKind::Custom(Box::leak(format!("[{}] Error", self.span.clone()).into_boxed_str()), Color::Red);
-                Report::build(report_kind, self.span.filename.clone(), self.span.start)
+            ErrorLevel::Json => unreachable!("Cannot make a json ariadne report."),
+            // The span goes where ariadne puts a report code, giving `[file:start:end] Error: title`. A
+            // custom report kind could do the same, but its color would ignore `config` turning color off
+            ErrorLevel::Compact => Report::build(self.severity.report_kind(), self.span.filename.clone(), self.span.start)
+                .with_code(&self.span)
                 .with_message(self.title.clone())
-                .with_config(Config::default().with_compact(true))
-            },
-            ErrorLevel::Normal => Report::build(ReportKind::Error, self.span.filename.clone(), self.span.start)
+                .with_config(config.with_compact(true)),
+            ErrorLevel::Normal => Report::build(self.severity.report_kind(), self.span.filename.clone(), self.span.start)
+                .with_config(config)
                 .with_message(self.title.clone())
-                .with_labels(self.labels.clone()),
-            ErrorLevel::Debug => Report::build(ReportKind::Error, self.span.filename.clone(), self.span.start)
+                .with_labels(self.labels.iter().map(Label::to_ariadne_label)),
+            ErrorLevel::Debug => Report::build(self.severity.report_kind(), self.span.filename.clone(), self.span.start)
+                .with_config(config)
                 .with_message(self.title.clone())
-                .with_labels(self.labels.clone())
-                .with_labels(self.debug_labels.clone())
+                .with_labels(self.labels.iter().map(Label::to_ariadne_label))
+                .with_labels(self.debug_labels.iter().map(Label::to_ariadne_label))
         };
+        // Without any label ariadne prints no source at all, so point at the report's own span
+        if self.labels.is_empty() && matches!(level, ErrorLevel::Normal | ErrorLevel::Debug) {
+            report = report.with_label(Label::new(self.span.clone()).with_color(self.severity.color()).to_ariadne_label());
+        }
         report = if let Some(note) = self.note.clone() {
             report.with_note(note)
         } else {
             report
         };
+        report = if let Some(help) = self.help.clone() {
+            report.with_help(help)
+        } else {
+            report
+        };
         report.finish()
     }
+
+    pub fn to_json(&self) -> String {
+        let labels = self.labels.iter().map(Label::to_json).collect::<Vec<_>>().join(",");
+        let note = self.note.as_deref().map_or("null".to_string(), json_string);
+        let help = self.help.as_deref().map_or("null".to_string(), json_string);
+        format!(
+            "{{\"severity\":{},\"title\":{},\"filename\":{},\"start\":{},\"end\":{},\"labels\":[{}],\"note\":{},\"help\":{}}}",
+            json_string(&format!("{:?}", self.severity).to_lowercase()),
+            json_string(&self.title),
+            json_string(&self.span.filename),
+            self.span.start,
+            self.span.end + 1,
+            labels,
+            note,
+            help
+        )
+    }
+}
+
+fn json_string(value: &str) -> String {
+    let mut out = String::with_capacity(value.len() + 2);
+    out.push('"');
+    for c in value.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if c.is_control() => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c)
+        }
+    }
+    out.push('"');
+    out
 }
 
 #[derive(Debug, PartialEq)]
@@ -94,9 +308,177 @@ pub enum ErrorReportKind {
     UnexpectedCharacter,
     UnexpectedToken,
     DidYouMean,
+    TypeError,
+    NameError,
+    RuntimeError,
+    ImportError,
+    IndentationStyle,
     Custom
 }
 
+impl ErrorReportKind {
+    const CODED: [ErrorReportKind; 9] = [
+        ErrorReportKind::SyntaxError,
+        ErrorReportKind::UnexpectedCharacter,
+        ErrorReportKind::UnexpectedToken,
+        ErrorReportKind::DidYouMean,
+        ErrorReportKind::TypeError,
+        ErrorReportKind::NameError,
+        ErrorReportKind::RuntimeError,
+        ErrorReportKind::ImportError,
+        ErrorReportKind::IndentationStyle
+    ];
+
+    /// The stable code shown in a report's title, which `explain` looks up. Custom reports have none.
+    pub fn code(&self) -> Option<&'static str> {
+        match self {
+            ErrorReportKind::SyntaxError => Some("E0001"),
+            ErrorReportKind::UnexpectedCharacter => Some("E0002"),
+            ErrorReportKind::UnexpectedToken => Some("E0003"),
+            ErrorReportKind::DidYouMean => Some("E0004"),
+            ErrorReportKind::TypeError => Some("E0005"),
+            ErrorReportKind::NameError => Some("E0006"),
+            ErrorReportKind::RuntimeError => Some("E0007"),
+            ErrorReportKind::ImportError => Some("E0008"),
+            ErrorReportKind::IndentationStyle => Some("E0009"),
+            ErrorReportKind::Custom => None
+        }
+    }
+
+    /// Look a kind up by its code, ignoring case so `e0001` works too.
+    pub fn from_code(code: &str) -> Option<ErrorReportKind> {
+        Self::CODED.into_iter().find(|kind| kind.code().is_some_and(|own| own.eq_ignore_ascii_case(code)))
+    }
+
+    /// A longer description of the kind, followed by an example of the error and its fix.
+    pub fn explanation(&self) -> &'static str {
+        match self {
+            ErrorReportKind::SyntaxError => "\
+A literal, statement or expression is malformed: a string or comment is never
+closed, an integer literal has a digit its base doesn't allow or doesn't fit
+its type, or a statement is missing a part it needs.
+
+Erroneous example:
+
+    let x = 0b102;
+
+`2` is not a binary digit. Write the number in a base that allows it:
+
+    let x = 0o102;
+",
+            ErrorReportKind::UnexpectedCharacter => "\
+The lexer found a character that cannot start any token. Outside of strings and
+comments only ASCII punctuation the language uses is allowed.
+
+Erroneous example:
+
+    let price = 5 @ 2;
+
+Remove the character, or put it in a string if it is meant as text:
+
+    let price = \"5 @ 2\";
+",
+            ErrorReportKind::UnexpectedToken => "\
+The parser found a token where it cannot go, such as two expressions with nothing
+between them, or a statement running on without a `;` to end it.
+
+Erroneous example:
+
+    let x = 1 2;
+
+Join the two with an operator, or end the statement after the first:
+
+    let x = 1 + 2;
+",
+            ErrorReportKind::DidYouMean => "\
+An identifier is spelled almost like a keyword, and is used where that keyword
+would go.
+
+Erroneous example:
+
+    fn add(a, b) { retun a + b; }
+
+Spell the keyword correctly:
+
+    fn add(a, b) { return a + b; }
+",
+            ErrorReportKind::TypeError => "\
+An operation was given a value of a type it doesn't accept. A condition must be
+a bool, and both sides of `+` must be numbers or both strings. The only implicit
+conversion is an int mixed with a float in arithmetic or a comparison, which is
+promoted to a float unless `--strict-numeric` is set.
+
+Erroneous example:
+
+    let count = 3;
+    if count { print(count); }
+
+Compare the value to get a bool:
+
+    let count = 3;
+    if count != 0 { print(count); }
+",
+            ErrorReportKind::NameError => "\
+A name was used that isn't declared in any enclosing scope. Variables must be
+declared with `let` before they are used.
+
+Erroneous example:
+
+    total = 10;
+
+Declare the variable first:
+
+    let total = 10;
+",
+            ErrorReportKind::RuntimeError => "\
+The program was well formed but failed while running, for instance by dividing
+by zero, indexing past the end of an array or overflowing an integer.
+
+Erroneous example:
+
+    let items = [1, 2, 3];
+    print(items[3]);
+
+Indexes start at zero, so the last element is at one less than the length:
+
+    let items = [1, 2, 3];
+    print(items[2]);
+",
+            ErrorReportKind::ImportError => "\
+An `import` could not be loaded: the file doesn't exist, can't be read, or
+imports the file that imported it. Paths are relative to the importing file.
+
+Erroneous example, in `main.nl`:
+
+    import \"lib/math.nl\";
+
+with `math.nl` next to `main.nl` rather than in `lib/`. Point at where the file is:
+
+    import \"math.nl\";
+",
+            ErrorReportKind::IndentationStyle => "\
+A line is indented in a way the lexer was asked to flag: with tabs when tabs are
+errors, or with tabs in a file whose first indented line used spaces, or the
+other way around. Indentation never changes what a program means, so these are
+only warnings and advice.
+
+Erroneous example, with tabs as errors:
+
+    fn main() {
+    \tprint(1);
+    }
+
+Indent with spaces instead:
+
+    fn main() {
+        print(1);
+    }
+",
+            ErrorReportKind::Custom => "Custom reports have no code, so there is nothing to explain.\n"
+        }
+    }
+}
+
 // This was the old code for doing errors before I decided an error struct was necessary.
 //
 // // These were intended to be macros, and I'm sure a nice macro to do this is possible,
@@ -117,4 +499,203 @@ pub enum ErrorReportKind {
 
 // pub fn note<'a>(span: Span, title: &str) -> ReportBuilder<'a> {
 //     report(ReportKind::Advice, span, title)
-// }
\ No newline at end of file
+// }
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn span(start: usize, end: usize) -> Span {
+        Span::new(start, end, Arc::from("test"))
+    }
+
+    /// Render a report against `source` without color, as it would be printed.
+    fn render(report: &ErrorReport, level: ErrorLevel, source: &str) -> String {
+        let mut sources = SourceCache::new();
+        sources.insert(Arc::from("test"), source);
+        let mut out = Vec::new();
+        // spans are byte offsets, as in main
+        let config = Config::default().with_color(false).with_index_type(ariadne::IndexType::Byte);
+        report.to_ariadne_report(level, config).write(&mut sources, &mut out).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn reports_are_errors_by_default() {
+        let report = ErrorReport::new(ErrorReportKind::SyntaxError, span(0, 0), "Oops".to_string());
+        assert_eq!(report.severity, Severity::Error);
+        assert!(report.is_error());
+    }
+
+    #[test]
+    fn warnings_and_advice_are_not_errors() {
+        let warning = ErrorReport::new(ErrorReportKind::NameError, span(0, 0), "Hmm".to_string()).with_severity(Severity::Warning);
+        let advice = ErrorReport::new(ErrorReportKind::NameError, span(0, 0), "Hmm".to_string()).with_severity(Severity::Advice);
+        assert!(!warning.is_error());
+        assert!(!advice.is_error());
+    }
+
+    #[test]
+    fn json_has_every_key() {
+        let report = ErrorReport::new(ErrorReportKind::SyntaxError, span(8, 12), "Invalid Integer Literal".to_string())
+            .with_label(Label::new(span(12, 12)).with_message("Invalid character"))
+            .with_note("Binary literals may only contain the digits 0 and 1.".to_string());
+        assert_eq!(
+            report.to_json(),
+            concat!(
+                r#"{"severity":"error","title":"SyntaxError[E0001]: Invalid Integer Literal","filename":"test","start":8,"end":13,"#,
+                r#""labels":[{"start":12,"end":13,"message":"Invalid character"}],"#,
+                r#""note":"Binary literals may only contain the digits 0 and 1.","help":null}"#
+            )
+        );
+    }
+
+    #[test]
+    fn json_ends_are_exclusive() {
+        let report = ErrorReport::new(ErrorReportKind::Custom, span(3, 3), "One character".to_string())
+            .with_label(Label::new(span(3, 3)));
+        let json = report.to_json();
+        assert!(json.contains(r#""start":3,"end":4,"labels":[{"start":3,"end":4,"message":null}]"#), "{}", json);
+    }
+
+    #[test]
+    fn json_strings_are_escaped() {
+        assert_eq!(json_string("a \"quote\"\n\t\\ \u{1}"), r#""a \"quote\"\n\t\\ \u0001""#);
+    }
+
+    #[test]
+    fn severity_picks_the_ariadne_report_kind() {
+        let report = |severity| ErrorReport::new(ErrorReportKind::NameError, span(4, 4), "Unused variable `x`".to_string()).with_severity(severity);
+        assert!(render(&report(Severity::Error), ErrorLevel::Normal, "let x = 1;").starts_with("Error:"));
+        assert!(render(&report(Severity::Warning), ErrorLevel::Normal, "let x = 1;").starts_with("Warning:"));
+        assert!(render(&report(Severity::Advice), ErrorLevel::Normal, "let x = 1;").starts_with("Advice:"));
+    }
+
+    #[test]
+    fn reports_sort_into_source_order() {
+        let mut reports = [
+            ErrorReport::new(ErrorReportKind::SyntaxError, span(9, 12), "Third".to_string()),
+            ErrorReport::new(ErrorReportKind::SyntaxError, span(2, 5), "Second".to_string()),
+            ErrorReport::new(ErrorReportKind::SyntaxError, span(2, 3), "First".to_string()),
+            ErrorReport::new(ErrorReportKind::SyntaxError, Span::new(0, 0, Arc::from("zzz")), "Last".to_string())
+        ];
+        reports.sort_by(|a, b| a.cmp_location(b));
+        let titles: Vec<&str> = reports.iter().map(ErrorReport::title).collect();
+        assert_eq!(titles, ["SyntaxError[E0001]: First", "SyntaxError[E0001]: Second", "SyntaxError[E0001]: Third", "SyntaxError[E0001]: Last"]);
+    }
+
+    #[test]
+    fn compact_reports_lead_with_the_span() {
+        let report = ErrorReport::new(ErrorReportKind::NameError, span(4, 4), "Unused variable `x`".to_string()).with_severity(Severity::Warning);
+        assert_eq!(render(&report, ErrorLevel::Compact, "let x = 1;"), "[test:4:4] Warning: NameError[E0006]: Unused variable `x`\n");
+    }
+
+    #[test]
+    fn no_color_means_no_escape_codes() {
+        let report = ErrorReport::new(ErrorReportKind::SyntaxError, span(4, 4), "Oops".to_string())
+            .with_label(Label::new(span(4, 4)).with_message("Here").with_color(Color::Red));
+        for level in [ErrorLevel::Compact, ErrorLevel::Normal, ErrorLevel::Debug] {
+            assert!(!render(&report, level, "let x = 1;").contains('\x1b'), "{:?}", level);
+        }
+    }
+
+    #[test]
+    fn tabs_render_at_the_configured_width() {
+        let report = ErrorReport::new(ErrorReportKind::SyntaxError, span(1, 1), "Oops".to_string());
+        let render_with_width = |width| {
+            let mut sources = SourceCache::new();
+            sources.insert(Arc::from("test"), "\tx");
+            let mut out = Vec::new();
+            let config = Config::default().with_color(false).with_tab_width(width);
+            report.to_ariadne_report(ErrorLevel::Normal, config).write(&mut sources, &mut out).unwrap();
+            String::from_utf8(out).unwrap()
+        };
+        assert!(render_with_width(2).contains("│   x"), "{}", render_with_width(2));
+        assert!(render_with_width(8).contains("│         x"), "{}", render_with_width(8));
+    }
+
+    #[test]
+    fn reports_without_labels_still_show_the_source() {
+        let report = ErrorReport::new(ErrorReportKind::Custom, span(7, 7), "Unexpected EOF".to_string());
+        assert!(render(&report, ErrorLevel::Normal, "let x =").contains("1 │ let x ="));
+    }
+
+    #[test]
+    fn source_cache_holds_every_file() {
+        let mut sources = SourceCache::new();
+        sources.insert(Arc::from("main.nl"), "import \"lib.nl\";");
+        sources.insert(Arc::from("lib.nl"), "let y = oops;");
+        let reports = [
+            ErrorReport::new(ErrorReportKind::SyntaxError, Span::new(0, 5, Arc::from("main.nl")), "First".to_string()),
+            ErrorReport::new(ErrorReportKind::NameError, Span::new(8, 11, Arc::from("lib.nl")), "Second".to_string())
+        ];
+        let rendered: Vec<String> = reports.iter().map(|report| {
+            let mut out = Vec::new();
+            report.to_ariadne_report(ErrorLevel::Normal, Config::default().with_color(false)).write(&mut sources, &mut out).unwrap();
+            String::from_utf8(out).unwrap()
+        }).collect();
+        assert!(rendered[0].contains("main.nl") && rendered[0].contains("import \"lib.nl\";"), "{}", rendered[0]);
+        assert!(rendered[1].contains("lib.nl") && rendered[1].contains("let y = oops;"), "{}", rendered[1]);
+    }
+
+    #[test]
+    fn note_and_help_are_both_rendered() {
+        let (_, reports) = crate::lexer::Lexer::lex_str(Arc::from("test"), "1.2.3");
+        assert_eq!(reports[0].note(), Some("A float literal has exactly one decimal point."));
+        assert_eq!(reports[0].help(), Some("Remove the second `.`."));
+        let rendered = render(&reports[0], ErrorLevel::Normal, "1.2.3");
+        assert!(rendered.contains("Note: A float literal has exactly one decimal point."), "{}", rendered);
+        assert!(rendered.contains("Help: Remove the second `.`."), "{}", rendered);
+    }
+
+    #[test]
+    fn secondary_labels_are_drawn_after_primary_ones() {
+        let report = ErrorReport::new(ErrorReportKind::SyntaxError, span(16, 16), "Duplicate field `a`".to_string())
+            .with_label(Label::new(span(16, 16)).with_message("Defined again here"))
+            .with_secondary_label(span(10, 10), "First defined here");
+        let rendered = render(&report, ErrorLevel::Normal, "let r = { a: 1, a: 2 };");
+        let again = rendered.find("Defined again here").unwrap();
+        let first = rendered.find("First defined here").unwrap();
+        assert!(again < first, "{}", rendered);
+    }
+
+    #[test]
+    fn debug_labels_only_show_at_debug_level() {
+        let report = ErrorReport::new(ErrorReportKind::SyntaxError, span(4, 4), "Oops".to_string())
+            .with_label(Label::new(span(4, 4)).with_message("Here"))
+            .with_debug_label(Label::new(span(0, 2)).with_message("Internal detail"));
+        assert!(!render(&report, ErrorLevel::Normal, "let x = 1;").contains("Internal detail"));
+        assert!(render(&report, ErrorLevel::Debug, "let x = 1;").contains("Internal detail"));
+    }
+
+    #[test]
+    fn multibyte_chars_do_not_shift_the_underline() {
+        // `é` is two bytes but one column
+        let source = "\"é\" + x";
+        let start = source.find('x').unwrap();
+        let report = ErrorReport::new(ErrorReportKind::NameError, span(start, start), "Undefined".to_string())
+            .with_label(Label::new(span(start, start)).with_message("here"));
+        let out = render(&report, ErrorLevel::Normal, source);
+        assert!(out.contains("[test:1:7]"), "{}", out);
+        let lines: Vec<&str> = out.lines().collect();
+        let code = lines.iter().find(|line| line.ends_with("\"é\" + x")).unwrap();
+        let caret = lines.iter().find(|line| line.contains('┬')).unwrap();
+        assert_eq!(code.chars().position(|c| c == 'x'), caret.chars().position(|c| c == '┬'));
+    }
+
+    #[test]
+    fn every_code_has_its_own_kind_and_explanation() {
+        for kind in ErrorReportKind::CODED {
+            let code = kind.code().unwrap();
+            assert_eq!(ErrorReportKind::from_code(&code.to_lowercase()).as_ref(), Some(&kind));
+            assert!(kind.explanation().contains("Erroneous example"), "{}", code);
+        }
+        assert_eq!(ErrorReportKind::Custom.code(), None);
+        assert_eq!(ErrorReportKind::from_code("E9999"), None);
+    }
+
+    #[test]
+    fn coded_titles_name_the_kind_and_code() {
+        assert_eq!(ErrorReport::new(ErrorReportKind::TypeError, span(0, 0), "Bad".to_string()).title(), "TypeError[E0005]: Bad");
+        assert_eq!(ErrorReport::new(ErrorReportKind::Custom, span(0, 0), "Bad".to_string()).title(), "Bad");
+    }
+}
@@ -0,0 +1,175 @@
+use std::rc::Rc;
+use crate::ast::{AST, ASTKind, BinaryOp, StringPart, UnaryOp};
+
+/// Evaluate arithmetic on literal operands ahead of time, so `1 + 2 * 3` becomes a single `7`
+/// spanning the whole expression. Anything that could fail or depends on runtime state, like
+/// division by zero, overflow, identifiers or calls, is left for the interpreter to report. So is
+/// mixing ints with floats, which is an error in strict numeric mode.
+pub fn fold_constants(ast: Rc<AST>) -> Rc<AST> {
+    let fold_all = |nodes: &[Rc<AST>]| nodes.iter().cloned().map(fold_constants).collect();
+    let kind = match &ast.kind {
+        ASTKind::Unary { op, operand } => {
+            let operand = fold_constants(operand.clone());
+            let folded = match (op, &operand.kind) {
+                (UnaryOp::Neg, ASTKind::IntegerLiteral(val)) => val.checked_neg().map(ASTKind::IntegerLiteral),
+                (UnaryOp::Neg, ASTKind::FloatLiteral(val)) => Some(ASTKind::FloatLiteral(-val)),
+                _ => None
+            };
+            if let Some(kind) = folded {
+                return Rc::new(AST::new(ast.span.clone(), kind));
+            }
+            ASTKind::Unary { op: *op, operand }
+        }
+        ASTKind::Binary { op, op_span, lhs, rhs } => {
+            let lhs = fold_constants(lhs.clone());
+            let rhs = fold_constants(rhs.clone());
+            if let Some(kind) = fold_binary(*op, &lhs.kind, &rhs.kind) {
+                return Rc::new(AST::new(ast.span.clone(), kind));
+            }
+            ASTKind::Binary { op: *op, op_span: op_span.clone(), lhs, rhs }
+        }
+        // Parentheses only matter to the parser, dropping them lets `(1 + 2) * 3` fold too
+        ASTKind::Grouping(inner) => return fold_constants(inner.clone()),
+        ASTKind::InterpolatedString(parts) => ASTKind::InterpolatedString(parts.iter().map(|part| match part {
+            StringPart::Literal(text) => StringPart::Literal(text.clone()),
+            StringPart::Expr(expr) => StringPart::Expr(fold_constants(expr.clone()))
+        }).collect()),
+        ASTKind::Block(statements) => ASTKind::Block(fold_all(statements)),
+        ASTKind::If { cond, then_branch, else_branch } => ASTKind::If {
+            cond: fold_constants(cond.clone()),
+            then_branch: fold_constants(then_branch.clone()),
+            else_branch: else_branch.clone().map(fold_constants)
+        },
+        ASTKind::While { cond, body } => ASTKind::While {
+            cond: fold_constants(cond.clone()),
+            body: fold_constants(body.clone())
+        },
+        ASTKind::Let { name, value } => ASTKind::Let { name: name.clone(), value: fold_constants(value.clone()) },
+        ASTKind::Assign { name, value } => ASTKind::Assign { name: name.clone(), value: fold_constants(value.clone()) },
+        ASTKind::Function { name, params, body } => ASTKind::Function {
+            name: name.clone(),
+            params: params.clone(),
+            body: fold_constants(body.clone())
+        },
+        ASTKind::Call { callee, args } => ASTKind::Call { callee: fold_constants(callee.clone()), args: fold_all(args) },
+        ASTKind::ArrayLiteral(elements) => ASTKind::ArrayLiteral(fold_all(elements)),
+        ASTKind::RecordLiteral(fields) => ASTKind::RecordLiteral(
+            fields.iter().map(|(name, value)| (name.clone(), fold_constants(value.clone()))).collect()
+        ),
+        ASTKind::Index { object, index } => ASTKind::Index {
+            object: fold_constants(object.clone()),
+            index: fold_constants(index.clone())
+        },
+        ASTKind::Member { object, name } => ASTKind::Member { object: fold_constants(object.clone()), name: name.clone() },
+        ASTKind::Range { start, end } => ASTKind::Range {
+            start: start.clone().map(fold_constants),
+            end: end.clone().map(fold_constants)
+        },
+        ASTKind::Cast { expr, target } => ASTKind::Cast { expr: fold_constants(expr.clone()), target: *target },
+        ASTKind::Match { scrutinee, arms } => ASTKind::Match {
+            scrutinee: fold_constants(scrutinee.clone()),
+            arms: arms.iter().map(|(pattern, body)| (pattern.clone(), fold_constants(body.clone()))).collect()
+        },
+        ASTKind::Return(Some(value)) => ASTKind::Return(Some(fold_constants(value.clone()))),
+        ASTKind::StringLiteral(_)
+        | ASTKind::IntegerLiteral(_)
+        | ASTKind::FloatLiteral(_)
+        | ASTKind::BoolLiteral(_)
+        | ASTKind::UnitLiteral
+        | ASTKind::Identifier(_)
+        | ASTKind::Wildcard
+        | ASTKind::Return(None)
+        | ASTKind::Import(_)
+        | ASTKind::Break
+        | ASTKind::Continue => return ast
+    };
+    Rc::new(AST::new(ast.span.clone(), kind))
+}
+
+fn fold_binary(op: BinaryOp, lhs: &ASTKind, rhs: &ASTKind) -> Option<ASTKind> {
+    if !op.is_arithmetic() {
+        return None;
+    }
+    match (lhs, rhs) {
+        (ASTKind::IntegerLiteral(lhs), ASTKind::IntegerLiteral(rhs)) => {
+            let result = match op {
+                BinaryOp::Add => lhs.checked_add(*rhs),
+                BinaryOp::Sub => lhs.checked_sub(*rhs),
+                BinaryOp::Mul => lhs.checked_mul(*rhs),
+                BinaryOp::Div => lhs.checked_div(*rhs),
+                _ => unreachable!("Only arithmetic is folded.")
+            };
+            result.map(ASTKind::IntegerLiteral)
+        }
+        // Dividing by zero is left to the interpreter, floats included, rather than folding to inf or NaN
+        (ASTKind::FloatLiteral(_), ASTKind::FloatLiteral(rhs)) if op == BinaryOp::Div && *rhs == 0.0 => None,
+        (ASTKind::FloatLiteral(lhs), ASTKind::FloatLiteral(rhs)) => Some(ASTKind::FloatLiteral(float_op(op, *lhs, *rhs))),
+        _ => None
+    }
+}
+
+fn float_op(op: BinaryOp, lhs: f64, rhs: f64) -> f64 {
+    match op {
+        BinaryOp::Add => lhs + rhs,
+        BinaryOp::Sub => lhs - rhs,
+        BinaryOp::Mul => lhs * rhs,
+        BinaryOp::Div => lhs / rhs,
+        _ => unreachable!("Only arithmetic is folded.")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use super::*;
+
+    /// Parse and fold `source`, returning the folded tree as it displays.
+    fn fold(source: &str) -> String {
+        fold_first(source).to_string()
+    }
+
+    /// Parse and fold `source`, returning its first statement.
+    fn fold_first(source: &str) -> Rc<AST> {
+        let (ast, reports) = crate::parse(Arc::from("test"), source);
+        assert!(reports.is_empty());
+        let folded = fold_constants(ast.unwrap());
+        let ASTKind::Block(statements) = folded.kind() else { unreachable!("A program is always a block") };
+        statements[0].clone()
+    }
+
+    #[test]
+    fn folds_arithmetic_on_literals() {
+        assert_eq!(fold("1 + 2 * 3"), "7");
+        assert_eq!(fold("(1 + 2) * 3"), "9");
+        assert!(matches!(fold_first("1.5 * 2.0").kind(), ASTKind::FloatLiteral(val) if *val == 3.0));
+    }
+
+    #[test]
+    fn folded_literal_spans_the_whole_expression() {
+        let folded = fold_first("1 + 2 * 3");
+        assert_eq!((folded.span().start, folded.span().end), (0, 8));
+    }
+
+    #[test]
+    fn leaves_identifiers_and_calls_alone() {
+        assert_eq!(fold("x + 2"), "x + 2");
+        assert_eq!(fold("len(\"a\") * 2"), "len(\"a\") * 2");
+    }
+
+    #[test]
+    fn leaves_division_by_zero_alone() {
+        assert!(matches!(fold_first("1 / 0").kind(), ASTKind::Binary { .. }));
+        assert!(matches!(fold_first("1.0 / 0.0").kind(), ASTKind::Binary { .. }));
+    }
+
+    #[test]
+    fn leaves_overflow_alone() {
+        assert!(matches!(fold_first(&format!("{} + 1", isize::MAX)).kind(), ASTKind::Binary { .. }));
+    }
+
+    #[test]
+    fn folds_negation_of_literals() {
+        assert_eq!(fold("-(1 + 2)"), "-3");
+        assert_eq!(fold("-x"), "-x");
+    }
+}
@@ -0,0 +1,471 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+use std::sync::Arc;
+use crate::ast::{AST, ASTKind, BinaryOp, Pattern, StringPart};
+use crate::error::ErrorReport;
+use crate::lexer::{escape_into, quote, Lexer};
+use crate::token::{Token, TokenKind};
+
+const DEFAULT_INDENT: usize = 2;
+
+/// Lay out a parsed program as canonical source, with one statement per line and blocks indented
+/// by two spaces. Formatting the output again gives the same text. A tree doesn't hold comments or
+/// how its literals were written, so use `format_source` to keep those.
+pub fn format(ast: &AST) -> String {
+    format_with_indent(ast, DEFAULT_INDENT)
+}
+
+/// Like `format`, indenting each block by `indent` spaces.
+pub fn format_with_indent(ast: &AST, indent: usize) -> String {
+    Writer::new(indent, None, &[]).program(ast)
+}
+
+/// Parse and format `source`, indenting each block by `indent` spaces. Unlike `format`, comments
+/// are kept and literals are written as they were, so `0xFF` stays `0xFF` and the expressions in a
+/// string's `${}` are left alone. A comment stays on the line of the statement or closing brace it
+/// follows, or goes on its own line before the next statement, as does a comment from inside an
+/// expression. Returns no text if the source has errors.
+pub fn format_source(filename: Arc<str>, source: &str, indent: usize) -> (Option<String>, Vec<ErrorReport>) {
+    let reports = Rc::new(RefCell::new(Vec::new()));
+    let mut lexer = Lexer::new(filename, source, reports.clone()).with_keep_comments(true);
+    lexer.lex_tokens();
+    let reports = reports.take();
+    if reports.iter().any(ErrorReport::is_error) {
+        return (None, reports);
+    }
+    let (ast, reports) = crate::parse_tokens(&lexer.tokens, reports);
+    let Some(ast) = ast else {
+        return (None, reports);
+    };
+    let mut formatted = String::new();
+    // The lexer skips a shebang, but it still has to be the first line
+    if let Some(shebang) = source.trim_start_matches('\u{FEFF}').lines().next().filter(|line| line.starts_with("#!")) {
+        formatted.push_str(shebang);
+        formatted.push('\n');
+    }
+    formatted.push_str(&Writer::new(indent, Some(source), &lexer.tokens).program(&ast));
+    (Some(formatted), reports)
+}
+
+struct Writer<'a> {
+    out: String,
+    indent: usize,
+    depth: usize,
+    /// The source being formatted, if there is one, to take literals from.
+    source: Option<&'a str>,
+    /// Every token of the source, comments included.
+    tokens: &'a [Token<'a>],
+    comments: Vec<&'a Token<'a>>,
+    /// The first comment not yet written.
+    next_comment: usize
+}
+
+impl<'a> Writer<'a> {
+    fn new(indent: usize, source: Option<&'a str>, tokens: &'a [Token<'a>]) -> Self {
+        let comments = tokens.iter().filter(|token| token.kind.is_comment()).collect();
+        Writer { out: String::new(), indent, depth: 0, source, tokens, comments, next_comment: 0 }
+    }
+
+    fn program(mut self, ast: &AST) -> String {
+        match &ast.kind {
+            // The program itself is a block, but without braces
+            ASTKind::Block(statements) => {
+                for statement in statements {
+                    self.comments_before(statement.span.start);
+                    self.statement(statement);
+                    self.trailing_comments(statement.span.end, usize::MAX);
+                    self.out.push('\n');
+                }
+                self.comments_before(usize::MAX);
+            }
+            _ => self.expression(ast)
+        }
+        self.out
+    }
+
+    /// Write each comment starting before `offset` on a line of its own.
+    fn comments_before(&mut self, offset: usize) {
+        while let Some(comment) = self.comments.get(self.next_comment).copied().filter(|comment| comment.span.start < offset) {
+            self.line_start();
+            self.out.push_str(comment.text);
+            self.out.push('\n');
+            self.next_comment += 1;
+        }
+    }
+
+    /// Write the comments after `offset` and before `limit` that are on the same line as it, after
+    /// what was just written. Returns whether there were any.
+    fn trailing_comments(&mut self, mut offset: usize, limit: usize) -> bool {
+        let Some(source) = self.source else { return false };
+        let first = self.next_comment;
+        while let Some(comment) = self.comments.get(self.next_comment).copied().filter(|comment| {
+            comment.span.start > offset && comment.span.start < limit && !source[offset..comment.span.start].contains('\n')
+        }) {
+            self.out.push(' ');
+            self.out.push_str(comment.text);
+            offset = comment.span.end;
+            self.next_comment += 1;
+        }
+        self.next_comment > first
+    }
+
+    /// A literal as it was written in the source, so `0xFF` isn't rewritten as `255`.
+    fn written(&self, node: &AST) -> Option<&'a str> {
+        self.source?.get(node.span.start..=node.span.end)
+    }
+
+    /// The pattern of the arm whose body is `body`, as it was written. Patterns are a single
+    /// literal, perhaps with a `-` before it, just before the arm's `=>`.
+    fn written_pattern(&self, body: &AST) -> Option<&'a str> {
+        let end = self.tokens.partition_point(|token| token.span.start < body.span.start);
+        let mut before = self.tokens[..end].iter().rev().filter(|token| !token.kind.is_comment());
+        before.next().filter(|token| token.kind == TokenKind::FatArrow)?;
+        let literal = before.next()?;
+        let start = match before.next() {
+            Some(token) if token.kind == TokenKind::Minus => token.span.start,
+            _ => literal.span.start
+        };
+        self.source?.get(start..=literal.span.end)
+    }
+
+    fn line_start(&mut self) {
+        self.out.push_str(&" ".repeat(self.indent * self.depth));
+    }
+
+    fn statement(&mut self, node: &AST) {
+        self.line_start();
+        self.expression(node);
+        // The parser doesn't need a `;` after a statement ending in a block
+        if !matches!(node.kind, ASTKind::If { .. } | ASTKind::While { .. } | ASTKind::Function { .. } | ASTKind::Block(_) | ASTKind::Match { .. }) {
+            self.out.push(';');
+        }
+    }
+
+    /// Write a block whose closing brace is at `end`.
+    fn block(&mut self, statements: &[Rc<AST>], end: usize) {
+        let has_comments = self.comments.get(self.next_comment).is_some_and(|comment| comment.span.start < end);
+        if statements.is_empty() && !has_comments {
+            self.out.push_str("{}");
+            return;
+        }
+        self.out.push_str("{\n");
+        self.depth += 1;
+        for statement in statements {
+            self.comments_before(statement.span.start);
+            self.statement(statement);
+            // A comment after the closing brace belongs to whatever the block is part of
+            self.trailing_comments(statement.span.end, end);
+            self.out.push('\n');
+        }
+        self.comments_before(end);
+        self.depth -= 1;
+        self.line_start();
+        self.out.push('}');
+    }
+
+    fn list(&mut self, nodes: &[Rc<AST>]) {
+        for (i, node) in nodes.iter().enumerate() {
+            if i > 0 {
+                self.out.push_str(", ");
+            }
+            self.expression(node);
+        }
+    }
+
+    /// Write a binary operand, adding parentheses where the tree's shape wouldn't survive without them.
+    /// Only desugared compound assignments produce such trees, the parser keeps written parentheses.
+    fn operand(&mut self, node: &AST, parent: BinaryOp, is_rhs: bool) {
+        let needs_parens = match &node.kind {
+            ASTKind::Binary { op, .. } => precedence(*op) < precedence(parent) || is_rhs && precedence(*op) == precedence(parent),
+            ASTKind::Assign { .. } | ASTKind::Range { .. } => true,
+            _ => false
+        };
+        if needs_parens {
+            self.out.push('(');
+            self.expression(node);
+            self.out.push(')');
+        } else {
+            self.expression(node);
+        }
+    }
+
+    fn expression(&mut self, node: &AST) {
+        match &node.kind {
+            ASTKind::StringLiteral(val) => match self.written(node) {
+                Some(text) => self.out.push_str(text),
+                None => self.out.push_str(&quote(val))
+            },
+            ASTKind::InterpolatedString(_) if self.source.is_some() => {
+                let text = self.written(node).expect("Literal span is outside the source.");
+                self.out.push_str(text);
+            }
+            ASTKind::InterpolatedString(parts) => {
+                self.out.push('"');
+                for part in parts {
+                    match part {
+                        StringPart::Literal(text) => escape_into(&mut self.out, text),
+                        StringPart::Expr(expr) => {
+                            self.out.push_str("${");
+                            self.expression(expr);
+                            self.out.push('}');
+                        }
+                    }
+                }
+                self.out.push('"');
+            }
+            ASTKind::IntegerLiteral(_) | ASTKind::FloatLiteral(_) if self.source.is_some() => {
+                let text = self.written(node).expect("Literal span is outside the source.");
+                self.out.push_str(text);
+            }
+            ASTKind::IntegerLiteral(val) => self.out.push_str(&val.to_string()),
+            // debug formatting always keeps a decimal point or exponent, so the literal stays a float
+            ASTKind::FloatLiteral(val) => self.out.push_str(&format!("{:?}", val)),
+            ASTKind::BoolLiteral(val) => self.out.push_str(&val.to_string()),
+            ASTKind::UnitLiteral => self.out.push_str("()"),
+            ASTKind::Identifier(name) => self.out.push_str(name),
+            ASTKind::Wildcard => self.out.push('_'),
+            ASTKind::Grouping(inner) => {
+                self.out.push('(');
+                self.expression(inner);
+                self.out.push(')');
+            }
+            ASTKind::Unary { op, operand } => {
+                self.out.push_str(&op.to_string());
+                // Unary operators bind tighter than any binary one
+                if matches!(operand.kind, ASTKind::Binary { .. } | ASTKind::Assign { .. } | ASTKind::Range { .. } | ASTKind::Cast { .. }) {
+                    self.out.push('(');
+                    self.expression(operand);
+                    self.out.push(')');
+                } else {
+                    self.expression(operand);
+                }
+            }
+            ASTKind::Binary { op, lhs, rhs, .. } => {
+                self.operand(lhs, *op, false);
+                self.out.push_str(&format!(" {} ", op));
+                self.operand(rhs, *op, true);
+            }
+            ASTKind::Block(statements) => self.block(statements, node.span.end),
+            ASTKind::If { cond, then_branch, else_branch } => {
+                self.out.push_str("if ");
+                self.expression(cond);
+                self.out.push(' ');
+                self.expression(then_branch);
+                if let Some(else_branch) = else_branch {
+                    // A comment after the `}` runs to the end of the line, so `else` has to start a new one
+                    if self.trailing_comments(then_branch.span.end, else_branch.span.start) {
+                        self.out.push('\n');
+                        self.line_start();
+                        self.out.push_str("else ");
+                    } else {
+                        self.out.push_str(" else ");
+                    }
+                    self.expression(else_branch);
+                }
+            }
+            ASTKind::While { cond, body } => {
+                self.out.push_str("while ");
+                self.expression(cond);
+                self.out.push(' ');
+                self.expression(body);
+            }
+            ASTKind::Let { name, value } => {
+                self.out.push_str(&format!("let {} = ", name));
+                self.expression(value);
+            }
+            ASTKind::Assign { name, value } => {
+                self.out.push_str(&format!("{} = ", name));
+                self.expression(value);
+            }
+            ASTKind::Function { name, params, body } => {
+                self.out.push_str(&format!("fn {}({}) ", name, params.join(", ")));
+                self.expression(body);
+            }
+            ASTKind::Call { callee, args } => {
+                self.expression(callee);
+                self.out.push('(');
+                self.list(args);
+                self.out.push(')');
+            }
+            ASTKind::ArrayLiteral(elements) => {
+                self.out.push('[');
+                self.list(elements);
+                self.out.push(']');
+            }
+            ASTKind::RecordLiteral(fields) => {
+                self.out.push_str("{ ");
+                for (i, (name, value)) in fields.iter().enumerate() {
+                    if i > 0 {
+                        self.out.push_str(", ");
+                    }
+                    self.out.push_str(&format!("{}: ", name));
+                    self.expression(value);
+                }
+                self.out.push_str(" }");
+            }
+            ASTKind::Index { object, index } => {
+                self.expression(object);
+                self.out.push('[');
+                self.expression(index);
+                self.out.push(']');
+            }
+            ASTKind::Member { object, name } => {
+                self.expression(object);
+                self.out.push_str(&format!(".{}", name));
+            }
+            ASTKind::Range { start, end } => {
+                if let Some(start) = start {
+                    self.expression(start);
+                }
+                self.out.push_str("..");
+                if let Some(end) = end {
+                    self.expression(end);
+                }
+            }
+            ASTKind::Cast { expr, target } => {
+                self.expression(expr);
+                self.out.push_str(&format!(" as {}", target));
+            }
+            ASTKind::Match { scrutinee, arms } => {
+                self.out.push_str("match ");
+                self.expression(scrutinee);
+                self.out.push_str(" {\n");
+                self.depth += 1;
+                for (pattern, body) in arms {
+                    self.comments_before(body.span.start);
+                    self.line_start();
+                    let pattern = match (self.written_pattern(body), pattern) {
+                        (Some(text), _) => text.to_string(),
+                        (None, Pattern::Str(val)) => quote(val),
+                        (None, pattern) => pattern.to_string()
+                    };
+                    self.out.push_str(&format!("{} => ", pattern));
+                    self.expression(body);
+                    self.out.push(',');
+                    self.trailing_comments(body.span.end, node.span.end);
+                    self.out.push('\n');
+                }
+                self.comments_before(node.span.end);
+                self.depth -= 1;
+                self.line_start();
+                self.out.push('}');
+            }
+            ASTKind::Return(Some(value)) => {
+                self.out.push_str("return ");
+                self.expression(value);
+            }
+            ASTKind::Return(None) => self.out.push_str("return"),
+            ASTKind::Import(path) => self.out.push_str(&format!("import {}", quote(path))),
+            ASTKind::Break => self.out.push_str("break"),
+            ASTKind::Continue => self.out.push_str("continue")
+        }
+    }
+}
+
+/// How tightly each operator binds, in the same order as `TokenKind::binary_binding_power`.
+fn precedence(op: BinaryOp) -> u8 {
+    match op {
+        BinaryOp::Or => 1,
+        BinaryOp::And => 2,
+        BinaryOp::Eq | BinaryOp::NotEq => 3,
+        BinaryOp::Less | BinaryOp::LessEq | BinaryOp::Greater | BinaryOp::GreaterEq => 4,
+        BinaryOp::Add | BinaryOp::Sub => 5,
+        BinaryOp::Mul | BinaryOp::Div => 6
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn format_ok(source: &str) -> String {
+        let (formatted, reports) = format_source(Arc::from("test"), source, DEFAULT_INDENT);
+        assert!(reports.is_empty(), "{:?}", reports.iter().map(ErrorReport::title).collect::<Vec<_>>());
+        formatted.unwrap()
+    }
+
+    #[test]
+    fn formatting_is_idempotent() {
+        let sources = [
+            "let x=1+2*3;fn f(a,b){if a<b{return a;}else if a>b{b}else{0}}",
+            "let r = { x: [1, 2][0], y: -(3 - 1) }; while r.x < 3 { r = { x: r.x + 1, y: r.y }; }",
+            "let m = match 2 { 1 => \"one\", -2 => \"two\", _ => \"${1 + 1}\" };",
+            "// top\nlet x = 0x1F; // hex\n{\n  /* inner */\n}\nfn f() {\n// body\n1\n}",
+        ];
+        for source in sources {
+            let once = format_ok(source);
+            assert_eq!(format_ok(&once), once, "{}", source);
+        }
+    }
+
+    #[test]
+    fn lays_out_one_statement_per_line() {
+        assert_eq!(format_ok("let x=1;fn f(a){a*2}"), "let x = 1;\nfn f(a) {\n  a * 2;\n}\n");
+    }
+
+    #[test]
+    fn keeps_literals_as_written() {
+        assert_eq!(format_ok("let x = 0xFF + 1_000u16 + 0b1010;"), "let x = 0xFF + 1_000u16 + 0b1010;\n");
+        assert_eq!(format_ok("let y = 2.50e1f64; let s = r\"a\\n\";"), "let y = 2.50e1f64;\nlet s = r\"a\\n\";\n");
+        assert_eq!(format_ok("match 1 { 0x10 => 1, -0b1 => 2, _ => 3 }"), "match 1 {\n  0x10 => 1,\n  -0b1 => 2,\n  _ => 3,\n}\n");
+    }
+
+    #[test]
+    fn keeps_comments_in_place() {
+        let source = "// leading\nlet x = 1; // trailing\n/* own line */\nfn f() {\n    // inside\n    x\n    // before the brace\n}\n{\n    // alone\n}\n// the end\n";
+        assert_eq!(format_ok(source), "\
+// leading
+let x = 1; // trailing
+/* own line */
+fn f() {
+  // inside
+  x;
+  // before the brace
+}
+{
+  // alone
+}
+// the end
+");
+    }
+
+    #[test]
+    fn comments_inside_an_expression_are_not_lost() {
+        assert_eq!(format_ok("let x = 1 + // one\n  2;\nx;"), "let x = 1 + 2;\n// one\nx;\n");
+    }
+
+    #[test]
+    fn comment_after_a_closing_brace_stays_outside_the_block() {
+        assert_eq!(format_ok("if a { 1 } // after the if\nb;"), "if a {\n  1;\n} // after the if\nb;\n");
+        assert_eq!(
+            format_ok("if a { 1 } // then done\nelse { 2 }"),
+            "if a {\n  1;\n} // then done\nelse {\n  2;\n}\n"
+        );
+        let once = format_ok("fn f() { if a { 1 } // then done\nelse { 2 } }");
+        assert_eq!(once, "fn f() {\n  if a {\n    1;\n  } // then done\n  else {\n    2;\n  }\n}\n");
+        assert_eq!(format_ok(&once), once);
+    }
+
+    #[test]
+    fn comments_inside_an_interpolation_are_kept() {
+        assert_eq!(format_ok("let s = \"${1 /* c */}\";"), "let s = \"${1 /* c */}\";\n");
+    }
+
+    #[test]
+    fn keeps_the_shebang_first() {
+        assert_eq!(format_ok("#!/usr/bin/env lox\nlet x=1;"), "#!/usr/bin/env lox\nlet x = 1;\n");
+    }
+
+    #[test]
+    fn a_tree_alone_is_formatted_from_its_values() {
+        let (ast, _) = crate::parse(Arc::from("test"), "let x = 0xFF; // gone");
+        assert_eq!(format(&ast.unwrap()), "let x = 255;\n");
+    }
+
+    #[test]
+    fn source_with_errors_is_not_formatted() {
+        let (formatted, reports) = format_source(Arc::from("test"), "let x = ;", DEFAULT_INDENT);
+        assert!(formatted.is_none());
+        assert!(!reports.is_empty());
+    }
+}
@@ -0,0 +1,960 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::io::Write;
+use std::rc::Rc;
+use ariadne::Color;
+use crate::builtins;
+use crate::ast::{AST, ASTKind, BinaryOp, Pattern, StringPart, TypeName, UnaryOp};
+use crate::environment::Environment;
+use crate::error::{ErrorReport, ErrorReportKind, Label, Result};
+use crate::span::Span;
+use crate::value::{Function, Value};
+
+/// Anything that interrupts normal evaluation and unwinds up the tree until something handles it.
+pub enum Signal {
+    Error(ErrorReport),
+    Return(Value),
+    Break,
+    Continue
+}
+
+impl From<ErrorReport> for Signal {
+    fn from(value: ErrorReport) -> Self {
+        Signal::Error(value)
+    }
+}
+
+type EvalResult<T> = std::result::Result<T, Signal>;
+
+const DEFAULT_MAX_CALL_DEPTH: usize = 256;
+
+/// Runs a tree, writing whatever the program prints to an output that lives for `'o`.
+pub struct Interpreter<'o> {
+    environment: Rc<RefCell<Environment>>,
+    output: Box<dyn Write + 'o>,
+    steps: usize,
+    max_steps: usize,
+    call_depth: usize,
+    max_call_depth: usize,
+    strict_numeric: bool
+}
+
+impl<'o> Interpreter<'o> {
+    pub fn new() -> Self {
+        let mut globals = Environment::new();
+        builtins::register(&mut globals);
+        Interpreter {
+            environment: Rc::new(RefCell::new(globals)),
+            output: Box::new(std::io::stdout()),
+            steps: 0,
+            max_steps: 0,
+            call_depth: 0,
+            max_call_depth: DEFAULT_MAX_CALL_DEPTH,
+            strict_numeric: false
+        }
+    }
+
+    /// Send everything `print` and `println` write to `output` instead of stdout. Pass a
+    /// `&mut Vec<u8>` to capture it, the buffer can be read again once the interpreter is dropped.
+    pub fn with_output(mut self, output: impl Write + 'o) -> Self {
+        self.output = Box::new(output);
+        self
+    }
+
+    /// Give up with an error after evaluating this many nodes, so a runaway loop can't hang the
+    /// process. Zero, the default, means no limit.
+    pub fn with_max_steps(mut self, max_steps: usize) -> Self {
+        self.max_steps = max_steps;
+        self
+    }
+
+    /// Give up with an error when calls nest deeper than this, instead of overflowing the stack.
+    /// Every call takes several frames of the native stack, so the thread running the interpreter
+    /// needs a stack large enough for this many.
+    pub fn with_max_call_depth(mut self, max_call_depth: usize) -> Self {
+        self.max_call_depth = max_call_depth;
+        self
+    }
+
+    /// Refuse to mix ints and floats in arithmetic and comparisons, instead of promoting the int.
+    pub fn with_strict_numeric(mut self, strict_numeric: bool) -> Self {
+        self.strict_numeric = strict_numeric;
+        self
+    }
+
+    pub fn run(&mut self, node: &Rc<AST>) -> Result<Value> {
+        match self.eval(node) {
+            Ok(value) => Ok(value),
+            Err(Signal::Error(error)) => Err(error),
+            Err(Signal::Return(_)) => unreachable!("Parser rejects return outside of a function."),
+            Err(Signal::Break | Signal::Continue) => unreachable!("Parser rejects loop control outside of a loop.")
+        }
+    }
+
+    fn eval(&mut self, node: &Rc<AST>) -> EvalResult<Value> {
+        if self.max_steps != 0 {
+            self.steps += 1;
+            if self.steps > self.max_steps {
+                let e = ErrorReport::new(ErrorReportKind::RuntimeError, node.span.clone(), "Execution Limit Exceeded".to_string())
+                    .with_label(Label::new(node.span.clone()).with_message("Stopped while evaluating this").with_color(Color::Red))
+                    .with_note(format!("Evaluation stopped after {} steps.", self.max_steps));
+                return Err(e.into());
+            }
+        }
+        match &node.kind {
+            ASTKind::StringLiteral(val) => Ok(Value::Str(val.clone())),
+            ASTKind::InterpolatedString(parts) => {
+                let mut out = String::new();
+                for part in parts {
+                    match part {
+                        StringPart::Literal(text) => out.push_str(text),
+                        StringPart::Expr(expr) => out.push_str(&self.eval(expr)?.to_string())
+                    }
+                }
+                Ok(Value::Str(out))
+            }
+            ASTKind::IntegerLiteral(val) => Ok(Value::Int(*val)),
+            ASTKind::FloatLiteral(val) => Ok(Value::Float(*val)),
+            ASTKind::BoolLiteral(val) => Ok(Value::Bool(*val)),
+            ASTKind::UnitLiteral => Ok(Value::Unit),
+            ASTKind::Identifier(name) => {
+                self.environment.borrow().get(name).ok_or_else(|| {
+                    ErrorReport::new(ErrorReportKind::NameError, node.span.clone(), format!("Undefined variable `{}`", name))
+                        .with_label(Label::new(node.span.clone()).with_message("Not defined in this scope").with_color(Color::Red))
+                        .into()
+                })
+            }
+            ASTKind::Grouping(inner) => self.eval(inner),
+            ASTKind::Wildcard => {
+                let e = ErrorReport::new(ErrorReportKind::NameError, node.span.clone(), "`_` is not a value".to_string())
+                    .with_label(Label::new(node.span.clone()).with_message("Cannot be read").with_color(Color::Red))
+                    .with_note("`_` can only be used to discard a value, as in `let _ = ...`.".to_string());
+                Err(e.into())
+            }
+            ASTKind::Unary { op, operand } => {
+                let value = self.eval(operand)?;
+                Ok(Self::eval_unary(*op, node, value)?)
+            }
+            ASTKind::Binary { op, lhs, rhs, .. } if op.is_logical() => {
+                // `&&` stops at the first false, `||` at the first true
+                let short_circuit = *op == BinaryOp::Or;
+                if self.eval(lhs)?.as_bool(&lhs.span)? == short_circuit {
+                    return Ok(Value::Bool(short_circuit));
+                }
+                Ok(Value::Bool(self.eval(rhs)?.as_bool(&rhs.span)?))
+            }
+            ASTKind::Binary { op, op_span, lhs, rhs } => {
+                let lhs = self.eval(lhs)?;
+                let rhs = self.eval(rhs)?;
+                Ok(self.eval_binary(*op, op_span, lhs, rhs)?)
+            }
+            ASTKind::Block(statements) => {
+                let previous = self.environment.clone();
+                self.environment = Rc::new(RefCell::new(Environment::with_parent(previous.clone())));
+                let result = self.eval_statements(statements);
+                self.environment = previous;
+                result
+            }
+            ASTKind::If { cond, then_branch, else_branch } => {
+                if self.eval(cond)?.as_bool(&cond.span)? {
+                    self.eval(then_branch)
+                } else if let Some(else_branch) = else_branch {
+                    self.eval(else_branch)
+                } else {
+                    Ok(Value::Unit)
+                }
+            }
+            ASTKind::While { cond, body } => {
+                while self.eval(cond)?.as_bool(&cond.span)? {
+                    match self.eval(body) {
+                        Err(Signal::Break) => break,
+                        Ok(_) | Err(Signal::Continue) => {}
+                        Err(signal) => return Err(signal)
+                    }
+                }
+                Ok(Value::Unit)
+            }
+            ASTKind::Let { name, value } => {
+                let value = self.eval(value)?;
+                if &**name != "_" {
+                    self.environment.borrow_mut().define(name.clone(), value);
+                }
+                Ok(Value::Unit)
+            }
+            ASTKind::Assign { name, value } => {
+                let value = self.eval(value)?;
+                if !self.environment.borrow_mut().assign(name, value) {
+                    let e = ErrorReport::new(ErrorReportKind::NameError, node.span.clone(), format!("Cannot assign to undefined variable `{}`", name))
+                        .with_label(Label::new(node.span.clone()).with_message("Declare it with `let` first").with_color(Color::Red));
+                    return Err(e.into());
+                }
+                Ok(Value::Unit)
+            }
+            ASTKind::Function { name, params, body } => {
+                let function = Function {
+                    name: name.clone(),
+                    params: params.clone(),
+                    body: body.clone(),
+                    closure: self.environment.clone()
+                };
+                self.environment.borrow_mut().define(name.clone(), Value::Function(Rc::new(function)));
+                Ok(Value::Unit)
+            }
+            ASTKind::Call { callee, args } => {
+                let callee_value = self.eval(callee)?;
+                let arity = match &callee_value {
+                    Value::Function(function) => function.params.len(),
+                    Value::NativeFunction(function) => function.arity,
+                    value => {
+                        let e = ErrorReport::new(ErrorReportKind::TypeError, callee.span.clone(), format!("Value of type {} is not callable", value.type_name()))
+                            .with_label(Label::new(callee.span.clone()).with_message(format!("This has type {}", value.type_name())).with_color(Color::Red));
+                        return Err(e.into());
+                    }
+                };
+                if args.len() != arity {
+                    let e = ErrorReport::new(ErrorReportKind::TypeError, node.span.clone(), format!("`{}` expects {} arguments but got {}", callee, arity, args.len()))
+                        .with_label(Label::new(node.span.clone()).with_message(format!("Called with {} arguments", args.len())).with_color(Color::Red));
+                    return Err(e.into());
+                }
+                let mut arg_values = Vec::with_capacity(args.len());
+                for arg in args {
+                    arg_values.push((self.eval(arg)?, arg.span.clone()));
+                }
+                match callee_value {
+                    Value::Function(function) => {
+                        if self.call_depth >= self.max_call_depth {
+                            let e = ErrorReport::new(ErrorReportKind::RuntimeError, node.span.clone(), "Call Depth Exceeded".to_string())
+                                .with_label(Label::new(node.span.clone()).with_message(format!("Calls nest more than {} deep here", self.max_call_depth)).with_color(Color::Red))
+                                .with_note("This is usually recursion without a base case that is ever reached.".to_string());
+                            return Err(e.into());
+                        }
+                        self.call_depth += 1;
+                        let result = self.call_function(&function, arg_values);
+                        self.call_depth -= 1;
+                        result
+                    }
+                    Value::NativeFunction(function) => Ok((function.function)(&mut *self.output, &arg_values)?),
+                    _ => unreachable!("Callee was checked to be callable.")
+                }
+            }
+            ASTKind::ArrayLiteral(elements) => {
+                let mut values = Vec::with_capacity(elements.len());
+                for element in elements {
+                    values.push(self.eval(element)?);
+                }
+                Ok(Value::Array(Rc::new(RefCell::new(values))))
+            }
+            ASTKind::RecordLiteral(fields) => {
+                let mut values = HashMap::with_capacity(fields.len());
+                for (name, value) in fields {
+                    values.insert(name.clone(), self.eval(value)?);
+                }
+                Ok(Value::Record(Rc::new(values)))
+            }
+            ASTKind::Index { object, index } => {
+                let object_value = self.eval(object)?;
+                let index_value = self.eval(index)?;
+                Ok(self.eval_index(object, object_value, index, index_value)?)
+            }
+            ASTKind::Range { start, end } => {
+                let start = start.as_ref().map(|start| self.eval_bound(start)).transpose()?;
+                let end = end.as_ref().map(|end| self.eval_bound(end)).transpose()?;
+                Ok(Value::Range(start, end))
+            }
+            ASTKind::Match { scrutinee, arms } => {
+                let value = self.eval(scrutinee)?;
+                match arms.iter().find(|(pattern, _)| Self::pattern_matches(pattern, &value)) {
+                    Some((_, body)) => self.eval(body),
+                    None => {
+                        let e = ErrorReport::new(ErrorReportKind::RuntimeError, node.span.clone(), "No Matching Arm".to_string())
+                            .with_label(Label::new(scrutinee.span.clone()).with_message(format!("{} matched none of the arms", value.repr())).with_color(Color::Red))
+                            .with_help("Add a `_ => ...` arm to handle every other value.".to_string());
+                        Err(e.into())
+                    }
+                }
+            }
+            ASTKind::Cast { expr, target } => {
+                let value = self.eval(expr)?;
+                Ok(Self::cast(node, value, *target)?)
+            }
+            ASTKind::Member { object, name } => {
+                let value = self.eval(object)?;
+                if let Value::Record(fields) = &value {
+                    return fields.get(name).cloned().ok_or_else(|| {
+                        let mut names: Vec<_> = fields.keys().map(|name| format!("`{}`", name)).collect();
+                        names.sort();
+                        ErrorReport::new(ErrorReportKind::NameError, node.span.clone(), format!("Record has no field `{}`", name))
+                            .with_label(Label::new(node.span.clone()).with_message("No such field").with_color(Color::Red))
+                            .with_note(format!("The record has the fields {}.", names.join(", ")))
+                            .into()
+                    });
+                }
+                let e = ErrorReport::new(ErrorReportKind::TypeError, node.span.clone(), format!("Value of type {} has no member `{}`", value.type_name(), name))
+                    .with_label(Label::new(object.span.clone()).with_message(format!("This has type {}", value.type_name())).with_color(Color::Red));
+                Err(e.into())
+            }
+            ASTKind::Return(value) => {
+                let value = match value {
+                    Some(value) => self.eval(value)?,
+                    None => Value::Unit
+                };
+                Err(Signal::Return(value))
+            }
+            ASTKind::Import(path) => {
+                let e = ErrorReport::new(ErrorReportKind::ImportError, node.span.clone(), format!("Unresolved import of `{}`", path))
+                    .with_label(Label::new(node.span.clone()).with_message("Never loaded").with_color(Color::Red))
+                    .with_note("Imports are loaded by the resolver before the program runs.".to_string());
+                Err(e.into())
+            }
+            ASTKind::Break => Err(Signal::Break),
+            ASTKind::Continue => Err(Signal::Continue),
+        }
+    }
+
+    fn call_function(&mut self, function: &Function, args: Vec<(Value, Span)>) -> EvalResult<Value> {
+        let mut scope = Environment::with_parent(function.closure.clone());
+        for (param, (arg, _)) in function.params.iter().zip(args) {
+            scope.define(param.clone(), arg);
+        }
+        let previous = std::mem::replace(&mut self.environment, Rc::new(RefCell::new(scope)));
+        let result = self.eval(&function.body);
+        self.environment = previous;
+        match result {
+            Err(Signal::Return(value)) => Ok(value),
+            result => result
+        }
+    }
+
+    fn eval_statements(&mut self, statements: &[Rc<AST>]) -> EvalResult<Value> {
+        let mut value = Value::Unit;
+        for statement in statements {
+            value = self.eval(statement)?;
+        }
+        Ok(value)
+    }
+
+    fn eval_bound(&mut self, bound: &Rc<AST>) -> EvalResult<isize> {
+        match self.eval(bound)? {
+            Value::Int(value) => Ok(value),
+            value => {
+                let e = ErrorReport::new(ErrorReportKind::TypeError, bound.span.clone(), format!("Range bounds must be int but got {}", value.type_name()))
+                    .with_label(Label::new(bound.span.clone()).with_message(format!("This has type {}", value.type_name())).with_color(Color::Red));
+                Err(e.into())
+            }
+        }
+    }
+
+    fn eval_slice(&mut self, object: &AST, object_value: Value, index: &AST, start: Option<isize>, end: Option<isize>) -> Result<Value> {
+        let length = match &object_value {
+            Value::Array(values) => values.borrow().len(),
+            Value::Str(val) => val.chars().count(),
+            value => return Err(Self::not_indexable(object, value))
+        };
+        let start_index = start.map_or(Some(0), |start| usize::try_from(start).ok());
+        let end_index = end.map_or(Some(length), |end| usize::try_from(end).ok());
+        let (Some(start_index), Some(end_index)) = (start_index, end_index) else {
+            return Err(Self::slice_out_of_bounds(index, start, end, length));
+        };
+        if start_index > end_index || end_index > length {
+            return Err(Self::slice_out_of_bounds(index, start, end, length));
+        }
+        match object_value {
+            Value::Array(values) => Ok(Value::Array(Rc::new(RefCell::new(values.borrow()[start_index..end_index].to_vec())))),
+            Value::Str(val) => Ok(Value::Str(val.chars().skip(start_index).take(end_index - start_index).collect())),
+            _ => unreachable!("Object was checked to be sliceable.")
+        }
+    }
+
+    fn slice_out_of_bounds(index: &AST, start: Option<isize>, end: Option<isize>, length: usize) -> ErrorReport {
+        ErrorReport::new(ErrorReportKind::RuntimeError, index.span.clone(), "Index Out Of Bounds".to_string())
+            .with_label(Label::new(index.span.clone()).with_message(format!("Range {} is out of bounds for length {}", Value::Range(start, end), length)).with_color(Color::Red))
+    }
+
+    fn not_indexable(object: &AST, value: &Value) -> ErrorReport {
+        ErrorReport::new(ErrorReportKind::TypeError, object.span.clone(), format!("Value of type {} cannot be indexed", value.type_name()))
+            .with_label(Label::new(object.span.clone()).with_message(format!("This has type {}", value.type_name())).with_color(Color::Red))
+    }
+
+    /// Literal patterns match the values that `==` would consider equal to them.
+    fn pattern_matches(pattern: &Pattern, value: &Value) -> bool {
+        let literal = match pattern {
+            Pattern::Int(val) => Value::Int(*val),
+            Pattern::Float(val) => Value::Float(*val),
+            Pattern::Str(val) => Value::Str(val.clone()),
+            Pattern::Bool(val) => Value::Bool(*val),
+            Pattern::Wildcard => return true
+        };
+        literal.equals(value)
+    }
+
+    /// Floats truncate toward zero when cast to int, and strings are parsed as numbers.
+    fn cast(node: &AST, value: Value, target: TypeName) -> Result<Value> {
+        let converted = match (&value, target) {
+            (_, TypeName::String) => Some(Value::Str(value.to_string())),
+            (Value::Int(val), TypeName::Int) => Some(Value::Int(*val)),
+            (Value::Int(val), TypeName::Float) => Some(Value::Float(*val as f64)),
+            (Value::Float(val), TypeName::Float) => Some(Value::Float(*val)),
+            // `as` on f64 saturates, so check the range to report rather than silently clamp
+            (Value::Float(val), TypeName::Int) if val.is_finite() && *val >= isize::MIN as f64 && *val < isize::MAX as f64 => Some(Value::Int(*val as isize)),
+            (Value::Str(val), TypeName::Int) => val.trim().parse().ok().map(Value::Int),
+            (Value::Str(val), TypeName::Float) => val.trim().parse().ok().map(Value::Float),
+            _ => None
+        };
+        converted.ok_or_else(|| {
+            ErrorReport::new(ErrorReportKind::RuntimeError, node.span.clone(), "Invalid Cast".to_string())
+                .with_label(Label::new(node.span.clone()).with_message(format!("Cannot convert {} {} to {}", value.type_name(), value.repr(), target)).with_color(Color::Red))
+        })
+    }
+
+    fn eval_index(&mut self, object: &AST, object_value: Value, index: &AST, index_value: Value) -> Result<Value> {
+        if let Value::Range(start, end) = index_value {
+            return self.eval_slice(object, object_value, index, start, end);
+        }
+        let Value::Int(i) = index_value else {
+            let e = ErrorReport::new(ErrorReportKind::TypeError, index.span.clone(), format!("Cannot index with {}", index_value.type_name()))
+                .with_label(Label::new(index.span.clone()).with_message(format!("This has type {}", index_value.type_name())).with_color(Color::Red));
+            return Err(e);
+        };
+        let (element, length) = match &object_value {
+            Value::Array(values) => {
+                let values = values.borrow();
+                (usize::try_from(i).ok().and_then(|i| values.get(i).cloned()), values.len())
+            }
+            Value::Str(val) => {
+                let char = usize::try_from(i).ok().and_then(|i| val.chars().nth(i));
+                (char.map(|c| Value::Str(c.to_string())), val.chars().count())
+            }
+            value => return Err(Self::not_indexable(object, value))
+        };
+        element.ok_or_else(|| {
+            ErrorReport::new(ErrorReportKind::RuntimeError, index.span.clone(), "Index Out Of Bounds".to_string())
+                .with_label(Label::new(index.span.clone()).with_message(format!("Index {} is out of bounds for length {}", i, length)).with_color(Color::Red))
+        })
+    }
+
+    fn eval_unary(op: UnaryOp, node: &AST, value: Value) -> Result<Value> {
+        match (op, value) {
+            (UnaryOp::Neg, Value::Int(val)) => val.checked_neg().map(Value::Int).ok_or_else(|| {
+                ErrorReport::new(ErrorReportKind::RuntimeError, node.span.clone(), "Integer Overflow".to_string())
+                    .with_label(Label::new(node.span.clone()).with_message("Result does not fit in an int").with_color(Color::Red))
+                    .with_note(format!("Operand was {}", val))
+            }),
+            (UnaryOp::Neg, Value::Float(val)) => Ok(Value::Float(-val)),
+            (op, value) => {
+                let e = ErrorReport::new(ErrorReportKind::TypeError, node.span.clone(), format!("Cannot apply `{}` to {}", op, value.type_name()))
+                    .with_label(Label::new(node.span.clone()).with_message(format!("Unsupported for {}", value.type_name())).with_color(Color::Red));
+                Err(e)
+            }
+        }
+    }
+
+    fn eval_binary(&mut self, op: BinaryOp, op_span: &Span, lhs: Value, rhs: Value) -> Result<Value> {
+        if self.strict_numeric && matches!((&lhs, &rhs), (Value::Int(_), Value::Float(_)) | (Value::Float(_), Value::Int(_))) {
+            let e = ErrorReport::new(ErrorReportKind::TypeError, op_span.clone(), format!("Cannot apply `{}` to {} and {}", op, lhs.type_name(), rhs.type_name()))
+                .with_label(Label::new(op_span.clone()).with_message("Operands are an int and a float").with_color(Color::Red))
+                .with_note("Ints are not promoted to floats in strict numeric mode.".to_string())
+                .with_help("Convert the int with `as float`, or the float with `as int`.".to_string());
+            return Err(e);
+        }
+        match op {
+            BinaryOp::Eq => return Ok(Value::Bool(lhs.equals(&rhs))),
+            BinaryOp::NotEq => return Ok(Value::Bool(!lhs.equals(&rhs))),
+            op if op.is_comparison() => {
+                if let Some(result) = Self::compare(op, &lhs, &rhs) {
+                    return Ok(Value::Bool(result));
+                }
+            }
+            _ => {}
+        }
+        match (op, lhs, rhs) {
+            (BinaryOp::Div, Value::Int(_), Value::Int(0)) => {
+                let e = ErrorReport::new(ErrorReportKind::RuntimeError, op_span.clone(), "Division By Zero".to_string())
+                    .with_label(Label::new(op_span.clone()).with_message("Right-hand side is zero").with_color(Color::Red));
+                Err(e)
+            }
+            (op, Value::Int(lhs), Value::Int(rhs)) if op.is_arithmetic() => {
+                let result = match op {
+                    BinaryOp::Add => lhs.checked_add(rhs),
+                    BinaryOp::Sub => lhs.checked_sub(rhs),
+                    BinaryOp::Mul => lhs.checked_mul(rhs),
+                    BinaryOp::Div => lhs.checked_div(rhs),
+                    _ => unreachable!("Only arithmetic reaches this point.")
+                };
+                result.map(Value::Int).ok_or_else(|| {
+                    ErrorReport::new(ErrorReportKind::RuntimeError, op_span.clone(), "Integer Overflow".to_string())
+                        .with_label(Label::new(op_span.clone()).with_message("Result does not fit in an int").with_color(Color::Red))
+                        .with_note(format!("Operands were {} and {}", lhs, rhs))
+                })
+            }
+            (op, Value::Int(lhs), Value::Float(rhs)) if op.is_arithmetic() => Ok(Value::Float(Self::float_op(op, lhs as f64, rhs))),
+            (op, Value::Float(lhs), Value::Int(rhs)) if op.is_arithmetic() => Ok(Value::Float(Self::float_op(op, lhs, rhs as f64))),
+            (op, Value::Float(lhs), Value::Float(rhs)) if op.is_arithmetic() => Ok(Value::Float(Self::float_op(op, lhs, rhs))),
+            (BinaryOp::Add, Value::Str(lhs), Value::Str(rhs)) => Ok(Value::Str(lhs + &rhs)),
+            (op, lhs, rhs) => {
+                let e = ErrorReport::new(ErrorReportKind::TypeError, op_span.clone(), format!("Cannot apply `{}` to {} and {}", op, lhs.type_name(), rhs.type_name()))
+                    .with_label(Label::new(op_span.clone()).with_message(format!("Unsupported for {} and {}", lhs.type_name(), rhs.type_name())).with_color(Color::Red));
+                Err(e)
+            }
+        }
+    }
+
+    fn float_op(op: BinaryOp, lhs: f64, rhs: f64) -> f64 {
+        match op {
+            BinaryOp::Add => lhs + rhs,
+            BinaryOp::Sub => lhs - rhs,
+            BinaryOp::Mul => lhs * rhs,
+            BinaryOp::Div => lhs / rhs,
+            _ => unreachable!("Only arithmetic reaches this point.")
+        }
+    }
+
+    /// Order numbers, promoting ints to floats when mixed, and strings lexicographically.
+    /// Returns `None` for any other pair of types.
+    fn compare(op: BinaryOp, lhs: &Value, rhs: &Value) -> Option<bool> {
+        let ordering = match (lhs, rhs) {
+            (Value::Int(lhs), Value::Int(rhs)) => lhs.partial_cmp(rhs),
+            (Value::Int(lhs), Value::Float(rhs)) => (*lhs as f64).partial_cmp(rhs),
+            (Value::Float(lhs), Value::Int(rhs)) => lhs.partial_cmp(&(*rhs as f64)),
+            (Value::Float(lhs), Value::Float(rhs)) => lhs.partial_cmp(rhs),
+            (Value::Str(lhs), Value::Str(rhs)) => lhs.partial_cmp(rhs),
+            _ => return None
+        };
+        // NaN has no ordering, so every comparison with it is false
+        Some(ordering.is_some_and(|ordering| match op {
+            BinaryOp::Less => ordering.is_lt(),
+            BinaryOp::LessEq => ordering.is_le(),
+            BinaryOp::Greater => ordering.is_gt(),
+            BinaryOp::GreaterEq => ordering.is_ge(),
+            _ => unreachable!("Only ordering comparisons are compared.")
+        }))
+    }
+}
+
+impl Default for Interpreter<'_> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use super::*;
+
+    /// Run `source`, which must succeed, returning the value of its last statement.
+    fn eval(source: &str) -> Value {
+        let (value, reports) = crate::eval(Arc::from("test"), source);
+        value.unwrap_or_else(|| panic!("{:?}", reports.iter().map(ErrorReport::title).collect::<Vec<_>>()))
+    }
+
+    /// Run `source`, which must fail, returning the error it failed with.
+    fn eval_error(source: &str) -> ErrorReport {
+        let (value, reports) = crate::eval(Arc::from("test"), source);
+        assert!(value.is_none(), "expected an error but got {:?}", value);
+        reports.into_iter().find(ErrorReport::is_error).expect("Failed without an error report")
+    }
+
+    #[test]
+    fn if_takes_the_then_branch() {
+        assert_eq!(eval("if 1 < 2 { \"then\" } else { \"else\" }").repr(), "\"then\"");
+    }
+
+    #[test]
+    fn if_takes_the_else_branch() {
+        assert_eq!(eval("if 2 < 1 { \"then\" } else { \"else\" }").repr(), "\"else\"");
+    }
+
+    #[test]
+    fn if_without_else_not_taken_is_unit() {
+        assert!(matches!(eval("if false { 1 }"), Value::Unit));
+    }
+
+    #[test]
+    fn if_condition_must_be_a_bool() {
+        let error = eval_error("if 1 { 2 }");
+        assert_eq!(error.title(), "TypeError[E0005]: Expected a bool condition but got int");
+        assert_eq!((error.span().start, error.span().end), (3, 3));
+    }
+
+    #[test]
+    fn statements_and_empty_blocks_are_unit() {
+        assert!(matches!(eval("let x = 1;"), Value::Unit));
+        assert!(matches!(eval("{}"), Value::Unit));
+        assert_eq!(Value::Unit.to_string(), "()");
+    }
+
+    #[test]
+    fn while_runs_until_the_condition_is_false() {
+        assert_eq!(eval("let i = 0; let runs = 0; while i < 5 { i = i + 1; runs = runs + 1; } runs").repr(), "5");
+    }
+
+    #[test]
+    fn while_is_unit() {
+        assert!(matches!(eval("let i = 0; while i < 1 { i = i + 1; }"), Value::Unit));
+    }
+
+    #[test]
+    fn while_condition_must_be_a_bool() {
+        let error = eval_error("while 1 { }");
+        assert_eq!(error.title(), "TypeError[E0005]: Expected a bool condition but got int");
+        assert_eq!((error.span().start, error.span().end), (6, 6));
+    }
+
+    #[test]
+    fn assignment_updates_a_variable() {
+        assert_eq!(eval("let x = 1; x = 2; x").repr(), "2");
+    }
+
+    #[test]
+    fn assignment_updates_the_defining_scope() {
+        assert_eq!(eval("let x = 1; { x = 2; } x").repr(), "2");
+    }
+
+    #[test]
+    fn compound_assignment_desugars_to_the_operator() {
+        assert_eq!(eval("let x = 10; x += 5; x -= 3; x *= 2; x /= 4; x").repr(), "6");
+    }
+
+    #[test]
+    fn assigning_an_undefined_variable_is_an_error() {
+        let error = eval_error("y = 1;");
+        assert_eq!(error.title(), "NameError[E0006]: Cannot assign to undefined variable `y`");
+    }
+
+    #[test]
+    fn block_variables_do_not_leak() {
+        let error = eval_error("{ let inner = 1; inner; } inner");
+        assert_eq!(error.title(), "NameError[E0006]: Undefined variable `inner`");
+    }
+
+    #[test]
+    fn shadowing_ends_with_the_block() {
+        assert_eq!(eval("let x = 1; let y = { let x = 2; x }; [x, y]").repr(), "[1, 2]");
+    }
+
+    #[test]
+    fn block_value_is_its_last_expression() {
+        assert_eq!(eval("{ 1; 2 }").repr(), "2");
+    }
+
+    #[test]
+    fn calls_a_function_without_arguments() {
+        assert_eq!(eval("fn one() { 1 } one()").repr(), "1");
+    }
+
+    #[test]
+    fn calls_a_function_with_two_arguments() {
+        assert_eq!(eval("fn sub(a, b) { a - b } sub(5, 3)").repr(), "2");
+    }
+
+    #[test]
+    fn functions_close_over_their_environment() {
+        assert_eq!(eval("fn counter() { let count = 0; fn next() { count = count + 1; count } next } let next = counter(); next(); next()").repr(), "2");
+    }
+
+    #[test]
+    fn arity_mismatch_points_at_the_call() {
+        let error = eval_error("fn f(a) { a } f(1, 2)");
+        assert_eq!(error.title(), "TypeError[E0005]: `f` expects 1 arguments but got 2");
+        assert_eq!((error.span().start, error.span().end), (14, 20));
+    }
+
+    #[test]
+    fn return_leaves_the_function_early() {
+        assert_eq!(eval("fn sign(x) { if x < 0 { return -1; } 1 } [sign(-5), sign(5)]").repr(), "[-1, 1]");
+    }
+
+    #[test]
+    fn return_leaves_a_loop_inside_the_function() {
+        assert_eq!(eval("fn first_over(limit) { let i = 0; while true { if i > limit { return i; } i = i + 1; } } first_over(3)").repr(), "4");
+    }
+
+    #[test]
+    fn bare_return_is_unit() {
+        assert!(matches!(eval("fn f() { return; 1 } f()"), Value::Unit));
+    }
+
+    #[test]
+    fn break_stops_the_loop() {
+        assert_eq!(eval("let i = 0; while true { if i == 3 { break; } i = i + 1; } i").repr(), "3");
+    }
+
+    #[test]
+    fn continue_skips_the_rest_of_the_iteration() {
+        let source = "let i = 0; let odd = 0; while i < 6 { i = i + 1; if i - i / 2 * 2 == 0 { continue; } odd = odd + 1; } odd";
+        assert_eq!(eval(source).repr(), "3");
+    }
+
+    #[test]
+    fn builds_an_array() {
+        assert_eq!(eval("[1, \"two\", [3]]").repr(), "[1, \"two\", [3]]");
+    }
+
+    #[test]
+    fn indexes_an_array() {
+        assert_eq!(eval("let items = [10, 20, 30]; items[1]").repr(), "20");
+    }
+
+    #[test]
+    fn out_of_bounds_index_points_at_the_index() {
+        let error = eval_error("let items = [1, 2, 3]; items[3]");
+        assert_eq!(error.title(), "RuntimeError[E0007]: Index Out Of Bounds");
+        let label = &error.labels()[0];
+        assert_eq!((label.span().start, label.span().end), (29, 29));
+    }
+
+    #[test]
+    fn multiplying_past_the_int_range_is_an_error() {
+        let error = eval_error(&format!("let x = {};\nx * 2", isize::MAX));
+        assert_eq!(error.title(), "RuntimeError[E0007]: Integer Overflow");
+        assert_eq!(error.note(), Some(format!("Operands were {} and 2", isize::MAX).as_str()));
+        let op = error.labels()[0].span();
+        assert_eq!((op.start, op.end), (31, 31));
+    }
+
+    #[test]
+    fn adding_up_to_the_int_range_is_fine() {
+        assert_eq!(eval(&format!("let x = {};\nx - 1 + 1", isize::MAX)).repr(), isize::MAX.to_string());
+    }
+
+    #[test]
+    fn folded_overflow_is_still_an_error() {
+        let error = eval_error(&format!("{} + 1", isize::MAX));
+        assert_eq!(error.title(), "RuntimeError[E0007]: Integer Overflow");
+    }
+
+    #[test]
+    fn ranges_evaluate_to_range_values() {
+        assert_eq!(eval("let a = 1; a..a + 2").repr(), "1..3");
+        assert_eq!(eval("..3").repr(), "..3");
+    }
+
+    #[test]
+    fn println_writes_to_the_output_sink() {
+        let (ast, _) = crate::parse(Arc::from("test"), "println(\"hi\")");
+        let mut output = Vec::new();
+        let value = Interpreter::new().with_output(&mut output).run(&ast.unwrap()).unwrap();
+        assert!(matches!(value, Value::Unit));
+        assert_eq!(String::from_utf8(output).unwrap(), "hi\n");
+    }
+
+    #[test]
+    fn print_leaves_out_the_newline() {
+        let (ast, _) = crate::parse(Arc::from("test"), "print(1); print(\" \"); print(2.5)");
+        let mut output = Vec::new();
+        Interpreter::new().with_output(&mut output).run(&ast.unwrap()).unwrap();
+        assert_eq!(String::from_utf8(output).unwrap(), "1 2.5");
+    }
+
+    #[test]
+    fn numbers_compare_across_ints_and_floats() {
+        assert_eq!(eval("1 < 2").repr(), "true");
+        assert_eq!(eval("2 <= 1.5").repr(), "false");
+        assert_eq!(eval("2.5 > 2").repr(), "true");
+        assert_eq!(eval("2 >= 2.0").repr(), "true");
+        assert_eq!(eval("1 == 1.0").repr(), "true");
+    }
+
+    #[test]
+    fn strings_compare_lexicographically() {
+        assert_eq!(eval("\"apple\" < \"banana\"").repr(), "true");
+        assert_eq!(eval("\"b\" > \"abc\"").repr(), "true");
+        assert_eq!(eval("\"a\" == \"a\"").repr(), "true");
+    }
+
+    #[test]
+    fn values_of_different_types_are_unequal() {
+        assert_eq!(eval("1 == \"1\"").repr(), "false");
+        assert_eq!(eval("true != 1").repr(), "true");
+    }
+
+    #[test]
+    fn ordering_different_types_is_an_error() {
+        assert_eq!(eval_error("1 < \"2\"").title(), "TypeError[E0005]: Cannot apply `<` to int and string");
+    }
+
+    /// Run `source`, which must succeed, returning what it printed.
+    fn printed(source: &str) -> String {
+        let (ast, reports) = crate::parse(Arc::from("test"), source);
+        assert!(reports.is_empty());
+        let mut output = Vec::new();
+        Interpreter::new().with_output(&mut output).run(&ast.unwrap()).unwrap();
+        String::from_utf8(output).unwrap()
+    }
+
+    #[test]
+    fn and_skips_the_right_operand_once_false() {
+        assert_eq!(printed("fn side() { print(\"evaluated\"); true } false && side()"), "");
+        assert_eq!(printed("fn side() { print(\"evaluated\"); true } true && side()"), "evaluated");
+    }
+
+    #[test]
+    fn or_skips_the_right_operand_once_true() {
+        assert_eq!(printed("fn side() { print(\"evaluated\"); true } true || side()"), "");
+        assert_eq!(printed("fn side() { print(\"evaluated\"); true } false || side()"), "evaluated");
+    }
+
+    #[test]
+    fn logical_operands_must_be_bools() {
+        let error = eval_error("true && 1");
+        assert_eq!(error.title(), "TypeError[E0005]: Expected a bool condition but got int");
+        assert_eq!((error.span().start, error.span().end), (8, 8));
+    }
+
+    #[test]
+    fn casts_convert_between_numbers_and_strings() {
+        assert_eq!(eval("let x = 2.9; x as int").repr(), "2");
+        assert_eq!(eval("let x = -2.9; x as int").repr(), "-2");
+        assert_eq!(eval("let x = 3; x as float").repr(), "3.0");
+        assert_eq!(eval("let x = 42; x as string").repr(), "\"42\"");
+        assert_eq!(eval("\" 12 \" as int").repr(), "12");
+        assert_eq!(eval("\"1.5\" as float").repr(), "1.5");
+    }
+
+    #[test]
+    fn non_numeric_string_cast_to_int_is_an_error() {
+        let error = eval_error("\"abc\" as int");
+        assert_eq!(error.title(), "RuntimeError[E0007]: Invalid Cast");
+        assert_eq!(error.labels()[0].message(), Some("Cannot convert string \"abc\" to int"));
+    }
+
+    #[test]
+    fn let_underscore_evaluates_without_binding() {
+        assert_eq!(printed("let _ = print(\"ran\"); let _ = 2;"), "ran");
+    }
+
+    #[test]
+    fn reading_underscore_is_an_error() {
+        let error = eval_error("let _ = 1; _ + 1");
+        assert_eq!(error.title(), "NameError[E0006]: `_` is not a value");
+        assert_eq!((error.span().start, error.span().end), (11, 11));
+    }
+
+    #[test]
+    fn reads_a_record_field() {
+        assert_eq!(eval("let point = { x: 1, y: 2 }; point.y").repr(), "2");
+        assert_eq!(eval("{ name: \"a\", inner: { n: 3 } }.inner.n").repr(), "3");
+    }
+
+    #[test]
+    fn missing_field_points_at_the_access() {
+        let error = eval_error("let point = { x: 1, y: 2 }; point.z");
+        assert_eq!(error.title(), "NameError[E0006]: Record has no field `z`");
+        assert_eq!((error.span().start, error.span().end), (28, 34));
+        assert_eq!(error.labels()[0].message(), Some("No such field"));
+        assert_eq!(error.note(), Some("The record has the fields `x`, `y`."));
+    }
+
+    #[test]
+    fn braces_without_a_field_are_a_block() {
+        assert!(matches!(eval("let x = 4; { x }"), Value::Int(4)));
+        assert!(matches!(eval("{ x: 4 }"), Value::Record(_)));
+    }
+
+    #[test]
+    fn infinite_loop_stops_at_the_step_limit() {
+        let (ast, _) = crate::parse(Arc::from("test"), "while true { }");
+        let error = Interpreter::new().with_max_steps(1000).run(&ast.unwrap()).unwrap_err();
+        assert_eq!(error.title(), "RuntimeError[E0007]: Execution Limit Exceeded");
+        assert_eq!(error.note(), Some("Evaluation stopped after 1000 steps."));
+    }
+
+    #[test]
+    fn step_limit_leaves_short_programs_alone() {
+        let (ast, _) = crate::parse(Arc::from("test"), "1 + 2");
+        assert!(matches!(Interpreter::new().with_max_steps(10).run(&ast.unwrap()), Ok(Value::Int(3))));
+    }
+
+    #[test]
+    fn unbounded_recursion_stops_at_the_call_depth_limit() {
+        let (ast, _) = crate::parse(Arc::from("test"), "fn f(n) { f(n + 1) }\nf(0)");
+        let error = Interpreter::new().with_max_call_depth(3).run(&ast.unwrap()).unwrap_err();
+        assert_eq!(error.title(), "RuntimeError[E0007]: Call Depth Exceeded");
+        assert_eq!((error.span().start, error.span().end), (10, 17));
+    }
+
+    #[test]
+    fn call_depth_limit_leaves_shallow_recursion_alone() {
+        let source = "fn f(n) { if n == 0 { 0 } else { 1 + f(n - 1) } }\nf(3)";
+        let (ast, _) = crate::parse(Arc::from("test"), source);
+        assert!(matches!(Interpreter::new().with_max_call_depth(4).run(&ast.unwrap()), Ok(Value::Int(3))));
+    }
+
+    #[test]
+    fn match_takes_the_first_matching_arm() {
+        assert_eq!(eval("match 2 { 1 => \"one\", 2 => \"two\", _ => \"many\" }").repr(), "\"two\"");
+        assert_eq!(eval("match -1 { -1 => \"minus one\", _ => \"other\" }").repr(), "\"minus one\"");
+    }
+
+    #[test]
+    fn match_falls_back_to_the_wildcard() {
+        assert_eq!(eval("match \"c\" { \"a\" => 1, _ => 0 }").repr(), "0");
+    }
+
+    #[test]
+    fn match_without_a_matching_arm_is_an_error() {
+        let error = eval_error("match 3 { 1 => true, 2 => false }");
+        assert_eq!(error.title(), "RuntimeError[E0007]: No Matching Arm");
+        assert_eq!((error.labels()[0].span().start, error.labels()[0].span().end), (6, 6));
+        assert_eq!(error.labels()[0].message(), Some("3 matched none of the arms"));
+    }
+
+    #[test]
+    fn grouping_evaluates_its_inner_expression() {
+        assert_eq!(eval("(1 + 2) * 3").repr(), "9");
+        assert_eq!(eval("((\"a\"))").repr(), "\"a\"");
+    }
+
+    #[test]
+    fn bool_conditions_need_no_comparison() {
+        assert!(matches!(eval("if true { }"), Value::Unit));
+        assert_eq!(eval("let ok = 1 < 2; if ok { \"yes\" } else { \"no\" }").repr(), "\"yes\"");
+    }
+
+    #[test]
+    fn interpolates_expressions_into_strings() {
+        assert_eq!(eval("let name = \"world\"; \"hello ${name}!\"").repr(), "\"hello world!\"");
+        assert_eq!(eval("\"${1 + 2} and ${[1, 2][1]} and ${true}\"").repr(), "\"3 and 2 and true\"");
+    }
+
+    #[test]
+    fn escaped_dollar_is_not_interpolated() {
+        assert_eq!(eval("let name = 1; \"\\${name} costs \\$5\"").to_string(), "${name} costs $5");
+    }
+
+    #[test]
+    fn interpolation_can_hold_braces_and_strings() {
+        assert_eq!(eval("\"${ { \"}\" } }\"").repr(), "\"}\"");
+    }
+
+    #[test]
+    fn unit_literal_is_unit() {
+        assert!(matches!(eval("()"), Value::Unit));
+        assert!(matches!(eval("fn nothing() { () } nothing()"), Value::Unit));
+    }
+
+    fn run_strict(source: &str) -> Result<Value> {
+        let (ast, _) = crate::parse(Arc::from("test"), source);
+        Interpreter::new().with_strict_numeric(true).run(&ast.unwrap())
+    }
+
+    #[test]
+    fn mixed_arithmetic_promotes_by_default() {
+        assert_eq!(eval("1 + 2.0").repr(), "3.0");
+    }
+
+    #[test]
+    fn strict_numeric_rejects_mixed_arithmetic() {
+        let Err(error) = run_strict("1 + 2.0") else { panic!("Expected an error") };
+        assert_eq!(error.title(), "TypeError[E0005]: Cannot apply `+` to int and float");
+        assert_eq!((error.span().start, error.span().end), (2, 2));
+        assert!(matches!(run_strict("1 as float + 2.0"), Ok(Value::Float(val)) if val == 3.0));
+        assert!(matches!(run_strict("1 + 2"), Ok(Value::Int(3))));
+    }
+
+    #[test]
+    fn negation_needs_a_number() {
+        assert_eq!(eval("let x = 2; -x * 3").repr(), "-6");
+        assert_eq!(eval_error("let s = \"a\"; -s").title(), "TypeError[E0005]: Cannot apply `-` to string");
+    }
+}
@@ -1,13 +1,15 @@
 use std::cell::RefCell;
 use std::iter::Peekable;
-use std::ops::Index;
+use std::ops::RangeInclusive;
 use std::rc::Rc;
 use std::str::Chars;
 use std::sync::{Arc};
-use ariadne::{Color, Label};
-use crate::error::{ResultErrorless, ErrorReport, ErrorReportKind};
+use ariadne::Color;
+use crate::error::{ResultErrorless, ErrorReport, ErrorReportKind, Label, Severity};
 use crate::span::{Span};
-use crate::token::{Token, TokenKind};
+use crate::token::{Base, Token, TokenKind};
+
+const DEFAULT_MAX_TOKEN_LENGTH: usize = 4096;
 
 pub struct Lexer<'a> {
     filename: Arc<str>,
@@ -15,6 +17,18 @@ pub struct Lexer<'a> {
     chars: Peekable<Chars<'a>>,
     current: Option<char>,
     index: usize,
+    offset: usize,
+    max_token_length: usize,
+    keep_comments: bool,
+    tabs_are_errors: bool,
+    check_mixed_indentation: bool,
+    /// The indentation char of the first indented line and where it was, once one has been seen.
+    indentation: Option<(char, Span)>,
+    reported_mixed_indentation: bool,
+    newline_before: bool,
+    /// The last token pushed that isn't a comment, even once a `TokenStream` has taken it.
+    last_token: Option<Token<'a>>,
+    finished: bool,
     pub had_error: bool,
     pub tokens: Vec<Token<'a>>,
     reports: Rc<RefCell<Vec<ErrorReport>>>
@@ -27,6 +41,16 @@ impl<'a> Lexer<'a> {
             filename,
             current: chars.next(),
             index: 0,
+            offset: 0,
+            max_token_length: DEFAULT_MAX_TOKEN_LENGTH,
+            keep_comments: false,
+            tabs_are_errors: false,
+            check_mixed_indentation: false,
+            indentation: None,
+            reported_mixed_indentation: false,
+            newline_before: false,
+            last_token: None,
+            finished: false,
             source,
             chars,
             had_error: false,
@@ -35,217 +59,1342 @@ impl<'a> Lexer<'a> {
         }
     }
 
-    fn peek(&mut self, offset: usize) -> Option<char> { self.chars.peek().cloned() }
+    /// Lex all of `source` in one go, returning the tokens along with every report made.
+    pub fn lex_str(filename: Arc<str>, source: &'a str) -> (Vec<Token<'a>>, Vec<ErrorReport>) {
+        let reports = Rc::new(RefCell::new(Vec::new()));
+        let mut lexer = Lexer::new(filename, source, reports.clone());
+        lexer.lex_tokens();
+        (lexer.tokens, reports.take())
+    }
+
+    /// Identifiers and number literals longer than this are reported instead of being pushed.
+    pub fn with_max_token_length(mut self, max_token_length: usize) -> Self {
+        self.max_token_length = max_token_length;
+        self
+    }
+
+    /// Push comments as tokens instead of discarding them, for tools that need to preserve them.
+    pub fn with_keep_comments(mut self, keep_comments: bool) -> Self {
+        self.keep_comments = keep_comments;
+        self
+    }
+
+    /// Warn about tabs used to indent a line. Tabs anywhere else are still ordinary whitespace.
+    pub fn with_tabs_are_errors(mut self, tabs_are_errors: bool) -> Self {
+        self.tabs_are_errors = tabs_are_errors;
+        self
+    }
+
+    /// Advise when some lines are indented with tabs and others with spaces. Only the first line to
+    /// differ from the first indented line is reported.
+    pub fn with_check_mixed_indentation(mut self, check_mixed_indentation: bool) -> Self {
+        self.check_mixed_indentation = check_mixed_indentation;
+        self
+    }
+
+    /// Shift every span by `offset` bytes, for lexing a piece of a larger source, like the
+    /// expression inside a string interpolation.
+    pub fn with_offset(mut self, offset: usize) -> Self {
+        self.offset = offset;
+        self
+    }
+
+    /// The char `offset` places after the current one.
+    fn peek(&self, offset: usize) -> Option<char> { self.source[self.index..].chars().nth(offset) }
 
-    fn span(&self, start: usize, end: usize) -> Span { Span::new(start, end, self.filename.clone()) }
+    fn span(&self, start: usize, end: usize) -> Span { Span::new(self.offset + start, self.offset + end, self.filename.clone()) }
+
+    /// The span of the whole char starting at `index`, which may be several bytes long.
+    fn span_at(&self, index: usize) -> Span {
+        let width = self.source[index..].chars().next().map_or(1, char::len_utf8);
+        self.span(index, index + width - 1)
+    }
 
-    fn span_at(&self, index: usize) -> Span { Span::location(index, self.filename.clone()) }
+    /// From `from` up to the last byte consumed, spans being inclusive at both ends.
+    fn span_from(&self, from: usize) -> Span { self.span(from, self.index.saturating_sub(1).max(from)) }
 
-    fn span_from(&self, from: usize) -> Span { Span::new(from, self.index, self.filename.clone()) }
+    /// Like `span_from`, but without any whitespace at either end.
+    fn trimmed_span_from(&self, from: usize) -> Span {
+        let Span { start, end, .. } = Span::new(from, self.index.saturating_sub(1).max(from), self.filename.clone()).trim(self.source);
+        self.span(start, end)
+    }
 
     fn advance(&mut self) {
-        if self.current.is_some() {
+        if let Some(char) = self.current {
             self.current = self.chars.next();
-            self.index += 1;
+            self.index += char.len_utf8();
         }
     }
 
+    /// Skip `count` bytes in one go. They must end on a char boundary, which any run of ASCII does.
+    fn advance_bytes(&mut self, count: usize) {
+        if count == 0 {
+            return;
+        }
+        self.index += count;
+        self.chars = self.source[self.index..].chars().peekable();
+        self.current = self.chars.next();
+    }
+
     fn push(&mut self, mut token: Token<'a>) {
+        let limited = matches!(token.kind,
+            TokenKind::Identifier
+            | TokenKind::IntegerLiteral(_)
+            | TokenKind::FloatLiteral
+        );
+        let length = if limited { token.text.chars().count() } else { 0 };
+        if length > self.max_token_length {
+            let start = self.span_at(token.span.start - self.offset);
+            let e = ErrorReport::new(ErrorReportKind::SyntaxError, start.clone(), "Token Too Long".to_string())
+                .with_label(Label::new(start).with_message(format!("This token is {} characters long", length)).with_color(Color::Red))
+                .with_note(format!("Identifiers and number literals may be at most {} characters long.", self.max_token_length));
+            self.push_report(e);
+            return;
+        }
+        token.newline_before = self.newline_before;
+        // Comments are skipped by the parser, so the line break belongs to the next real token too
+        if !token.kind.is_comment() {
+            self.newline_before = false;
+            self.last_token = Some(token.clone());
+        }
         self.tokens.push(token)
     }
 
+    /// Push the next `length` bytes as one token. Stops early rather than slicing past the end
+    /// of the source or into the middle of a char.
     fn push_simple(&mut self, kind: TokenKind, length: usize) {
         let start = self.index;
-        let text = &self.source[self.index..self.index+length];
-        for _ in 0..length {
+        while self.current.is_some() && self.index + self.current.map_or(0, char::len_utf8) <= start + length {
             self.advance();
         }
+        let text = &self.source[start..self.index];
         self.push(Token::new(kind, self.span_from(start), text))
     }
 
+    /// Whether only whitespace comes before the current character on its line.
+    fn at_indentation(&self) -> bool {
+        let line_start = self.source[..self.index].rfind('\n').map_or(0, |newline| newline + 1);
+        self.source[line_start..self.index].chars().all(char::is_whitespace)
+    }
+
     fn push_report(&mut self, report: ErrorReport) {
+        self.had_error |= report.is_error();
         self.reports.borrow_mut().push(report);
-        self.had_error = true;
+    }
+
+    /// Two identifiers in a row are never valid, so if the first one is a near miss of a keyword that
+    /// takes a name or expression after it, it was most likely a typo of that keyword.
+    fn suggest_keyword(&mut self) {
+        let Some(previous) = &self.last_token else { return; };
+        if previous.kind != TokenKind::Identifier {
+            return;
+        }
+        let Some(keyword) = SUGGESTED_KEYWORDS.iter().find(|keyword| edit_distance(previous.text, keyword) == 1) else { return; };
+        let span = previous.span.clone();
+        let e = ErrorReport::new(ErrorReportKind::DidYouMean, span.clone(), format!("did you mean `{}`?", keyword))
+            .with_severity(Severity::Advice)
+            .with_label(Label::new(span).with_message(format!("`{}` is not a keyword", previous.text)).with_color(Color::BrightBlue));
+        self.push_report(e);
     }
 
     pub fn lex_tokens(&mut self) {
-        while let Some(char) = self.current {
-            let start = self.index;
-            match char {
-                c if c.is_whitespace() => self.advance(),
-                'a'..='z' | 'A'..='Z' | '_' => {
-                    while let Some(c) = self.current {
-                        match c {
-                            'a'..='z' | 'A'..='Z' | '0'..='9' | '_' => {
-                                self.advance();
-                            }
-                            _ => break
+        while !self.finished {
+            self.lex_step();
+        }
+    }
+
+    /// Lex whatever comes next, which may not push a token at all, like whitespace or a comment. Once
+    /// the source runs out this pushes EOF and the lexer is finished.
+    fn lex_step(&mut self) {
+        // A lexer with an offset is lexing the middle of a file, like an interpolation
+        let at_file_start = self.index == 0 && self.offset == 0;
+        // Windows editors may start a file with a byte order mark, which means nothing in UTF-8
+        if at_file_start && self.current == Some('\u{FEFF}') {
+            self.advance();
+        }
+        // A shebang line is only skipped at the very start of a file, anywhere else `#` is still an error
+        if at_file_start && self.source[self.index..].starts_with("#!") {
+            while let Some(char) = self.current {
+                if char == '\n' || char == '\r' && self.peek(1) == Some('\n') {
+                    break;
+                }
+                self.advance();
+            }
+        }
+        if self.check_mixed_indentation && (self.index == 0 || self.source[..self.index].ends_with('\n')) {
+            self.check_indentation();
+        }
+        let Some(char) = self.current else {
+            // Just past the last char, which is where anything missing at the end should go
+            self.push_simple(TokenKind::EOF, 0);
+            self.finished = true;
+            return;
+        };
+        let start = self.index;
+        match char {
+            '\n' => {
+                self.newline_before = true;
+                self.advance();
+            }
+            '\t' if self.tabs_are_errors && self.at_indentation() => {
+                while self.current == Some('\t') {
+                    self.advance();
+                }
+                let span = self.span_from(start);
+                let e = ErrorReport::new(ErrorReportKind::IndentationStyle, span.clone(), "Tab Indentation".to_string())
+                    .with_severity(Severity::Warning)
+                    .with_label(Label::new(span).with_message("Tab used for indentation").with_color(Color::Yellow))
+                    .with_note("Indent with spaces instead.".to_string());
+                self.push_report(e);
+            }
+            // `\r\n` is a single line break, the `\n` arm records it
+            c if c.is_whitespace() => self.advance(),
+            'r' if self.at_raw_string() => self.lex_raw_string(start),
+            'a'..='z' | 'A'..='Z' | '_' => {
+                while let Some(c) = self.current {
+                    match c {
+                        'a'..='z' | 'A'..='Z' | '0'..='9' | '_' => {
+                            self.advance();
                         }
-                    };
-                    let ident = &self.source[start..self.index];
+                        _ => break
+                    }
+                };
+                let ident = &self.source[start..self.index];
+                let span = self.span(start, self.index-1);
+                let kind = match ident {
+                    "let" => TokenKind::Let,
+                    "true" => TokenKind::True,
+                    "false" => TokenKind::False,
+                    "if" => TokenKind::If,
+                    "else" => TokenKind::Else,
+                    "while" => TokenKind::While,
+                    "fn" => TokenKind::Fn,
+                    "return" => TokenKind::Return,
+                    "break" => TokenKind::Break,
+                    "continue" => TokenKind::Continue,
+                    "as" => TokenKind::As,
+                    "match" => TokenKind::Match,
+                    "import" => TokenKind::Import,
+                    _ => TokenKind::Identifier
+                };
+                if kind == TokenKind::Identifier {
+                    self.suggest_keyword();
+                }
+                self.push(Token::new(kind, span, ident))
+            }
+            '0' if self.peek(1).is_some_and(|c| "box".contains(c)) => {
+                let base = match (char, self.peek(1)) {
+                    ('0', Some('b')) => Base::Bin,
+                    ('0', Some('o')) => Base::Oct,
+                    ('0', Some('x')) => Base::Hex,
+                    _ => unreachable!()
+                };
+                self.advance();
+                self.advance();
+                if self.current == Some('_') {
+                    let e = ErrorReport::new(ErrorReportKind::SyntaxError, self.span_at(start), "Invalid Integer Literal".to_string())
+                        .with_label(Label::new(self.span_at(self.index)).with_message("Separator directly after the prefix").with_color(Color::Red))
+                        .with_note("`_` may only separate digits, remove it or move it between two digits.".to_string());
+                    self.push_report(e);
+                    // Still consume the digits so they aren't lexed as an identifier
+                    let _ = self.lex_integer(base, start);
+                    return;
+                }
+                if self.lex_integer(base, start).is_err() {
+                    return;
+                }
+                if self.current == Some('.') && self.peek(1).is_some_and(|c| c.is_ascii_alphanumeric()) {
+                    // Consume the whole fraction so it isn't lexed as a separate float
+                    self.advance();
+                    while self.current.is_some_and(|c| c.is_ascii_alphanumeric() || c == '_') {
+                        self.advance();
+                    }
                     let span = self.span(start, self.index-1);
-                    let kind = match ident {
-                        "let" => TokenKind::Let,
-                        _ => TokenKind::Identifier
-                    };
-                    self.push(Token::new(kind, span, ident))
-                }
-                '0' if self.peek(1).map_or(false, |c| "box".contains(c)) => {
-                    let base = match (char, self.peek(1)) {
-                        ('0', Some('b')) => Base::Bin,
-                        ('0', Some('o')) => Base::Oct,
-                        ('0', Some('x')) => Base::Hex,
-                        _ => unreachable!()
+                    let e = ErrorReport::new(ErrorReportKind::SyntaxError, span.clone(), "Unsupported Float Literal".to_string())
+                        .with_label(Label::new(span).with_message(format!("{} float literal", base.to_string())).with_color(Color::Red))
+                        .with_note("Only decimal float literals are supported.".to_string());
+                    self.push_report(e);
+                    return;
+                }
+                self.lex_integer_suffix();
+                let num = &self.source[start..self.index];
+                self.push(Token::new(TokenKind::IntegerLiteral(base), self.span_from(start), num));
+            }
+            '0'..='9' => {
+                if self.lex_integer(Base::Dec, start).is_err() {
+                    return;
+                }
+                // `1..2` is a range, not the float `1.` followed by `.2`
+                if self.current == Some('.') && self.peek(1) != Some('.') {
+                    // `_` only separates digits, so `1_.5` and `1._5` are both malformed
+                    let separator = if self.source[..self.index].ends_with('_') {
+                        Some(self.index - 1)
+                    } else if self.peek(1) == Some('_') {
+                        Some(self.index + 1)
+                    } else {
+                        None
                     };
                     self.advance();
-                    self.advance();
-                    if self.lex_integer(base, start).is_err() {
-                        continue;
+                    if self.lex_integer(Base::Dec, start).is_err() {
+                        return;
+                    }
+                    if let Some(separator) = separator {
+                        let span = self.span_from(start);
+                        let e = ErrorReport::new(ErrorReportKind::SyntaxError, span, "Invalid Float Literal".to_string())
+                            .with_label(Label::new(self.span_at(separator)).with_message("Separator next to the decimal point").with_color(Color::Red))
+                            .with_note("A `_` may only go between two digits.".to_string())
+                            .with_help("Remove the `_`.".to_string());
+                        self.push_report(e);
+                        return;
+                    }
+                    if self.current == Some('.') && self.peek(1) != Some('.') {
+                        let span = self.span_from(start);
+                        let e = ErrorReport::new(ErrorReportKind::SyntaxError, span, "Invalid Float Literal".to_string())
+                            .with_label(Label::new(self.span_at(self.index)).with_message("Second fractional indicator").with_color(Color::Red))
+                            .with_note("A float literal has exactly one decimal point.".to_string())
+                            .with_help("Remove the second `.`.".to_string());
+                        self.push_report(e);
+                        return;
                     }
-                    let num = &self.source[start..self.index];
-                    self.push(Token::new(TokenKind::from(base), self.span_from(start), num));
+                    let _ = self.lex_float_end(start);
+                    return;
                 }
-                '0'..='9' => {
+                // `1f64` is a float written without a decimal point
+                if self.at_exponent() || self.float_suffix_length().is_some() {
+                    let _ = self.lex_float_end(start);
+                    return;
+                }
+                self.lex_integer_suffix();
+                let num = &self.source[start..self.index];
+                self.push(Token::new(TokenKind::IntegerLiteral(Base::Dec), self.span_from(start), num));
+            },
+            '.' => match self.peek(1) {
+                Some('0'..='9') => {
+                    self.advance();
                     if self.lex_integer(Base::Dec, start).is_err() {
-                        continue;
+                        return;
                     }
-                    if let Some('.') = self.current {
-                        self.advance();
-                        if self.lex_integer(Base::Dec, start).is_err() {
-                            continue;
-                        }
-                        if let Some('.') = self.current {
-                            let span = self.span_from(start);
-                            let e = ErrorReport::new(ErrorReportKind::SyntaxError, span, "Invalid Float Literal".to_string())
-                                .with_label(Label::new(self.span_at(self.index)).with_message("Second fractional indicator").with_color(Color::Red));
-                            self.push_report(e);
-                            continue;
-                        }
-                        let num = &self.source[start..self.index];
-                        self.push(Token::new(TokenKind::FloatLiteral, self.span_from(start), num));
-                        continue;
+                    if self.current == Some('.') && self.peek(1) != Some('.') {
+                        let span = self.span_from(start);
+                        let e = ErrorReport::new(ErrorReportKind::SyntaxError, span, "Invalid Float Literal".to_string())
+                            .with_label(Label::new(self.span_at(self.index)).with_message("Second fractional indicator").with_color(Color::Red))
+                            .with_note("A float literal has exactly one decimal point.".to_string())
+                            .with_help("Remove the second `.`.".to_string());
+                        self.push_report(e);
+                        return;
                     }
-                    let num = &self.source[start..self.index];
-                    self.push(Token::new(TokenKind::IntegerLiteralDec, self.span_from(start), num));
-                },
-                '.' => match self.peek(1) {
-                    Some('0'..='9') => {
-                        self.advance();
-                        if self.lex_integer(Base::Dec, start).is_err() {
-                            continue;
+                    let _ = self.lex_float_end(start);
+                }
+                Some('.') => self.push_simple(TokenKind::DotDot, 2),
+                _ => self.push_simple(TokenKind::Period, 1)
+            }
+            '/' => match self.peek(1) {
+                Some('/') => {
+                    while let Some(char) = self.current {
+                        if char == '\n' || char == '\r' && self.peek(1) == Some('\n') {
+                            break;
                         }
-                        if let Some('.') = self.current {
-                            let span = self.span_from(start);
-                            let e = ErrorReport::new(ErrorReportKind::SyntaxError, span, "Invalid Float Literal".to_string())
-                                .with_label(Label::new(self.span_at(self.index)).with_message("Second fractional indicator").with_color(Color::Red));
+                        self.advance()
+                    }
+                    if self.keep_comments {
+                        self.push(Token::new(TokenKind::LineComment, self.span(start, self.index-1), &self.source[start..self.index]));
+                    }
+                }
+                Some('*') => {
+                    let mut depth: usize = 1;
+                    self.advance();
+                    self.advance();
+                    while depth > 0 {
+                        let Some(char) = self.current else {
+                            let span = self.trimmed_span_from(start);
+                            let e = ErrorReport::new(ErrorReportKind::SyntaxError, span, "Unterminated Multi-Line Comment".to_string())
+                                .with_label(Label::new(self.span(start, start+1)).with_message("Comment started here").with_color(Color::Red));
                             self.push_report(e);
-                            continue;
+                            break;
+                        };
+                        match (char, self.peek(1)) {
+                            ('/', Some('*')) => {
+                                self.advance();
+                                self.advance();
+                                depth += 1;
+                            }
+                            ('*', Some('/')) => {
+                                self.advance();
+                                self.advance();
+                                depth -= 1;
+                            },
+                            _ => self.advance()
                         }
-                        self.push(Token::new(TokenKind::FloatLiteral, self.span_from(start), &self.source[start..self.index]));
                     }
-                    _ => self.push_simple(TokenKind::Period, 1)
+                    if depth == 0 && self.keep_comments {
+                        self.push(Token::new(TokenKind::BlockComment, self.span(start, self.index-1), &self.source[start..self.index]));
+                    }
                 }
-                '/' => match self.peek(1) {
-                    Some('/') => {
-                        while let Some(char) = self.current {
-                            if char == '\n' {
-                                break;
+                Some('=') => self.push_simple(TokenKind::SlashEquals, 2),
+                _ => self.push_simple(TokenKind::Slash, 1)
+            }
+            '+' => match self.peek(1) {
+                Some('=') => self.push_simple(TokenKind::PlusEquals, 2),
+                _ => self.push_simple(TokenKind::Plus, 1)
+            }
+            '-' => match self.peek(1) {
+                Some('=') => self.push_simple(TokenKind::MinusEquals, 2),
+                _ => self.push_simple(TokenKind::Minus, 1)
+            }
+            '*' => match self.peek(1) {
+                Some('=') => self.push_simple(TokenKind::StarEquals, 2),
+                _ => self.push_simple(TokenKind::Star, 1)
+            }
+            '"' if self.source[self.index..].starts_with(TRIPLE_QUOTE) => self.lex_triple_string(start),
+            '"' => {
+                self.advance();
+                loop {
+                    match self.current {
+                        None => {
+                            let e = ErrorReport::new(ErrorReportKind::SyntaxError, self.span_at(start), "Unterminated String Literal".to_string())
+                                .with_label(Label::new(self.span_at(start)).with_message("String starts here").with_color(Color::Red))
+                                .with_note("Add a closing `\"` to end the string.".to_string());
+                            self.push_report(e);
+                            break;
+                        }
+                        Some('"') => {
+                            self.advance();
+                            self.push(Token::new(TokenKind::StringLiteral, self.span(start, self.index-1), &self.source[start..self.index]));
+                            break;
+                        }
+                        Some('\\') => {
+                            let escape_start = self.index;
+                            self.advance();
+                            match self.current {
+                                Some('n' | 't' | 'r' | '0' | '\\' | '"' | '$') => self.advance(),
+                                Some(c) => {
+                                    let span = self.span(escape_start, self.index + c.len_utf8() - 1);
+                                    let e = ErrorReport::new(ErrorReportKind::SyntaxError, span.clone(), "Invalid Escape Sequence".to_string())
+                                        .with_label(Label::new(span).with_message(format!("Unknown escape `\\{}`", c)).with_color(Color::Red));
+                                    self.push_report(e);
+                                    self.advance();
+                                }
+                                None => {}
                             }
-                            self.advance()
                         }
-                    }
-                    Some('*') => {
-                        let mut depth: usize = 1;
-                        self.advance();
-                        while depth > 0 {
-                            let Some(char) = self.current else {
-                                let span = self.span_from(start);
-                                let e = ErrorReport::new(ErrorReportKind::SyntaxError, span, "Unterminated Multi-Line Comment".to_string())
-                                    .with_label(Label::new(self.span(start, start+2)).with_message("Comment started here").with_color(Color::Red));
+                        Some('$') if self.peek(1) == Some('{') => match interpolation_end(&self.source[self.index+2..]) {
+                            Some(end) => self.advance_bytes(end + 3),
+                            None => {
+                                let e = ErrorReport::new(ErrorReportKind::SyntaxError, self.span_at(start), "Unterminated Interpolation".to_string())
+                                    .with_label(Label::new(self.span(self.index, self.index+1)).with_message("Interpolation starts here").with_color(Color::Red))
+                                    .with_note("Add a closing `}` to end the interpolation.".to_string());
                                 self.push_report(e);
-                                break;
-                            };
-                            match char {
-                                '/' if self.peek(1) == Some('*') => depth += 1,
-                                '*' if self.peek(1) == Some('/') => {
+                                while self.current.is_some() {
                                     self.advance();
-                                    self.advance();
-                                    depth -= 1;
-                                },
-                                _ => {}
+                                }
+                                break;
                             }
-                            self.advance();
                         }
+                        Some(_) => self.advance()
                     }
-                    _ => self.push_simple(TokenKind::Slash, 1)
-                }
-                ';' => self.push_simple(TokenKind::SemiColon, 1),
-                '=' => self.push_simple(TokenKind::Equals, 1),
-                _ => {
-                    let span = self.span_at(self.index);
-                    let e = ErrorReport::new(ErrorReportKind::UnexpectedCharacter, span.clone(), format!("{:?}", self.current.expect("Lexer matched on Some but found None")))
-                        .with_label(Label::new(span).with_message("Not a valid character.").with_color(Color::Red));
-                    self.push_report(e);
+                }
+            }
+            ';' => self.push_simple(TokenKind::SemiColon, 1),
+            ':' => self.push_simple(TokenKind::Colon, 1),
+            '=' => match self.peek(1) {
+                Some('=') => self.push_simple(TokenKind::EqualsEquals, 2),
+                Some('>') => self.push_simple(TokenKind::FatArrow, 2),
+                _ => self.push_simple(TokenKind::Equals, 1)
+            }
+            '!' if self.peek(1) == Some('=') => self.push_simple(TokenKind::BangEquals, 2),
+            '&' if self.peek(1) == Some('&') => self.push_simple(TokenKind::AmpAmp, 2),
+            '|' if self.peek(1) == Some('|') => self.push_simple(TokenKind::PipePipe, 2),
+            '<' => match self.peek(1) {
+                Some('=') => self.push_simple(TokenKind::LessEquals, 2),
+                _ => self.push_simple(TokenKind::Less, 1)
+            }
+            '>' => match self.peek(1) {
+                Some('=') => self.push_simple(TokenKind::GreaterEquals, 2),
+                _ => self.push_simple(TokenKind::Greater, 1)
+            }
+            ',' => self.push_simple(TokenKind::Comma, 1),
+            '(' => self.push_simple(TokenKind::LeftParen, 1),
+            ')' => self.push_simple(TokenKind::RightParen, 1),
+            '{' => self.push_simple(TokenKind::LeftBrace, 1),
+            '}' => self.push_simple(TokenKind::RightBrace, 1),
+            '[' => self.push_simple(TokenKind::LeftBracket, 1),
+            ']' => self.push_simple(TokenKind::RightBracket, 1),
+            _ => {
+                // Report a run of garbage once rather than once per char
+                let mut count: usize = 0;
+                while self.current.is_some() && (count == 0 || !self.at_token_start()) {
                     self.advance();
+                    count += 1;
                 }
-            };
+                let span = self.span_from(start);
+                let e = if count == 1 {
+                    ErrorReport::new(ErrorReportKind::UnexpectedCharacter, span.clone(), format!("{:?}", char))
+                        .with_label(Label::new(span).with_message("Not a valid character.").with_color(Color::Red))
+                } else {
+                    ErrorReport::new(ErrorReportKind::UnexpectedCharacter, span.clone(), format!("{} unexpected characters", count))
+                        .with_label(Label::new(span).with_message("Not valid characters.").with_color(Color::Red))
+                };
+                self.push_report(e);
+            }
+        };
+    }
+
+    /// Record how the line starting at the current char is indented, advising if it differs from the
+    /// first indented line. Blank lines don't count.
+    fn check_indentation(&mut self) {
+        let rest = &self.source[self.index..];
+        let length = rest.bytes().take_while(|byte| matches!(byte, b' ' | b'\t')).count();
+        if length == 0 || matches!(rest.as_bytes().get(length), None | Some(b'\n' | b'\r')) {
+            return;
+        }
+        let style = if rest.starts_with('\t') { '\t' } else { ' ' };
+        let span = self.span(self.index, self.index + length - 1);
+        let Some((first_style, first_span)) = &self.indentation else {
+            self.indentation = Some((style, span));
+            return;
+        };
+        if style == *first_style || self.reported_mixed_indentation {
+            return;
         }
-        self.push_simple(TokenKind::EOF, 0);
+        let name = |style: char| if style == '\t' { "tabs" } else { "spaces" };
+        let e = ErrorReport::new(ErrorReportKind::IndentationStyle, span.clone(), "Mixed Indentation".to_string())
+            .with_severity(Severity::Advice)
+            .with_label(Label::new(span).with_message(format!("Indented with {}", name(style))).with_color(Color::BrightBlue))
+            .with_secondary_label(first_span.clone(), format!("The first indented line uses {}", name(*first_style)))
+            .with_note("Indenting every line the same way keeps the file readable in any editor.".to_string());
+        self.reported_mixed_indentation = true;
+        self.push_report(e);
     }
 
-    fn lex_integer(&mut self, base: Base, start: usize) -> ResultErrorless<()> {
-        // use slices instead
-        while let Some(char) = self.current {
-            match (base, char.to_ascii_lowercase()) {
-                (Base::Bin, '0'..='1')
-                | (Base::Oct, '0'..='7')
-                | (Base::Dec, '0'..='9')
-                | (Base::Hex, '0'..='9' | 'a'..='f') => {
+    /// Whether the current char could begin a token, or is whitespace, so a run of unexpected
+    /// characters ends before it.
+    fn at_token_start(&self) -> bool {
+        match self.current {
+            Some(c) if c.is_whitespace() || c.is_ascii_alphanumeric() || "_\".+-*/;:=,(){}[]<>".contains(c) => true,
+            Some('!') => self.peek(1) == Some('='),
+            Some('&') => self.peek(1) == Some('&'),
+            Some('|') => self.peek(1) == Some('|'),
+            _ => false
+        }
+    }
+
+    /// Lex `"""..."""`, which may span several lines. Like a raw string nothing inside is escaped,
+    /// so it ends at the first `"""`.
+    fn lex_triple_string(&mut self, start: usize) {
+        self.advance_bytes(TRIPLE_QUOTE.len());
+        match self.source[self.index..].find(TRIPLE_QUOTE) {
+            Some(end) => {
+                self.advance_bytes(end);
+                self.advance_bytes(TRIPLE_QUOTE.len());
+                self.push(Token::new(TokenKind::StringLiteral, self.span_from(start), &self.source[start..self.index]));
+            }
+            None => {
+                let e = ErrorReport::new(ErrorReportKind::SyntaxError, self.span_at(start), "Unterminated String Literal".to_string())
+                    .with_label(Label::new(self.span(start, start + TRIPLE_QUOTE.len() - 1)).with_message("Triple-quoted string starts here").with_color(Color::Red))
+                    .with_note(format!("Add a closing `{}` to end the string.", TRIPLE_QUOTE));
+                self.push_report(e);
+                while self.current.is_some() {
                     self.advance();
-                },
-                (_, '0'..='9' | 'a'..='z') => {
-                    let span = self.span_from(start);
-                    let e = ErrorReport::new(ErrorReportKind::SyntaxError, span.clone(), "Invalid Integer Literal".to_string())
-                        .with_label(Label::new(span).with_message(format!("{} integer literal", base.to_string())).with_color(Color::BrightBlue).with_order(1))
-                        .with_label(Label::new(self.span_at(self.index)).with_message("Invalid character").with_color(Color::Red));
-                    self.push_report(e);
-                    // self.advance(); // Is doing this a worse approach to handling this error?
-                    return Err(())
-                },
-                (_, '_') => self.advance(),
-                _ => break
+                }
             }
-        };
+        }
+    }
+
+    /// Whether the current char is the `r` of `r"` or `r#"`, rather than the start of an identifier.
+    fn at_raw_string(&self) -> bool {
+        let hashes = self.source[self.index+1..].chars().take_while(|c| *c == '#').count();
+        self.peek(hashes + 1) == Some('"')
+    }
+
+    /// Lex `r"..."`, where nothing is escaped. Any number of `#`s may follow the `r`, and the
+    /// string then only ends at a `"` followed by as many `#`s, so it can contain plain quotes.
+    fn lex_raw_string(&mut self, start: usize) {
+        self.advance();
+        let mut hashes = 0;
+        while self.current == Some('#') {
+            hashes += 1;
+            self.advance();
+        }
+        self.advance();
+        let terminator = format!("\"{}", "#".repeat(hashes));
+        loop {
+            if self.source[self.index..].starts_with(&terminator) {
+                for _ in 0..terminator.len() {
+                    self.advance();
+                }
+                self.push(Token::new(TokenKind::StringLiteral, self.span(start, self.index-1), &self.source[start..self.index]));
+                return;
+            }
+            if self.current.is_none() {
+                let e = ErrorReport::new(ErrorReportKind::SyntaxError, self.span_at(start), "Unterminated String Literal".to_string())
+                    .with_label(Label::new(self.span_at(start)).with_message("Raw string starts here").with_color(Color::Red))
+                    .with_note(format!("Add a closing `{}` to end the string.", terminator));
+                self.push_report(e);
+                return;
+            }
+            self.advance();
+        }
+    }
+
+    /// Whether the current char starts an exponent like `e5`, `E-3` or `e+10`.
+    fn at_exponent(&self) -> bool {
+        if !matches!(self.current, Some('e' | 'E')) {
+            return false;
+        }
+        match self.peek(1) {
+            Some('+' | '-') => self.peek(2).is_some_and(|c| c.is_ascii_digit()),
+            next => next.is_some_and(|c| c.is_ascii_digit())
+        }
+    }
+
+    /// Lex the optional exponent and type suffix of a float whose digits up to here have been consumed,
+    /// then push it. The exponent is taken first, so the `f64` of `1e3f64` is the suffix.
+    /// Literals too large for a float are reported rather than silently becoming infinity, while
+    /// ones too small to represent, like `1e-400`, round to zero as they would in any float arithmetic.
+    fn lex_float_end(&mut self, start: usize) -> ResultErrorless<()> {
+        if self.at_exponent() {
+            self.advance();
+            if matches!(self.current, Some('+' | '-')) {
+                self.advance();
+            }
+            self.lex_integer(Base::Dec, start)?;
+            if self.at_exponent() {
+                let second = self.index;
+                self.advance();
+                if matches!(self.current, Some('+' | '-')) {
+                    self.advance();
+                }
+                self.lex_integer(Base::Dec, start)?;
+                let e = ErrorReport::new(ErrorReportKind::SyntaxError, self.span_from(start), "Invalid Float Literal".to_string())
+                    .with_label(Label::new(self.span(second, self.index - 1)).with_message("Second exponent").with_color(Color::Red))
+                    .with_note("A float literal has at most one exponent.".to_string())
+                    .with_help("Remove the second exponent, or fold it into the first.".to_string());
+                self.push_report(e);
+                return Err(());
+            }
+        }
+        if let Some(length) = self.integer_suffix_length() {
+            let e = ErrorReport::new(ErrorReportKind::SyntaxError, self.span_from(start), "Integer Suffix On Float Literal".to_string())
+                .with_label(Label::new(self.span(self.index, self.index + length - 1)).with_message("Integer type suffix").with_color(Color::Red))
+                .with_note("A literal with a decimal point or exponent is a float, so it can't take an integer suffix.".to_string())
+                .with_help(format!("Use a float suffix, {}, or write the value as a plain integer.", FLOAT_SUFFIXES.map(|suffix| format!("`{}`", suffix)).join(" or ")));
+            self.advance_bytes(length);
+            self.push_report(e);
+            return Err(());
+        }
+        let value = self.source[start..self.index].replace('_', "").parse::<f64>().expect("Lexed an invalid float literal.");
+        if let Some(length) = self.float_suffix_length() {
+            self.advance_bytes(length);
+        }
+        let num = &self.source[start..self.index];
+        if value.is_infinite() {
+            let span = self.span(start, self.index-1);
+            let e = ErrorReport::new(ErrorReportKind::SyntaxError, span.clone(), "Float Literal Out Of Range".to_string())
+                .with_label(Label::new(span).with_message("Too large to be represented as a float").with_color(Color::Red))
+                .with_note(format!("The largest float is {:e}.", f64::MAX));
+            self.push_report(e);
+            return Err(());
+        }
+        self.push(Token::new(TokenKind::FloatLiteral, self.span_from(start), num));
         Ok(())
     }
+
+    /// The length of the type suffix at the current char, like the `u8` of `255u8`, if there is one.
+    fn integer_suffix_length(&self) -> Option<usize> {
+        let rest = &self.source[self.index..];
+        let length = rest.bytes().take_while(|byte| byte.is_ascii_alphanumeric() || *byte == b'_').count();
+        integer_suffix_range(&rest[..length]).map(|_| length)
+    }
+
+    /// The length of the float type suffix at the current char, like the `f32` of `1.5f32`.
+    fn float_suffix_length(&self) -> Option<usize> {
+        let rest = &self.source[self.index..];
+        let length = rest.bytes().take_while(|byte| byte.is_ascii_alphanumeric() || *byte == b'_').count();
+        FLOAT_SUFFIXES.contains(&&rest[..length]).then_some(length)
+    }
+
+    /// Consume the type suffix of an integer literal, if it has one.
+    fn lex_integer_suffix(&mut self) {
+        if let Some(length) = self.integer_suffix_length() {
+            self.advance_bytes(length);
+        }
+    }
+
+    fn lex_integer(&mut self, base: Base, start: usize) -> ResultErrorless<()> {
+        // Every digit and separator is ASCII, so scan the bytes and skip them all at once
+        let length = self.source.as_bytes()[self.index..].iter()
+            .take_while(|byte| base.is_digit(**byte) || **byte == b'_')
+            .count();
+        self.advance_bytes(length);
+        match self.current.map(|c| c.to_ascii_lowercase()) {
+            // `1e5` is the float 100000.0, not an invalid integer
+            Some('e') if matches!(base, Base::Dec) && self.at_exponent() => Ok(()),
+            // `255u8`, the suffix is consumed by whoever pushes the token
+            Some('i' | 'u') if self.integer_suffix_length().is_some() => Ok(()),
+            Some('f') if matches!(base, Base::Dec) && self.float_suffix_length().is_some() => Ok(()),
+            Some('0'..='9' | 'a'..='z') => {
+                let span = self.span_from(start);
+                let e = ErrorReport::new(ErrorReportKind::SyntaxError, span.clone(), "Invalid Integer Literal".to_string())
+                    .with_label(Label::new(span).with_message(format!("{} integer literal", base.to_string())).with_color(Color::BrightBlue).with_order(1))
+                    .with_label(Label::new(self.span_at(self.index)).with_message("Invalid character").with_color(Color::Red))
+                    .with_note(format!("{} literals may only contain the digits {}, separated by `_`.", base.to_string(), base.valid_digits()));
+                self.push_report(e);
+                // self.advance(); // Is doing this a worse approach to handling this error?
+                Err(())
+            }
+            _ => Ok(())
+        }
+    }
+}
+
+/// Lexes lazily, yielding each token as soon as it is found instead of collecting them all first,
+/// ending with EOF. Reports still go to the lexer's report list as they are made.
+pub struct TokenStream<'a> {
+    lexer: Lexer<'a>
+}
+
+impl<'a> Iterator for TokenStream<'a> {
+    type Item = Token<'a>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        // A step pushes at most a couple of tokens, so taking from the front stays cheap
+        while self.lexer.tokens.is_empty() {
+            if self.lexer.finished {
+                return None;
+            }
+            self.lexer.lex_step();
+        }
+        Some(self.lexer.tokens.remove(0))
+    }
+}
+
+impl<'a> IntoIterator for Lexer<'a> {
+    type Item = Token<'a>;
+    type IntoIter = TokenStream<'a>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        TokenStream { lexer: self }
+    }
+}
+
+/// The values an integer literal with the type suffix `suffix` may take, or `None` if it isn't one.
+pub fn integer_suffix_range(suffix: &str) -> Option<RangeInclusive<i128>> {
+    let range = match suffix {
+        "i8" => i8::MIN as i128..=i8::MAX as i128,
+        "i16" => i16::MIN as i128..=i16::MAX as i128,
+        "i32" => i32::MIN as i128..=i32::MAX as i128,
+        "i64" => i64::MIN as i128..=i64::MAX as i128,
+        "isize" => isize::MIN as i128..=isize::MAX as i128,
+        "u8" => 0..=u8::MAX as i128,
+        "u16" => 0..=u16::MAX as i128,
+        "u32" => 0..=u32::MAX as i128,
+        "u64" => 0..=u64::MAX as i128,
+        "usize" => 0..=usize::MAX as i128,
+        _ => return None
+    };
+    Some(range)
+}
+
+/// The type suffixes a float literal may end with.
+pub const FLOAT_SUFFIXES: [&str; 2] = ["f32", "f64"];
+
+/// Opens and closes a multi-line string.
+const TRIPLE_QUOTE: &str = "\"\"\"";
+
+/// Keywords that are followed by an identifier or expression, and so are worth suggesting for a typo.
+const SUGGESTED_KEYWORDS: [&str; 5] = ["let", "fn", "if", "while", "return"];
+
+/// Levenshtein distance between two strings, counted in chars.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+    for (i, a_char) in a.chars().enumerate() {
+        let mut diagonal = row[0];
+        row[0] = i + 1;
+        for (j, b_char) in b.iter().enumerate() {
+            let substitution = diagonal + usize::from(a_char != *b_char);
+            diagonal = row[j + 1];
+            row[j + 1] = substitution.min(row[j] + 1).min(diagonal + 1);
+        }
+    }
+    row[b.len()]
+}
+
+/// Given the text just after a `${`, the byte offset of the `}` closing it. Braces and strings
+/// inside the interpolation are skipped over, so neither ends it early.
+pub fn interpolation_end(text: &str) -> Option<usize> {
+    let mut depth: usize = 0;
+    let mut index = 0;
+    while let Some(char) = text[index..].chars().next() {
+        match char {
+            '{' => depth += 1,
+            '}' if depth == 0 => return Some(index),
+            '}' => depth -= 1,
+            '"' => index += string_end(&text[index+1..])? + 1,
+            _ => {}
+        }
+        index += char.len_utf8();
+    }
+    None
+}
+
+/// Given the text just after an opening `"`, the byte offset of the closing one.
+fn string_end(text: &str) -> Option<usize> {
+    let mut index = 0;
+    while let Some(char) = text[index..].chars().next() {
+        match char {
+            '"' => return Some(index),
+            '\\' => index += text[index+1..].chars().next()?.len_utf8(),
+            '$' if text[index+1..].starts_with('{') => index += interpolation_end(&text[index+2..])? + 2,
+            _ => {}
+        }
+        index += char.len_utf8();
+    }
+    None
+}
+
+/// The value of a string literal token, with its quotes removed and escapes resolved.
+/// Raw strings are the ones starting with `r`, and like triple-quoted strings are taken as written.
+pub fn string_value(text: &str) -> String {
+    if let Some(body) = text.strip_prefix(TRIPLE_QUOTE) {
+        return body[..body.len()-TRIPLE_QUOTE.len()].to_string();
+    }
+    match text.strip_prefix('r') {
+        Some(raw) => {
+            let hashes = raw.len() - raw.trim_start_matches('#').len();
+            raw[hashes+1..raw.len()-hashes-1].to_string()
+        }
+        None => unescape(&text[1..text.len()-1])
+    }
+}
+
+/// A string literal for `text`, using only the escapes the lexer understands. Lexing it and taking
+/// its `string_value` gives back `text`.
+pub fn quote(text: &str) -> String {
+    let mut out = String::with_capacity(text.len() + 2);
+    out.push('"');
+    escape_into(&mut out, text);
+    out.push('"');
+    out
 }
 
-#[derive(Debug, Clone, Copy)]
-enum Base {
-    Bin,
-    Oct,
-    Dec,
-    Hex,
+/// Append `text` as the body of a string literal, escaping anything that would end it early or
+/// start an interpolation.
+pub fn escape_into(out: &mut String, text: &str) {
+    let mut chars = text.chars().peekable();
+    while let Some(char) = chars.next() {
+        match char {
+            '\n' => out.push_str("\\n"),
+            '\t' => out.push_str("\\t"),
+            '\r' => out.push_str("\\r"),
+            '\0' => out.push_str("\\0"),
+            '\\' => out.push_str("\\\\"),
+            '"' => out.push_str("\\\""),
+            '$' if chars.peek() == Some(&'{') => out.push_str("\\$"),
+            char => out.push(char)
+        }
+    }
 }
 
-impl Base {
-    pub fn to_string(self) -> &'static str {
-        match self {
-            Base::Bin => "Binary",
-            Base::Oct => "Octal",
-            Base::Dec => "Decimal",
-            Base::Hex => "Hexadecimal"
+/// Whether a string literal token is triple-quoted.
+pub fn is_triple_quoted(text: &str) -> bool {
+    text.starts_with(TRIPLE_QUOTE)
+}
+
+/// Resolve the escape sequences in the body of a string literal the lexer has already validated.
+pub fn unescape(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut chars = text.chars();
+    while let Some(char) = chars.next() {
+        if char != '\\' {
+            out.push(char);
+            continue;
+        }
+        match chars.next() {
+            Some('n') => out.push('\n'),
+            Some('t') => out.push('\t'),
+            Some('r') => out.push('\r'),
+            Some('0') => out.push('\0'),
+            Some(char) => out.push(char),
+            None => out.push('\\')
         }
     }
+    out
 }
 
-impl From<Base> for TokenKind {
-    fn from(value: Base) -> Self {
-        match value {
-            Base::Bin => Self::IntegerLiteralBin,
-            Base::Oct => Self::IntegerLiteralOct,
-            Base::Dec => Self::IntegerLiteralDec,
-            Base::Hex => Self::IntegerLiteralHex,
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lex(source: &str) -> (Vec<Token<'_>>, Vec<ErrorReport>) {
+        Lexer::lex_str(Arc::from("test"), source)
+    }
+
+    /// The kinds of every token in `source`, which must lex without any report.
+    fn kinds(source: &str) -> Vec<TokenKind> {
+        let (tokens, reports) = lex(source);
+        assert!(reports.is_empty(), "{:?}", reports.iter().map(ErrorReport::title).collect::<Vec<_>>());
+        tokens.into_iter().map(|token| token.kind).collect()
+    }
+
+    #[test]
+    fn true_and_false_are_keywords() {
+        assert_eq!(kinds("true false"), [TokenKind::True, TokenKind::False, TokenKind::EOF]);
+    }
+
+    #[test]
+    fn keywords_only_match_whole_words() {
+        assert_eq!(kinds("trueish falsey"), [TokenKind::Identifier, TokenKind::Identifier, TokenKind::EOF]);
+    }
+
+    #[test]
+    fn over_long_identifier_is_reported() {
+        let source = "a".repeat(20);
+        let reports = Rc::new(RefCell::new(Vec::new()));
+        let mut lexer = Lexer::new(Arc::from("test"), &source, reports.clone()).with_max_token_length(16);
+        lexer.lex_tokens();
+        assert!(lexer.had_error);
+        assert_eq!(lexer.tokens.iter().map(|token| &token.kind).collect::<Vec<_>>(), [&TokenKind::EOF]);
+        let reports = reports.take();
+        assert_eq!(reports[0].title(), "SyntaxError[E0001]: Token Too Long");
+        assert_eq!(reports[0].note(), Some("Identifiers and number literals may be at most 16 characters long."));
+    }
+
+    #[test]
+    fn identifier_at_the_limit_is_fine() {
+        let source = "a".repeat(16);
+        let reports = Rc::new(RefCell::new(Vec::new()));
+        let mut lexer = Lexer::new(Arc::from("test"), &source, reports.clone()).with_max_token_length(16);
+        lexer.lex_tokens();
+        assert!(reports.take().is_empty());
+    }
+
+    #[test]
+    fn leading_shebang_is_skipped() {
+        assert_eq!(
+            kinds("#!/usr/bin/env newlang\nlet x = 1;"),
+            [TokenKind::Let, TokenKind::Identifier, TokenKind::Equals, TokenKind::IntegerLiteral(Base::Dec), TokenKind::SemiColon, TokenKind::EOF]
+        );
+    }
+
+    #[test]
+    fn shebang_after_the_start_is_an_error() {
+        let (_, reports) = lex("let x = 1;\n#!/usr/bin/env newlang");
+        assert!(!reports.is_empty());
+    }
+
+    #[test]
+    fn comments_are_kept_when_asked() {
+        let source = "// line\nlet /* block */ x";
+        let reports = Rc::new(RefCell::new(Vec::new()));
+        let mut lexer = Lexer::new(Arc::from("test"), source, reports.clone()).with_keep_comments(true);
+        lexer.lex_tokens();
+        assert!(reports.take().is_empty());
+        let comments: Vec<_> = lexer.tokens.iter()
+            .filter(|token| token.kind.is_comment())
+            .map(|token| (token.kind.clone(), token.span.start, token.span.end, token.text))
+            .collect();
+        assert_eq!(comments, [
+            (TokenKind::LineComment, 0, 6, "// line"),
+            (TokenKind::BlockComment, 12, 22, "/* block */")
+        ]);
+    }
+
+    #[test]
+    fn comments_are_discarded_by_default() {
+        assert_eq!(kinds("// line\nlet /* block */ x"), [TokenKind::Let, TokenKind::Identifier, TokenKind::EOF]);
+    }
+
+    #[test]
+    fn edit_distance_counts_single_char_edits() {
+        assert_eq!(edit_distance("let", "let"), 0);
+        assert_eq!(edit_distance("lett", "let"), 1);
+        assert_eq!(edit_distance("lte", "let"), 2);
+        assert_eq!(edit_distance("", "fn"), 2);
+        assert_eq!(edit_distance("kitten", "sitting"), 3);
+    }
+
+    #[test]
+    fn misspelled_keyword_gets_a_suggestion() {
+        let (_, reports) = lex("lett x = 1;");
+        assert_eq!(reports.len(), 1);
+        assert_eq!(reports[0].title(), "DidYouMean[E0004]: did you mean `let`?");
+        assert_eq!((reports[0].span().start, reports[0].span().end), (0, 3));
+        assert!(!reports[0].is_error());
+    }
+
+    #[test]
+    fn near_miss_in_an_expression_is_not_a_typo() {
+        assert!(lex("lett + x").1.is_empty());
+    }
+
+    #[test]
+    fn unterminated_string_points_at_the_opening_quote() {
+        let (_, reports) = lex("let s = \"abc");
+        assert_eq!(reports.len(), 1);
+        assert_eq!(reports[0].title(), "SyntaxError[E0001]: Unterminated String Literal");
+        let label = reports[0].labels()[0].span();
+        assert_eq!((label.start, label.end), (8, 8));
+        assert_eq!(reports[0].note(), Some("Add a closing `\"` to end the string."));
+    }
+
+    #[test]
+    fn crlf_lexes_like_lf() {
+        let lf = "let x = 1; // one\nlet y = 2;\n\nx";
+        let crlf = lf.replace('\n', "\r\n");
+        let (lf_tokens, lf_reports) = lex(lf);
+        let (crlf_tokens, crlf_reports) = lex(&crlf);
+        assert!(lf_reports.is_empty() && crlf_reports.is_empty());
+        let (lf_lines, crlf_lines) = (crate::span::LineIndex::new(lf), crate::span::LineIndex::new(&crlf));
+        let lf_info: Vec<_> = lf_tokens.iter().map(|token| (token.kind.clone(), token.newline_before, lf_lines.line_col(token.span.start))).collect();
+        let crlf_info: Vec<_> = crlf_tokens.iter().map(|token| (token.kind.clone(), token.newline_before, crlf_lines.line_col(token.span.start))).collect();
+        assert_eq!(lf_info, crlf_info);
+    }
+
+    #[test]
+    fn hex_and_binary_floats_are_rejected_as_one_literal() {
+        for source in ["0x1.5", "0b1.1"] {
+            let (tokens, reports) = lex(source);
+            assert_eq!(reports.len(), 1, "{}", source);
+            assert_eq!(reports[0].title(), "SyntaxError[E0001]: Unsupported Float Literal");
+            assert_eq!((reports[0].span().start, reports[0].span().end), (0, 4));
+            assert_eq!(tokens.iter().map(|token| &token.kind).collect::<Vec<_>>(), [&TokenKind::EOF]);
         }
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn hex_integer_before_a_range_is_fine() {
+        assert_eq!(kinds("0x1..2"), [TokenKind::IntegerLiteral(Base::Hex), TokenKind::DotDot, TokenKind::IntegerLiteral(Base::Dec), TokenKind::EOF]);
+    }
+
+    #[test]
+    fn invalid_digit_note_lists_the_valid_ones() {
+        let (_, reports) = lex("0b102");
+        assert_eq!(reports[0].title(), "SyntaxError[E0001]: Invalid Integer Literal");
+        assert_eq!(reports[0].note(), Some("Binary literals may only contain the digits 0 and 1, separated by `_`."));
+        let (_, reports) = lex("0o78");
+        assert_eq!(reports[0].note(), Some("Octal literals may only contain the digits 0 through 7, separated by `_`."));
+    }
+
+    #[test]
+    fn separator_after_a_base_prefix_is_rejected() {
+        let (tokens, reports) = lex("0x_FF");
+        assert_eq!(reports.len(), 1);
+        assert_eq!(reports[0].title(), "SyntaxError[E0001]: Invalid Integer Literal");
+        let label = reports[0].labels()[0].span();
+        assert_eq!((label.start, label.end), (2, 2));
+        // the digits are consumed rather than becoming an identifier
+        assert_eq!(tokens.iter().map(|token| &token.kind).collect::<Vec<_>>(), [&TokenKind::EOF]);
+    }
+
+    #[test]
+    fn separator_between_prefixed_digits_is_fine() {
+        let (tokens, reports) = lex("0xFF_00");
+        assert!(reports.is_empty());
+        assert_eq!((&tokens[0].kind, tokens[0].text), (&TokenKind::IntegerLiteral(Base::Hex), "0xFF_00"));
+    }
+
+    #[test]
+    fn lex_str_returns_tokens_and_reports() {
+        let (tokens, reports) = Lexer::lex_str(Arc::from("file.nl"), "let x = \"abc");
+        assert_eq!(tokens.iter().map(|token| &token.kind).collect::<Vec<_>>(), [&TokenKind::Let, &TokenKind::Identifier, &TokenKind::Equals, &TokenKind::EOF]);
+        assert_eq!(&*tokens[0].span.filename, "file.nl");
+        assert_eq!(reports.len(), 1);
+    }
+
+    #[test]
+    fn periods_between_names_are_not_floats() {
+        assert_eq!(kinds("a.b"), [TokenKind::Identifier, TokenKind::Period, TokenKind::Identifier, TokenKind::EOF]);
+        assert_eq!(kinds("1.5"), [TokenKind::FloatLiteral, TokenKind::EOF]);
+    }
+
+    #[test]
+    fn float_bounds_are_not_ranges() {
+        assert_eq!(kinds("1.0..2.0"), [TokenKind::FloatLiteral, TokenKind::DotDot, TokenKind::FloatLiteral, TokenKind::EOF]);
+        assert_eq!(kinds("1..2"), [TokenKind::IntegerLiteral(Base::Dec), TokenKind::DotDot, TokenKind::IntegerLiteral(Base::Dec), TokenKind::EOF]);
+    }
+
+    fn lex_with(source: &str, configure: impl FnOnce(Lexer) -> Lexer) -> Vec<ErrorReport> {
+        let reports = Rc::new(RefCell::new(Vec::new()));
+        let mut lexer = configure(Lexer::new(Arc::from("test"), source, reports.clone()));
+        lexer.lex_tokens();
+        reports.take()
+    }
+
+    #[test]
+    fn tab_indentation_is_reported_once_per_line() {
+        let reports = lex_with("{\n\t\tx;\n\ty;\n}", |lexer| lexer.with_tabs_are_errors(true));
+        assert_eq!(reports.len(), 2);
+        assert_eq!(reports[0].title(), "IndentationStyle[E0009]: Tab Indentation");
+        assert_eq!((reports[0].span().start, reports[0].span().end), (2, 3));
+        assert_eq!((reports[1].span().start, reports[1].span().end), (7, 7));
+        assert!(reports.iter().all(|report| !report.is_error()));
+    }
+
+    #[test]
+    fn tabs_after_indentation_are_not_reported() {
+        assert!(lex_with("x;\t\"a\tb\";\t", |lexer| lexer.with_tabs_are_errors(true)).is_empty());
+        assert!(lex_with("\tx;", |lexer| lexer).is_empty());
+    }
+
+    #[test]
+    fn mixed_indentation_has_its_own_code() {
+        let reports = lex_with("{\n  x;\n\ty;\n}", |lexer| lexer.with_check_mixed_indentation(true));
+        assert_eq!(reports.len(), 1);
+        assert_eq!(reports[0].title(), "IndentationStyle[E0009]: Mixed Indentation");
+        assert_eq!(ErrorReportKind::from_code("E0009"), Some(ErrorReportKind::IndentationStyle));
+    }
+
+    #[test]
+    fn float_too_large_is_reported() {
+        let (tokens, reports) = lex("1e400");
+        assert_eq!(reports.len(), 1);
+        assert_eq!(reports[0].title(), "SyntaxError[E0001]: Float Literal Out Of Range");
+        assert_eq!((reports[0].span().start, reports[0].span().end), (0, 4));
+        assert_eq!(tokens.last().unwrap().kind, TokenKind::EOF);
+    }
+
+    #[test]
+    fn float_too_small_rounds_to_zero() {
+        let (tokens, reports) = lex("1e-400");
+        assert!(reports.is_empty());
+        assert_eq!(tokens[0].kind, TokenKind::FloatLiteral);
+    }
+
+    #[test]
+    fn inf_and_nan_are_plain_names() {
+        assert_eq!(kinds("inf; nan"), [TokenKind::Identifier, TokenKind::SemiColon, TokenKind::Identifier, TokenKind::EOF]);
+    }
+
+    #[test]
+    fn raw_string_keeps_backslashes() {
+        let (tokens, reports) = lex(r#"r"a\n""#);
+        assert!(reports.is_empty());
+        assert_eq!(tokens[0].kind, TokenKind::StringLiteral);
+        assert_eq!(string_value(tokens[0].text), r"a\n");
+    }
+
+    #[test]
+    fn hashes_let_a_raw_string_hold_quotes() {
+        let (tokens, reports) = lex(r###"r#"has "quotes""# x"###);
+        assert!(reports.is_empty());
+        assert_eq!(string_value(tokens[0].text), r#"has "quotes""#);
+        assert_eq!(tokens[1].kind, TokenKind::Identifier);
+    }
+
+    #[test]
+    fn unterminated_raw_string_names_its_terminator() {
+        let (_, reports) = lex(r##"r#"open" "##);
+        assert_eq!(reports[0].title(), "SyntaxError[E0001]: Unterminated String Literal");
+        assert_eq!(reports[0].note(), Some("Add a closing `\"#` to end the string."));
+    }
+
+    #[test]
+    fn r_alone_is_an_identifier() {
+        assert_eq!(kinds("r; r + 1"), [TokenKind::Identifier, TokenKind::SemiColon, TokenKind::Identifier, TokenKind::Plus, TokenKind::IntegerLiteral(Base::Dec), TokenKind::EOF]);
+    }
+
+    #[test]
+    fn long_integer_is_one_token() {
+        let digits = "9".repeat(200) + "_" + &"1".repeat(200);
+        let source = format!("{} é", digits);
+        let reports = Rc::new(RefCell::new(Vec::new()));
+        let mut lexer = Lexer::new(Arc::from("test"), &source, reports.clone()).with_max_token_length(usize::MAX);
+        lexer.lex_tokens();
+        assert_eq!(lexer.tokens[0].kind, TokenKind::IntegerLiteral(Base::Dec));
+        assert_eq!(lexer.tokens[0].text, digits);
+        assert_eq!((lexer.tokens[0].span.start, lexer.tokens[0].span.end), (0, 400));
+        // the scan leaves the lexer in step with the chars after it
+        let reports = reports.take();
+        assert_eq!(reports.iter().map(ErrorReport::title).collect::<Vec<_>>(), ["UnexpectedCharacter[E0002]: 'é'"]);
+        assert_eq!((reports[0].span().start, reports[0].span().end), (402, 403));
+    }
+
+    #[test]
+    fn digit_scan_stops_at_the_first_invalid_digit() {
+        let (tokens, reports) = lex("0b1010_2 0o17 0xFF_ff");
+        assert_eq!(reports.len(), 1);
+        assert_eq!(reports[0].labels()[1].span().start, 7);
+        let texts: Vec<&str> = tokens.iter().map(|token| token.text).collect();
+        assert_eq!(texts[texts.len()-3..], ["0o17", "0xFF_ff", ""]);
+    }
+
+    #[test]
+    fn operators_at_the_end_of_input() {
+        let (tokens, reports) = lex("x =");
+        assert!(reports.is_empty());
+        assert_eq!(tokens[1].kind, TokenKind::Equals);
+        assert_eq!((tokens[1].span.start, tokens[1].span.end), (2, 2));
+        assert_eq!(kinds("1 -"), [TokenKind::IntegerLiteral(Base::Dec), TokenKind::Minus, TokenKind::EOF]);
+    }
+
+    #[test]
+    fn push_simple_stops_at_the_end_of_the_source() {
+        let mut lexer = Lexer::new(Arc::from("test"), "=", Rc::new(RefCell::new(Vec::new())));
+        lexer.push_simple(TokenKind::EqualsEquals, 2);
+        assert_eq!(lexer.tokens[0].text, "=");
+        assert_eq!((lexer.tokens[0].span.start, lexer.tokens[0].span.end), (0, 0));
+    }
+
+    #[test]
+    fn push_simple_never_splits_a_char() {
+        let mut lexer = Lexer::new(Arc::from("test"), "-é", Rc::new(RefCell::new(Vec::new())));
+        lexer.push_simple(TokenKind::Minus, 2);
+        assert_eq!(lexer.tokens[0].text, "-");
+        assert_eq!(lexer.current, Some('é'));
+    }
+
+    #[test]
+    fn eof_is_just_past_the_last_char() {
+        let (tokens, _) = lex("");
+        assert_eq!((tokens[0].span.start, tokens[0].span.end), (0, 0));
+        let (tokens, _) = lex("x\n");
+        let eof = tokens.last().unwrap();
+        assert_eq!((eof.kind.clone(), eof.span.start, eof.span.end), (TokenKind::EOF, 2, 2));
+    }
+
+    #[test]
+    fn run_of_garbage_is_one_report() {
+        let (tokens, reports) = lex("@#$ x");
+        assert_eq!(reports.len(), 1);
+        assert_eq!(reports[0].title(), "UnexpectedCharacter[E0002]: 3 unexpected characters");
+        assert_eq!((reports[0].span().start, reports[0].span().end), (0, 2));
+        assert_eq!(tokens[0].kind, TokenKind::Identifier);
+    }
+
+    #[test]
+    fn garbage_runs_end_at_a_token() {
+        let (_, reports) = lex("@@1 ~");
+        assert_eq!(reports.len(), 2);
+        assert_eq!((reports[0].span().start, reports[0].span().end), (0, 1));
+        assert_eq!(reports[1].title(), "UnexpectedCharacter[E0002]: '~'");
+    }
+
+    #[test]
+    fn triple_string_spans_lines() {
+        let (tokens, reports) = lex("\"\"\"first\n\"second\" \\n\"\"\" x");
+        assert!(reports.is_empty());
+        assert_eq!(tokens[0].kind, TokenKind::StringLiteral);
+        assert_eq!(string_value(tokens[0].text), "first\n\"second\" \\n");
+        assert_eq!(tokens[1].kind, TokenKind::Identifier);
+    }
+
+    #[test]
+    fn unterminated_triple_string_points_at_the_opening_quotes() {
+        let (tokens, reports) = lex("x \"\"\"never\nclosed");
+        assert_eq!(reports[0].title(), "SyntaxError[E0001]: Unterminated String Literal");
+        let label = &reports[0].labels()[0];
+        assert_eq!((label.span().start, label.span().end), (2, 4));
+        assert_eq!(label.message(), Some("Triple-quoted string starts here"));
+        assert_eq!(tokens.last().unwrap().kind, TokenKind::EOF);
+    }
+
+    #[test]
+    fn leading_byte_order_mark_is_skipped() {
+        let (tokens, reports) = lex("\u{FEFF}let x = 1;");
+        assert!(reports.is_empty());
+        assert_eq!(tokens[0].kind, TokenKind::Let);
+        // spans still count the mark's three bytes
+        assert_eq!(tokens[0].span.start, 3);
+        assert_eq!(kinds("\u{FEFF}#!/usr/bin/env lox\nx")[0], TokenKind::Identifier);
+    }
+
+    #[test]
+    fn byte_order_mark_elsewhere_is_an_error() {
+        let (_, reports) = lex("x \u{FEFF}");
+        assert_eq!(reports.len(), 1);
+        assert_eq!(reports[0].title(), "UnexpectedCharacter[E0002]: '\\u{feff}'");
+    }
+
+    #[test]
+    fn exponent_comes_before_a_float_suffix() {
+        let (tokens, reports) = lex("1e3f64");
+        assert!(reports.is_empty());
+        assert_eq!(tokens[0].kind, TokenKind::FloatLiteral);
+        assert_eq!(tokens[0].text, "1e3f64");
+        assert_eq!(tokens[1].kind, TokenKind::EOF);
+    }
+
+    #[test]
+    fn integer_suffix_after_an_exponent_is_an_error() {
+        let (tokens, reports) = lex("1e3i32 x");
+        assert_eq!(reports.len(), 1);
+        assert_eq!(reports[0].title(), "SyntaxError[E0001]: Integer Suffix On Float Literal");
+        let label = &reports[0].labels()[0];
+        assert_eq!((label.span().start, label.span().end), (3, 5));
+        // the whole literal is skipped, suffix included
+        assert_eq!(tokens[0].kind, TokenKind::Identifier);
+    }
+
+    #[test]
+    fn streaming_matches_the_batch_lexer() {
+        let source = "let x = 0xFF; // note\nfn f(a) { a as float } \"${x}\" @ 1e3f64";
+        let (batch, batch_reports) = lex(source);
+        let reports = Rc::new(RefCell::new(Vec::new()));
+        let streamed: Vec<Token> = Lexer::new(Arc::from("test"), source, reports.clone()).into_iter().collect();
+        let describe = |tokens: &[Token]| tokens.iter().map(|token| (token.kind.clone(), token.span.start, token.span.end, token.text.to_string(), token.newline_before)).collect::<Vec<_>>();
+        assert_eq!(describe(&streamed), describe(&batch));
+        assert_eq!(reports.borrow().len(), batch_reports.len());
+    }
+
+    #[test]
+    fn stream_ends_after_eof() {
+        let mut stream = Lexer::new(Arc::from("test"), "x", Rc::new(RefCell::new(Vec::new()))).into_iter();
+        assert_eq!(stream.next().unwrap().kind, TokenKind::Identifier);
+        assert_eq!(stream.next().unwrap().kind, TokenKind::EOF);
+        assert!(stream.next().is_none());
+        assert!(stream.next().is_none());
+    }
+
+    #[test]
+    fn mixed_indentation_points_at_both_styles_once() {
+        let source = "{\n    a;\n\n\t\tb;\n\tc;\n  d;\n}";
+        let reports = lex_with(source, |lexer| lexer.with_check_mixed_indentation(true));
+        assert_eq!(reports.len(), 1);
+        assert_eq!(reports[0].severity, Severity::Advice);
+        let labels = reports[0].labels();
+        assert_eq!((labels[0].span().start, labels[0].span().end), (10, 11));
+        assert_eq!(labels[0].message(), Some("Indented with tabs"));
+        assert_eq!((labels[1].span().start, labels[1].span().end), (2, 5));
+        assert_eq!(labels[1].message(), Some("The first indented line uses spaces"));
+    }
+
+    #[test]
+    fn consistent_or_unchecked_indentation_is_fine() {
+        assert!(lex_with("{\n\ta;\n\t\tb;\n}", |lexer| lexer.with_check_mixed_indentation(true)).is_empty());
+        assert!(lex_with("{\n  a;\n\tb;\n}", |lexer| lexer).is_empty());
+        // whitespace-only lines don't count
+        assert!(lex_with("{\n  a;\n\t\n  b;\n}", |lexer| lexer.with_check_mixed_indentation(true)).is_empty());
+    }
+
+    #[test]
+    fn integer_tokens_carry_their_base() {
+        assert_eq!(
+            kinds("0b1 0o7 9 0xF"),
+            [Base::Bin, Base::Oct, Base::Dec, Base::Hex].map(TokenKind::IntegerLiteral).into_iter().chain([TokenKind::EOF]).collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn unterminated_comment_span_leaves_out_trailing_blanks() {
+        let (_, reports) = lex("x /* open  \n\n");
+        assert_eq!(reports[0].title(), "SyntaxError[E0001]: Unterminated Multi-Line Comment");
+        assert_eq!((reports[0].span().start, reports[0].span().end), (2, 8));
+    }
+
+    #[test]
+    fn second_exponent_is_reported() {
+        let (tokens, reports) = lex("1e5e-5;");
+        assert_eq!(reports.len(), 1);
+        assert_eq!(reports[0].title(), "SyntaxError[E0001]: Invalid Float Literal");
+        assert_eq!((reports[0].span().start, reports[0].span().end), (0, 5));
+        assert!(!tokens.iter().any(|token| token.kind == TokenKind::Identifier));
+    }
+
+    #[test]
+    fn separated_float_with_exponent_is_one_literal() {
+        assert_eq!(kinds("1_000.5e-1_0"), vec![TokenKind::FloatLiteral, TokenKind::EOF]);
+        let (_, reports) = lex("1_.5");
+        assert_eq!(reports[0].title(), "SyntaxError[E0001]: Invalid Float Literal");
+    }
+}
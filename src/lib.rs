@@ -0,0 +1,108 @@
+#![allow(clippy::result_large_err)]
+
+use std::cell::RefCell;
+use std::rc::Rc;
+use std::sync::Arc;
+
+pub mod lexer;
+pub mod span;
+pub mod token;
+pub mod error;
+pub mod parser;
+pub mod ast;
+pub mod value;
+pub mod environment;
+pub mod interpreter;
+pub mod visitor;
+pub mod fold;
+pub mod format;
+pub mod resolve;
+pub mod symbol;
+mod builtins;
+
+use crate::ast::AST;
+use crate::error::{ErrorReport, Result, SourceCache};
+use crate::fold::fold_constants;
+use crate::interpreter::Interpreter;
+use crate::lexer::Lexer;
+use crate::parser::Parser;
+use crate::resolve::Resolver;
+use crate::token::Token;
+use crate::value::Value;
+
+/// The language features this build understands, as listed by `info`.
+pub const FEATURES: &[&str] = &[
+    "variables",
+    "functions",
+    "closures",
+    "control-flow",
+    "arrays",
+    "ranges",
+    "records",
+    "casts",
+    "match",
+    "interpolation",
+    "imports"
+];
+
+/// Lex `source`, returning no tokens if any errors were reported.
+pub fn lex(filename: Arc<str>, source: &str) -> (Option<Vec<Token<'_>>>, Vec<ErrorReport>) {
+    let (tokens, reports) = Lexer::lex_str(filename, source);
+    let tokens = if reports.iter().any(ErrorReport::is_error) { None } else { Some(tokens) };
+    (tokens, reports)
+}
+
+/// Lex and parse `source`, then load its imports, returning no tree if any stage reported errors.
+/// Imports are found relative to `filename`.
+pub fn parse(filename: Arc<str>, source: &str) -> (Option<Rc<AST>>, Vec<ErrorReport>) {
+    let (tokens, reports) = lex(filename, source);
+    let Some(tokens) = tokens else {
+        return (None, reports);
+    };
+    let (ast, reports) = parse_tokens(&tokens, reports);
+    let Some(ast) = ast else {
+        return (None, reports);
+    };
+    let reports = Rc::new(RefCell::new(reports));
+    let ast = resolve(ast, &mut SourceCache::new(), reports.clone());
+    (ast, reports.take())
+}
+
+/// Parse tokens that have already been lexed, and may include comments, adding the parser's
+/// reports to the lexer's. Returns no tree if the parser reported errors. Imports are left as
+/// they are.
+pub fn parse_tokens(tokens: &[Token], mut reports: Vec<ErrorReport>) -> (Option<Rc<AST>>, Vec<ErrorReport>) {
+    let parser_reports = Rc::new(RefCell::new(Vec::new()));
+    let mut parser = Parser::new(tokens, parser_reports.clone());
+    let ast = parser.parse().filter(|_| !parser.had_error);
+    reports.extend(parser_reports.take());
+    (ast, reports)
+}
+
+/// Splice the files a parsed program imports into it, adding each file read to `sources` so
+/// reports can point into it. Returns no tree if any import failed.
+pub fn resolve(ast: Rc<AST>, sources: &mut SourceCache, reports: Rc<RefCell<Vec<ErrorReport>>>) -> Option<Rc<AST>> {
+    let mut resolver = Resolver::new(sources, reports);
+    let ast = resolver.resolve(ast);
+    (!resolver.had_error).then_some(ast)
+}
+
+/// Fold the constants of a resolved program and run it, returning the value of its last statement.
+pub fn run(ast: Rc<AST>, interpreter: &mut Interpreter) -> Result<Value> {
+    interpreter.run(&fold_constants(ast))
+}
+
+/// Run `source` to completion, returning the value of its last statement.
+pub fn eval(filename: Arc<str>, source: &str) -> (Option<Value>, Vec<ErrorReport>) {
+    let (ast, mut reports) = parse(filename, source);
+    let Some(ast) = ast else {
+        return (None, reports);
+    };
+    match run(ast, &mut Interpreter::new()) {
+        Ok(value) => (Some(value), reports),
+        Err(error) => {
+            reports.push(error);
+            (None, reports)
+        }
+    }
+}
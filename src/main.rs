@@ -4,103 +4,381 @@ extern crate core;
 use std::cell::RefCell;
 use std::rc::Rc;
 use std::fs::File;
-use std::io::Read;
+use std::io::{IsTerminal, Read};
 use std::ops::Deref;
 use std::process::exit;
 use std::sync::{Arc};
-use ariadne::{Report, Source};
-use clap::Parser as ArgParser;
+use std::time::{Duration, Instant};
+use ariadne::{Config, IndexType, Report, Source};
+use clap::{Parser as ArgParser, Subcommand, ValueEnum};
 
-pub mod lexer;
-pub mod span;
-pub mod token;
-mod error;
-mod parser;
-mod ast;
+use lox::lexer::Lexer;
+use lox::parser::Parser;
+use lox::interpreter::Interpreter;
+use lox::value::Value;
+use lox::span::LineIndex;
+use lox::visitor::{walk, DeclarationCollector};
+use lox::error::{ErrorLevel, ErrorReport, ErrorReportKind, Severity, SourceCache};
 
+const VERSION: &str = "0.0.1";
+/// Every call the interpreter makes takes several native frames, and far more in a debug build,
+/// so the main thread's stack would overflow well before `--max-call-depth` is reached.
+const STACK_SIZE: usize = 256 * 1024 * 1024;
 
-use crate::lexer::Lexer;
-use crate::parser::Parser;
-use crate::error::{ErrorLevel, ErrorReport};
 
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq)]
+enum Emit {
+    Tokens,
+    Ast,
+    Run
+}
 
 #[derive(ArgParser, Debug)]
-#[command(version, about = "Haven's interpreter", version="0.0.1")]
+#[command(about = "Haven's interpreter", version = VERSION, args_conflicts_with_subcommands = true)]
 struct Args {
+    #[command(subcommand)]
+    command: Option<Command>,
     #[arg(default_value = None)]
     filename: Option<String>,
     #[arg(short, long)]
     debug: bool,
     #[arg(long, value_enum, default_value_t=ErrorLevel::Normal)]
-    error_level: ErrorLevel
+    error_level: ErrorLevel,
+    #[arg(long, value_enum, default_value_t=Emit::Run)]
+    emit: Emit,
+    /// Only lex and parse, reporting every problem found and printing nothing on success.
+    #[arg(long, conflicts_with = "emit")]
+    check: bool,
+    /// Disable colors in diagnostics, also done when `NO_COLOR` is set.
+    #[arg(long)]
+    no_color: bool,
+    /// How many columns a tab takes up when rendering diagnostics.
+    #[arg(long, default_value_t = 4)]
+    tab_width: usize,
+    /// Print the AST as an indented tree instead of on one line.
+    #[arg(long)]
+    pretty: bool,
+    /// Stop after this many errors, both while parsing and when printing reports, 0 for no limit.
+    #[arg(long, default_value_t = 25)]
+    max_errors: usize,
+    /// Stop running after evaluating this many expressions, 0 for no limit.
+    #[arg(long, default_value_t = 0)]
+    max_steps: usize,
+    /// Stop running when function calls nest deeper than this.
+    #[arg(long, default_value_t = 256)]
+    max_call_depth: usize,
+    /// Advise when a file indents some lines with tabs and others with spaces.
+    #[arg(long)]
+    check_indentation: bool,
+    /// Make mixing ints and floats an error instead of promoting the int to a float.
+    #[arg(long)]
+    strict_numeric: bool,
+    /// Print how long lexing, parsing and running took.
+    #[arg(long)]
+    time: bool,
+    /// Also write every report to this file as JSON, one per line, even if the run succeeded.
+    #[arg(long)]
+    dump_reports: Option<String>
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Print the version, the target this build is for, and the language features it supports.
+    Info,
+    /// Print a file laid out in the canonical style.
+    Fmt {
+        filename: String,
+        /// Rewrite the file in place instead of printing it.
+        #[arg(long)]
+        write: bool,
+        /// How many spaces to indent each block by.
+        #[arg(long, default_value_t = 2)]
+        indent: usize
+    },
+    /// List every variable and function a file declares, with where each is declared.
+    Symbols {
+        filename: String
+    },
+    /// Describe the error with a code like `E0001`, with an example of it and its fix.
+    Explain {
+        code: String
+    }
+}
+
+/// Process exit codes, following the BSD `sysexits` numbering where one applies.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum ExitCode {
+    Ok = 0,
+    /// The same code clap exits with for bad arguments.
+    UsageError = 2,
+    LexError = 64,
+    IoError = 66,
+    ParseError = 69,
+    RuntimeError = 70
+}
+
+impl From<ExitCode> for i32 {
+    fn from(value: ExitCode) -> Self {
+        value as i32
+    }
 }
 
-fn interpret(debug: bool, filename: Arc<str>, contents: &str, reports: &mut Vec<ErrorReport>) -> i32 {
+/// Runs each phase up to `emit`, recording how long every phase that ran took in `timings`.
+/// Imported files are added to `sources`.
+fn interpret(args: &Args, filename: Arc<str>, contents: &str, sources: &mut SourceCache, reports: Rc<RefCell<Vec<ErrorReport>>>, timings: &mut Vec<(&'static str, Duration)>) -> ExitCode {
     let tokens = {
-        let mut lexer = Lexer::new(filename.clone(), contents, reports);
+        let start = Instant::now();
+        let mut lexer = Lexer::new(filename.clone(), contents, reports.clone()).with_check_mixed_indentation(args.check_indentation);
         lexer.lex_tokens();
-        if debug {
+        timings.push(("lex", start.elapsed()));
+        if args.debug || args.emit == Emit::Tokens {
+            let lines = LineIndex::new(contents);
             for (i, token) in lexer.tokens.iter().enumerate() {
-                println!("{}: {}", i, token);
+                let (start_line, start_col) = lines.line_col(token.span.start);
+                let (end_line, end_col) = lines.line_col(token.span.end);
+                println!("{}: {}:{}-{}:{} {}", i, start_line, start_col, end_line, end_col, token);
             }
         }
-        if lexer.had_error { return 64; }
+        if lexer.had_error { return ExitCode::LexError; }
+        if args.emit == Emit::Tokens { return ExitCode::Ok; }
         lexer.tokens
     };
 
     let ast = {
-        let mut parser = Parser::new(&tokens, reports);
-        let Some(ast) = parser.parse() else { return 69; };
-        if debug { println!("{}", ast) }
-        if parser.had_error { return 69; }
+        let start = Instant::now();
+        let mut parser = Parser::new(&tokens, reports.clone()).with_max_errors(args.max_errors);
+        let ast = parser.parse();
+        timings.push(("parse", start.elapsed()));
+        let Some(ast) = ast else { return ExitCode::ParseError; };
+        if args.debug || args.emit == Emit::Ast {
+            if args.pretty { print!("{}", ast.pretty_print()) } else { println!("{}", ast) }
+        }
+        if parser.had_error { return ExitCode::ParseError; }
+        if args.emit == Emit::Ast { return ExitCode::Ok; }
+        ast
+    };
+
+    let ast = {
+        let start = Instant::now();
+        let ast = lox::resolve(ast, sources, reports.clone());
+        timings.push(("resolve", start.elapsed()));
+        let Some(ast) = ast else { return ExitCode::ParseError; };
+        if args.check { return ExitCode::Ok; }
         ast
     };
 
-    // Interpret!
-    unimplemented!("Reached interpretation step, not yet finished.");
+    let start = Instant::now();
+    let mut interpreter = Interpreter::new().with_max_steps(args.max_steps).with_max_call_depth(args.max_call_depth).with_strict_numeric(args.strict_numeric);
+    let result = lox::run(ast, &mut interpreter);
+    timings.push(("run", start.elapsed()));
+    match result {
+        Ok(Value::Unit) => ExitCode::Ok,
+        Ok(value) => {
+            println!("{}", value.repr());
+            ExitCode::Ok
+        }
+        Err(error) => {
+            reports.borrow_mut().push(error);
+            ExitCode::RuntimeError
+        }
+    }
+}
+
+fn render_reports(args: &Args, sources: &mut SourceCache, reports: Vec<ErrorReport>) {
+    // https://no-color.org: any non-empty value disables color
+    let color = !args.no_color && std::env::var_os("NO_COLOR").is_none_or(|value| value.is_empty());
+    // spans are byte offsets, ariadne still counts display columns in chars
+    let config = Config::default().with_color(color).with_tab_width(args.tab_width).with_index_type(IndexType::Byte);
+    print_reports(args.error_level, config, args.max_errors, sources, reports);
 }
 
-fn print_reports(level: ErrorLevel, filename: Arc<str>, contents: &String, reports: Vec<ErrorReport>) {
+fn print_reports(level: ErrorLevel, config: Config, max_errors: usize, sources: &mut SourceCache, mut reports: Vec<ErrorReport>) {
+    reports.sort_by(|a, b| a.cmp_location(b));
+    if level == ErrorLevel::Json {
+        for report in reports.iter() {
+            println!("{}", report.to_json());
+        }
+        return;
+    }
     let silent = level == ErrorLevel::Silent;
-    let cache = (filename, Source::from(contents));
     let mut emitted_errors: usize = 0;
+    let mut emitted_warnings: usize = 0;
+    let mut suppressed_errors: usize = 0;
     for report in reports.iter() {
+        if report.is_error() && max_errors != 0 && emitted_errors >= max_errors {
+            suppressed_errors += 1;
+            continue;
+        }
         if !silent {
-            report.to_ariadne_report(level).eprint(cache.clone()).unwrap();
+            report.to_ariadne_report(level, config).eprint(&mut *sources).unwrap();
+        }
+        match report.severity {
+            Severity::Error => emitted_errors += 1,
+            Severity::Warning => emitted_warnings += 1,
+            Severity::Advice => {}
         }
-        emitted_errors += 1;
     };
     if !silent {
-        if emitted_errors == 1 {
-            eprintln!("Emitted {} error.", emitted_errors);
-        } else if emitted_errors > 0 {
-            eprintln!("Emitted {} errors.", emitted_errors);
+        let mut counts = Vec::new();
+        if emitted_errors > 0 {
+            counts.push(pluralize(emitted_errors, "error"));
+        }
+        if emitted_warnings > 0 {
+            counts.push(pluralize(emitted_warnings, "warning"));
         }
+        if !counts.is_empty() {
+            eprintln!("Emitted {}.", counts.join(", "));
+        }
+        if suppressed_errors > 0 {
+            eprintln!("{} shown; more were suppressed.", pluralize(emitted_errors, "error"));
+        }
+    }
+}
+
+fn pluralize(count: usize, noun: &str) -> String {
+    if count == 1 {
+        format!("{} {}", count, noun)
+    } else {
+        format!("{} {}s", count, noun)
     }
 }
 
+fn fmt(args: &Args, filename: &str, write: bool, indent: usize) -> ExitCode {
+    let contents = match std::fs::read_to_string(filename) {
+        Ok(contents) => contents,
+        Err(error) => {
+            eprintln!("Could not read {}: {}", filename, error);
+            return ExitCode::IoError;
+        }
+    };
+    let (formatted, reports) = lox::format::format_source(Arc::from(filename), &contents, indent);
+    if !reports.is_empty() {
+        let mut sources = SourceCache::new();
+        sources.insert(Arc::from(filename), &contents);
+        render_reports(args, &mut sources, reports);
+    }
+    let Some(formatted) = formatted else { return ExitCode::ParseError; };
+    if !write {
+        print!("{}", formatted);
+    } else if let Err(error) = std::fs::write(filename, formatted) {
+        eprintln!("Could not write {}: {}", filename, error);
+        return ExitCode::IoError;
+    }
+    ExitCode::Ok
+}
+
+fn symbols(args: &Args, filename: &str) -> ExitCode {
+    let contents = match std::fs::read_to_string(filename) {
+        Ok(contents) => contents,
+        Err(error) => {
+            eprintln!("Could not read {}: {}", filename, error);
+            return ExitCode::IoError;
+        }
+    };
+    // Only this file's declarations are listed, so its imports are left unresolved
+    let (tokens, reports) = lox::lex(Arc::from(filename), &contents);
+    let (ast, reports) = match tokens {
+        Some(tokens) => lox::parse_tokens(&tokens, reports),
+        None => (None, reports)
+    };
+    if !reports.is_empty() {
+        let mut sources = SourceCache::new();
+        sources.insert(Arc::from(filename), &contents);
+        render_reports(args, &mut sources, reports);
+    }
+    let Some(ast) = ast else { return ExitCode::ParseError; };
+    let mut collector = DeclarationCollector::default();
+    walk(&ast, &mut collector);
+    let lines = LineIndex::new(&contents);
+    for (name, span, kind) in collector.declarations {
+        let (line, column) = lines.line_col(span.start);
+        println!("{}  {}:{}:{}  {}", name, filename, line, column, kind);
+    }
+    ExitCode::Ok
+}
+
+fn explain(code: &str) -> ExitCode {
+    let Some(kind) = ErrorReportKind::from_code(code) else {
+        eprintln!("Unknown error code {}.", code);
+        return ExitCode::UsageError;
+    };
+    println!("{:?}[{}]\n", kind, kind.code().unwrap());
+    print!("{}", kind.explanation());
+    ExitCode::Ok
+}
+
+fn info() {
+    println!("{} {}", env!("CARGO_PKG_NAME"), VERSION);
+    println!("target: {}", env!("TARGET"));
+    println!("features: {}", lox::FEATURES.join(", "));
+}
+
 fn repl(_debug: bool) {
     unimplemented!("Repl is not implemented.");
 }
 
 fn main() {
+    let cli = std::thread::Builder::new().stack_size(STACK_SIZE).spawn(cli).expect("Could not start the interpreter thread.");
+    if let Err(panic) = cli.join() {
+        std::panic::resume_unwind(panic);
+    }
+}
+
+fn cli() {
     let args = Args::parse();
+    if let Some(command) = &args.command {
+        return match command {
+            Command::Info => info(),
+            Command::Fmt { filename, write, indent } => exit(fmt(&args, filename, *write, *indent).into()),
+            Command::Symbols { filename } => exit(symbols(&args, filename).into()),
+            Command::Explain { code } => exit(explain(code).into())
+        };
+    }
 
-    if args.filename.is_some() {
-        let mut reports = Vec::<ErrorReport>::new();
-        let arc_filename: Arc<str> = Arc::from(args.filename.unwrap());
+    let filename = match args.filename.clone() {
+        Some(filename) if filename != "-" => Some(filename),
+        // an explicit dash, or input piped in without a filename, means the program is on stdin
+        Some(_) => None,
+        None if std::io::stdin().is_terminal() => return repl(args.debug),
+        None => None
+    };
 
-        let mut contents = String::new();
-        File::open(arc_filename.deref()).unwrap().read_to_string(&mut contents).unwrap();
+    let reports = Rc::new(RefCell::new(Vec::<ErrorReport>::new()));
+    let mut contents = String::new();
+    let (arc_filename, read): (Arc<str>, _) = match filename {
+        Some(filename) => {
+            let read = File::open(&filename).and_then(|mut file| file.read_to_string(&mut contents));
+            (Arc::from(filename), read)
+        }
+        None => (Arc::from("<stdin>"), std::io::stdin().read_to_string(&mut contents))
+    };
+    if let Err(error) = read {
+        eprintln!("Could not read {}: {}", arc_filename, error);
+        exit(ExitCode::IoError.into());
+    }
 
-        let code = {
-            interpret(args.debug, arc_filename.clone(), &contents, &mut reports)
-        };
-        if !reports.is_empty() {
-            print_reports(args.error_level, arc_filename, &contents, reports);
+    let mut sources = SourceCache::new();
+    sources.insert(arc_filename.clone(), &contents);
+    let mut timings = Vec::new();
+    let code = {
+        interpret(&args, arc_filename.clone(), &contents, &mut sources, reports.clone(), &mut timings)
+    };
+    if let Some(path) = &args.dump_reports {
+        let mut dumped = reports.borrow().clone();
+        dumped.sort_by(|a, b| a.cmp_location(b));
+        let json: String = dumped.iter().map(|report| report.to_json() + "\n").collect();
+        if let Err(error) = std::fs::write(path, json) {
+            eprintln!("Could not write {}: {}", path, error);
+            exit(ExitCode::IoError.into());
+        }
+    }
+    if !reports.borrow().is_empty() {
+        render_reports(&args, &mut sources, reports.take());
+    }
+    if args.time && args.error_level != ErrorLevel::Silent {
+        for (phase, duration) in timings {
+            eprintln!("{:>5}: {:?}", phase, duration);
         }
-        exit(code);
-    } else {
-        repl(args.debug)
     }
+    exit(code.into());
 }
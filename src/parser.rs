@@ -1,90 +1,924 @@
 use std::cell::RefCell;
-use std::collections::BinaryHeap;
-use std::ops::Deref;
+use std::collections::HashMap;
+use std::num::IntErrorKind;
 use std::rc::Rc;
 use std::slice::{Iter};
-use ariadne::{Color, Label};
-use crate::ast::{AST, ASTKind};
-use crate::token::{Token, TokenKind};
-use crate::error::{ErrorReport, ErrorReportKind, Result, ResultErrorless};
+use std::sync::Arc;
+use ariadne::Color;
+use crate::ast::{AST, ASTKind, BinaryOp, Pattern, StringPart, TypeName, UnaryOp};
+use crate::lexer::{integer_suffix_range, interpolation_end, is_triple_quoted, string_value, unescape, Lexer};
+use crate::token::{Base, Token, TokenKind};
+use crate::error::{ErrorReport, ErrorReportKind, Label, Result, Severity};
 use crate::span::Span;
+use crate::symbol::{Interner, Symbol};
+use crate::visitor::DeclarationKind;
+
+const DEFAULT_MAX_ERRORS: usize = 25;
+const DEFAULT_MAX_DEPTH: usize = 256;
 
 pub struct Parser<'a> {
     current: RefToken<'a>,
+    previous: RefToken<'a>,
     pub had_error: bool,
+    error_count: usize,
+    max_errors: usize,
+    gave_up: bool,
+    function_depth: usize,
+    loop_depth: usize,
+    depth: usize,
+    max_depth: usize,
+    /// How many `{` have been consumed and not yet closed, so recovery knows which braces a broken
+    /// statement opened itself.
+    open_braces: usize,
+    trim_leading_newline: bool,
+    interner: Interner,
+    /// Names declared in each enclosing block, innermost last.
+    scopes: Vec<HashMap<Symbol, Declaration>>,
     tokens: Iter<'a, Token<'a>>,
     reports: Rc<RefCell<Vec<ErrorReport>>>
 }
 
 type RefToken<'a> = &'a Token<'a>;
 
+/// Where a name was declared, and whether anything has referred to it since.
+struct Declaration {
+    span: Span,
+    kind: DeclarationKind,
+    used: bool
+}
+
 impl<'a> Parser<'a> {
     pub fn new(tokens_vec: &'a [Token], reports: Rc<RefCell<Vec<ErrorReport>>>) -> Self {
         let mut tokens = tokens_vec.iter();
+        let current = tokens.find(|token| !token.kind.is_comment()).expect("EOF Token doesn't exist.");
         Parser {
-            current: tokens.next().expect("EOF Token doesn't exist."),
+            current,
+            previous: current,
             had_error: false,
+            error_count: 0,
+            max_errors: DEFAULT_MAX_ERRORS,
+            gave_up: false,
+            function_depth: 0,
+            loop_depth: 0,
+            depth: 0,
+            max_depth: DEFAULT_MAX_DEPTH,
+            open_braces: 0,
+            trim_leading_newline: true,
+            interner: Interner::new(),
+            scopes: vec![HashMap::new()],
             tokens,
             reports
         }
     }
 
+    /// Parsing gives up at the first error past this many, which is still reported so that callers
+    /// can tell some were cut off. Zero means no limit.
+    pub fn with_max_errors(mut self, max_errors: usize) -> Self {
+        self.max_errors = max_errors;
+        self
+    }
+
+    /// Expressions nested deeper than this are reported instead of risking a stack overflow.
+    pub fn with_max_depth(mut self, max_depth: usize) -> Self {
+        self.max_depth = max_depth;
+        self
+    }
+
+    /// Drop the line break straight after an opening `"""`, so the text of a multi-line string can
+    /// start on its own line.
+    pub fn with_trim_leading_newline(mut self, trim_leading_newline: bool) -> Self {
+        self.trim_leading_newline = trim_leading_newline;
+        self
+    }
+
     fn push_report(&mut self, report: ErrorReport) {
-        self.had_error = true;
-        self.reports.borrow_mut().push(report)
+        if self.gave_up {
+            return;
+        }
+        if report.is_error() {
+            self.had_error = true;
+            self.error_count += 1;
+        }
+        self.reports.borrow_mut().push(report);
+        if self.max_errors != 0 && self.error_count > self.max_errors {
+            self.gave_up = true;
+        }
     }
 
-    fn advance(&mut self) -> RefToken {
-        self.current = self.tokens.next().expect("EOF Token skipped.");
+    fn advance(&mut self) -> RefToken<'a> {
+        match self.current.kind {
+            TokenKind::LeftBrace => self.open_braces += 1,
+            TokenKind::RightBrace => self.open_braces = self.open_braces.saturating_sub(1),
+            _ => {}
+        }
+        self.previous = self.current;
+        // Comments are only kept for tooling, the grammar never sees them
+        self.current = self.tokens.find(|token| !token.kind.is_comment()).expect("EOF Token skipped.");
         self.current
     }
 
-    fn consume(&mut self, kind: TokenKind, message: &str) -> Result<RefToken> {
+    /// Look past the current token without consuming anything, skipping comments like `advance`.
+    fn peek(&self, offset: usize) -> RefToken<'a> {
+        // EOF is always the last token, so looking past it gives EOF again
+        self.tokens.clone().filter(|token| !token.kind.is_comment()).nth(offset).unwrap_or(self.current)
+    }
+
+    fn check(&self, kind: TokenKind) -> bool {
+        self.current.kind == kind
+    }
+
+    fn check_any(&self, kinds: &[TokenKind]) -> bool {
+        kinds.contains(&self.current.kind)
+    }
+
+    fn consume(&mut self, kind: TokenKind, message: &str) -> Result<RefToken<'a>> {
+        self.consume_any(&[kind], message)
+    }
+
+    /// Consume the current token if it is any of `kinds`, otherwise report which kinds were expected.
+    fn consume_any(&mut self, kinds: &[TokenKind], message: &str) -> Result<RefToken<'a>> {
         let token = self.current;
-        if token.kind == kind {
+        if self.check_any(kinds) {
             self.advance();
-            Ok(token)
-        } else if token.kind.clone() == TokenKind::EOF {
-            let e = ErrorReport::new(ErrorReportKind::Custom, token.span.clone(), "Unexpected EOF".to_string())
-                .with_label(Label::new(token.span.clone()).with_message(message).with_color(Color::Red));
-            Err(e)
+            return Ok(token);
+        }
+        let e = if token.kind == TokenKind::EOF {
+            ErrorReport::new(ErrorReportKind::Custom, token.span.clone(), "Unexpected EOF".to_string())
         } else {
-            let e = ErrorReport::new(ErrorReportKind::UnexpectedToken, token.span.clone(), format!("got {:?}", token.kind))
-                .with_label(Label::new(token.span.clone()).with_message(message).with_color(Color::Red));
-            Err(e)
+            ErrorReport::new(ErrorReportKind::UnexpectedToken, token.span.clone(), format!("got {:?}", token.kind))
+        };
+        let e = e.with_label(Label::new(token.span.clone()).with_message(message).with_color(Color::Red));
+        if let [_, _, ..] = kinds {
+            let expected: Vec<String> = kinds.iter().map(|kind| format!("{:?}", kind)).collect();
+            return Err(e.with_note(format!("Expected one of {}.", expected.join(", "))));
+        }
+        Err(e)
+    }
+
+    fn consume_identifier(&mut self, message: &str) -> Result<Symbol> {
+        let text = self.consume(TokenKind::Identifier, message)?.text;
+        Ok(self.interner.intern(text))
+    }
+
+    /// Record a declaration in the innermost scope.
+    fn declare(&mut self, name: Symbol, span: Span, kind: DeclarationKind) {
+        let scope = self.scopes.last_mut().expect("The global scope is never popped.");
+        let declaration = Declaration { span, kind, used: false };
+        // Redefining a name in the same scope shadows it, which is fine unless the first was never used
+        if let Some(first) = scope.insert(name.clone(), declaration) {
+            self.warn_unused(&name, &first);
+        }
+    }
+
+    /// Mark the innermost declaration of `name` as used. Names declared nowhere are left for the
+    /// interpreter to report, since they may be built-ins or come from an import.
+    fn use_name(&mut self, name: &Symbol) {
+        if let Some(declaration) = self.scopes.iter_mut().rev().find_map(|scope| scope.get_mut(name)) {
+            declaration.used = true;
+        }
+    }
+
+    /// Leave a block, warning about each variable it declared that was never used. Top-level
+    /// variables are never popped, as another file may import them.
+    fn pop_scope(&mut self) {
+        let scope = self.scopes.pop().expect("Popped more scopes than were pushed.");
+        let mut declarations: Vec<_> = scope.into_iter().collect();
+        declarations.sort_by_key(|(_, declaration)| declaration.span.start);
+        for (name, declaration) in declarations {
+            self.warn_unused(&name, &declaration);
+        }
+    }
+
+    fn warn_unused(&mut self, name: &Symbol, declaration: &Declaration) {
+        // A statement skipped by error recovery may have been the one using it
+        if declaration.used || declaration.kind != DeclarationKind::Variable || name.starts_with('_') || self.had_error {
+            return;
+        }
+        let span = declaration.span.clone();
+        let e = ErrorReport::new(ErrorReportKind::NameError, span.clone(), format!("Unused variable `{}`", name))
+            .with_severity(Severity::Warning)
+            .with_label(Label::new(span).with_message("Declared here but never used").with_color(Color::Yellow))
+            .with_help(format!("Remove it, or rename it to `_{}` if that is intended.", name));
+        self.push_report(e);
+    }
+
+    fn consume_closing(&mut self, kind: TokenKind, open: &Span, message: &str) -> Result<RefToken<'a>> {
+        let token = self.current;
+        if token.kind == kind {
+            self.advance();
+            return Ok(token);
         }
+        let e = ErrorReport::new(ErrorReportKind::SyntaxError, token.span.clone(), "Unbalanced Delimiter".to_string())
+            .with_label(Label::new(open.clone()).with_message("Opened here").with_color(Color::BrightBlue).with_order(1))
+            .with_label(Label::new(token.span.clone()).with_message(message).with_color(Color::Red));
+        Err(e)
     }
 
     fn consume_line_end(&mut self) -> Result<()> {
         match self.current.kind {
-            TokenKind::SemiColon => Ok(()),
-            TokenKind::EOF => Ok(()),
+            TokenKind::SemiColon => {
+                self.advance();
+                Ok(())
+            },
+            TokenKind::EOF | TokenKind::RightBrace => Ok(()),
+            // Statements ending in a block don't need a semicolon
+            _ if self.previous.kind == TokenKind::RightBrace => Ok(()),
             _ => {
-                let e = ErrorReport::new(ErrorReportKind::UnexpectedToken, self.current.span.clone(), format!("Expected end of line but got {:?}", self.current.kind))
-                    .with_label(Label::new(self.current.span.clone()).with_color(Color::Red));
+                let Token { span, text, .. } = self.current;
+                // Right after the last token of the statement, which is where the `;` belongs
+                let previous = self.previous;
+                let insert_at = Span::location(previous.span.start + previous.text.len(), previous.span.filename.clone());
+                let e = ErrorReport::new(ErrorReportKind::UnexpectedToken, span.clone(), format!("Expected end of line but got `{}`", text))
+                    .with_label(Label::new(span.clone()).with_message("Expected `;` before this").with_color(Color::Red))
+                    .with_secondary_label(insert_at, "insert `;` here");
                 Err(e)
             }
         }
     }
 
+    /// Skip tokens until the end of the current statement so parsing can resume after an error.
+    /// `open_braces` is how many braces were open when the statement started, any opened since are
+    /// part of it, like the `{` of a record literal that failed partway through.
+    fn synchronize(&mut self, terminator: &TokenKind, open_braces: usize) {
+        let mut depth = self.open_braces.saturating_sub(open_braces);
+        let failed_at = self.current;
+        loop {
+            match self.current.kind {
+                TokenKind::EOF => break,
+                TokenKind::SemiColon if depth == 0 => {
+                    self.advance();
+                    break;
+                }
+                TokenKind::RightBrace if depth == 0 && *terminator == TokenKind::RightBrace => break,
+                // Outside a block it closes nothing, and is reported by itself unless it is what
+                // the statement failed on
+                TokenKind::RightBrace if depth == 0 && !std::ptr::eq(self.current, failed_at) => break,
+                TokenKind::RightBrace => depth = depth.saturating_sub(1),
+                TokenKind::LeftBrace => depth += 1,
+                _ => {}
+            }
+            self.advance();
+        }
+    }
+
+    /// Report a run of `}` with no block left to close, and skip past it.
+    fn skip_unmatched_braces(&mut self) {
+        let start = self.current.span.clone();
+        while self.check(TokenKind::RightBrace) {
+            self.advance();
+        }
+        let span = start.extend(self.previous.span.clone());
+        let e = ErrorReport::new(ErrorReportKind::SyntaxError, span.clone(), "Unbalanced Delimiter".to_string())
+            .with_label(Label::new(span).with_message("There is no block to close here").with_color(Color::Red))
+            .with_note("Every `}` must close a `{` opened before it.".to_string());
+        self.push_report(e);
+    }
+
     pub fn parse(&mut self) -> Option<Rc<AST>> {
-        match self.parse_atom() {
-            Ok(node) => Some(node),
-            Err(error) => {
-                self.push_report(error);
-                None
+        let start = self.current.span.clone();
+        let statements = self.parse_statements(TokenKind::EOF);
+        if self.gave_up {
+            return None;
+        }
+        Some(Rc::new(AST::new(start.extend(self.current.span.clone()), ASTKind::Block(statements))))
+    }
+
+    fn parse_statements(&mut self, terminator: TokenKind) -> Vec<Rc<AST>> {
+        let mut statements = Vec::new();
+        while !self.gave_up && !self.check_any(&[terminator.clone(), TokenKind::EOF]) {
+            if self.check(TokenKind::RightBrace) {
+                self.skip_unmatched_braces();
+                continue;
+            }
+            let open_braces = self.open_braces;
+            match self.parse_statement() {
+                Ok(node) => statements.push(node),
+                Err(error) => {
+                    self.push_report(error);
+                    self.synchronize(&terminator, open_braces);
+                }
+            }
+        }
+        statements
+    }
+
+    pub fn parse_statement(&mut self) -> Result<Rc<AST>> {
+        let node = match self.current.kind {
+            TokenKind::Let => self.parse_let()?,
+            TokenKind::Fn => self.parse_function()?,
+            TokenKind::Return => self.parse_return()?,
+            TokenKind::Import => self.parse_import()?,
+            TokenKind::Break | TokenKind::Continue => self.parse_loop_control()?,
+            _ => self.parse_expression()?
+        };
+        self.consume_line_end()?;
+        Ok(node)
+    }
+
+    /// Count one more level of nesting towards `max_depth`, reporting it at `span` once over the limit.
+    /// Only call this inside `restoring_depth`, which undoes every level taken.
+    fn nest(&mut self, span: &Span) -> Result<()> {
+        if self.depth >= self.max_depth {
+            let e = ErrorReport::new(ErrorReportKind::SyntaxError, span.clone(), "Expression Nested Too Deeply".to_string())
+                .with_label(Label::new(span.clone()).with_message(format!("Nesting exceeds {} levels here", self.max_depth)).with_color(Color::Red));
+            return Err(e);
+        }
+        self.depth += 1;
+        Ok(())
+    }
+
+    /// Run `parse`, then put the depth back to what it was, whether it succeeded or not.
+    fn restoring_depth<T>(&mut self, parse: impl FnOnce(&mut Self) -> Result<T>) -> Result<T> {
+        let depth = self.depth;
+        let result = parse(self);
+        self.depth = depth;
+        result
+    }
+
+    pub fn parse_expression(&mut self) -> Result<Rc<AST>> {
+        let span = self.current.span.clone();
+        self.restoring_depth(|parser| {
+            parser.nest(&span)?;
+            parser.parse_assignment()
+        })
+    }
+
+    fn parse_assignment(&mut self) -> Result<Rc<AST>> {
+        let target = self.parse_range()?;
+        let op = match self.current.kind {
+            TokenKind::Equals => None,
+            TokenKind::PlusEquals => Some(BinaryOp::Add),
+            TokenKind::MinusEquals => Some(BinaryOp::Sub),
+            TokenKind::StarEquals => Some(BinaryOp::Mul),
+            TokenKind::SlashEquals => Some(BinaryOp::Div),
+            _ => return Ok(target)
+        };
+        let op_span = self.current.span.clone();
+        // `(x) = 1` assigns to `x` just the same
+        let mut inner = &target;
+        while let ASTKind::Grouping(grouped) = &inner.kind {
+            inner = grouped;
+        }
+        let ASTKind::Identifier(name) = &inner.kind else {
+            let e = ErrorReport::new(ErrorReportKind::SyntaxError, op_span.clone(), "Invalid Assignment Target".to_string())
+                .with_label(Label::new(target.span.clone()).with_message("Cannot assign to this expression").with_color(Color::Red));
+            return Err(e);
+        };
+        self.advance();
+        // `a = b = c` nests to the right, one level per `=`
+        let rhs = self.restoring_depth(|parser| {
+            parser.nest(&op_span)?;
+            parser.parse_assignment()
+        })?;
+        let span = target.span.clone().extend(rhs.span.clone());
+        // Compound assignments desugar into a plain assignment of the binary operation
+        let value = match op {
+            Some(op) => Rc::new(AST::new(span.clone(), ASTKind::Binary { op, op_span, lhs: target.clone(), rhs })),
+            None => rhs
+        };
+        Ok(Rc::new(AST::new(span, ASTKind::Assign { name: name.clone(), value })))
+    }
+
+    /// `a..b`, where either bound can be left out.
+    fn parse_range(&mut self) -> Result<Rc<AST>> {
+        let start = if self.check(TokenKind::DotDot) { None } else { Some(self.parse_binary(0)?) };
+        let op_span = self.current.span.clone();
+        if !self.check(TokenKind::DotDot) {
+            return Ok(start.expect("A missing start bound means the current token is '..'"));
+        }
+        self.advance();
+        // Anything that can't start an expression means the range is open ended
+        let has_end = !self.check_any(&[
+            TokenKind::SemiColon,
+            TokenKind::Comma,
+            TokenKind::RightParen,
+            TokenKind::RightBracket,
+            TokenKind::LeftBrace,
+            TokenKind::RightBrace,
+            TokenKind::EOF
+        ]);
+        let end = if has_end { Some(self.parse_binary(0)?) } else { None };
+        let first = start.as_ref().map_or(op_span.clone(), |start| start.span.clone());
+        let last = end.as_ref().map_or(op_span, |end| end.span.clone());
+        Ok(Rc::new(AST::new(first.extend(last), ASTKind::Range { start, end })))
+    }
+
+    /// Every binary operator, climbing by the binding powers in `TokenKind::binary_binding_power`.
+    /// Only operators binding at least as tightly as `min_power` are taken as part of this operand.
+    /// Each operator taken nests everything before it one level deeper, so a long chain like
+    /// `1 + 1 + ...` counts towards the depth limit just as `((1 + 1) + ...)` would.
+    fn parse_binary(&mut self, min_power: u8) -> Result<Rc<AST>> {
+        self.restoring_depth(|parser| {
+            let mut lhs = parser.parse_cast()?;
+            while let Some((left_power, right_power)) = parser.current.kind.binary_binding_power() {
+                if left_power < min_power {
+                    break;
+                }
+                let op = binary_op(&parser.current.kind);
+                let op_span = parser.current.span.clone();
+                parser.nest(&op_span)?;
+                parser.advance();
+                let rhs = parser.parse_binary(right_power)?;
+                lhs = Rc::new(AST::new(lhs.span.clone().extend(rhs.span.clone()), ASTKind::Binary { op, op_span, lhs, rhs }));
+            }
+            Ok(lhs)
+        })
+    }
+
+    /// `x as int as string` nests to the left, one level per cast, like a binary chain.
+    fn parse_cast(&mut self) -> Result<Rc<AST>> {
+        self.restoring_depth(|parser| {
+            let mut expr = parser.parse_unary()?;
+            while parser.check(TokenKind::As) {
+                parser.nest(&parser.current.span.clone())?;
+                parser.advance();
+                let Token { span, text, .. } = parser.consume(TokenKind::Identifier, "Expected a type name after 'as'")?;
+                let target = match *text {
+                    "int" => TypeName::Int,
+                    "float" => TypeName::Float,
+                    "string" => TypeName::String,
+                    _ => {
+                        let e = ErrorReport::new(ErrorReportKind::TypeError, span.clone(), format!("Unknown type `{}`", text))
+                            .with_label(Label::new(span.clone()).with_message("Cannot cast to this").with_color(Color::Red))
+                            .with_note("Values can be cast to int, float or string.".to_string());
+                        return Err(e);
+                    }
+                };
+                expr = Rc::new(AST::new(expr.span.clone().extend(span.clone()), ASTKind::Cast { expr, target }));
+            }
+            Ok(expr)
+        })
+    }
+
+    fn parse_unary(&mut self) -> Result<Rc<AST>> {
+        if !self.current.kind.is_prefix_operator() {
+            return self.parse_call();
+        }
+        let op_span = self.current.span.clone();
+        self.advance();
+        // Which literals are in range depends on the sign, so `-128i8` is read as one negative literal
+        if let TokenKind::IntegerLiteral(base) = self.current.kind {
+            if !matches!(self.peek(0).kind, TokenKind::LeftParen | TokenKind::LeftBracket | TokenKind::Period) {
+                return self.parse_integer(base, Some(op_span));
+            }
+        }
+        // `- - x` nests, and each level counts towards the depth limit like a parenthesised expression
+        let operand = self.restoring_depth(|parser| {
+            parser.nest(&op_span)?;
+            parser.parse_unary()
+        })?;
+        Ok(Rc::new(AST::new(op_span.extend(operand.span.clone()), ASTKind::Unary { op: UnaryOp::Neg, operand })))
+    }
+
+    /// Calls, indexing and member access. Each one nests the expression before it a level deeper,
+    /// so `f()()()` and `a.b.c` count towards the depth limit too.
+    fn parse_call(&mut self) -> Result<Rc<AST>> {
+        self.restoring_depth(|parser| {
+            let mut callee = parser.parse_atom()?;
+            loop {
+                match parser.current.kind {
+                    TokenKind::LeftParen => {
+                        let open = parser.current.span.clone();
+                        parser.nest(&open)?;
+                        parser.advance();
+                        let args = parser.parse_list(TokenKind::RightParen)?;
+                        let end = parser.consume_closing(TokenKind::RightParen, &open, "Expected ')' to close the argument list")?.span.clone();
+                        callee = Rc::new(AST::new(callee.span.clone().extend(end), ASTKind::Call { callee, args }));
+                    }
+                    TokenKind::LeftBracket => {
+                        let open = parser.current.span.clone();
+                        parser.nest(&open)?;
+                        parser.advance();
+                        let index = parser.parse_expression()?;
+                        let end = parser.consume_closing(TokenKind::RightBracket, &open, "Expected ']' to close the index")?.span.clone();
+                        callee = Rc::new(AST::new(callee.span.clone().extend(end), ASTKind::Index { object: callee, index }));
+                    }
+                    TokenKind::Period => {
+                        parser.nest(&parser.current.span.clone())?;
+                        parser.advance();
+                        let end = parser.current.span.clone();
+                        let name = parser.consume_identifier("Expected a member name after '.'")?;
+                        callee = Rc::new(AST::new(callee.span.clone().extend(end), ASTKind::Member { object: callee, name }));
+                    }
+                    _ => break
+                }
+            }
+            Ok(callee)
+        })
+    }
+
+    /// Parse comma separated expressions up to, but not including, the closing delimiter.
+    /// A single trailing comma before the delimiter is allowed.
+    fn parse_list(&mut self, closing: TokenKind) -> Result<Vec<Rc<AST>>> {
+        let mut items = Vec::new();
+        while !self.check(closing.clone()) {
+            items.push(self.parse_expression()?);
+            if !self.check(TokenKind::Comma) {
+                break;
+            }
+            self.advance();
+            if self.check(TokenKind::Comma) {
+                let e = ErrorReport::new(ErrorReportKind::SyntaxError, self.current.span.clone(), "Unexpected Comma".to_string())
+                    .with_label(Label::new(self.current.span.clone()).with_message("Expected an expression between the commas").with_color(Color::Red));
+                return Err(e);
+            }
+        }
+        Ok(items)
+    }
+
+    fn parse_let(&mut self) -> Result<Rc<AST>> {
+        let start = self.consume(TokenKind::Let, "Expected 'let'")?.span.clone();
+        let name = self.consume_identifier("Expected a variable name")?;
+        let name_span = self.previous.span.clone();
+        self.consume(TokenKind::Equals, "Expected '=' after the variable name")?;
+        let value = self.parse_expression()?;
+        // `let _ = ...` binds nothing, so it can be repeated freely
+        if &*name != "_" {
+            self.declare(name.clone(), name_span, DeclarationKind::Variable);
+        }
+        Ok(Rc::new(AST::new(start.extend(value.span.clone()), ASTKind::Let { name, value })))
+    }
+
+    fn parse_function(&mut self) -> Result<Rc<AST>> {
+        let start = self.consume(TokenKind::Fn, "Expected 'fn'")?.span.clone();
+        let name = self.consume_identifier("Expected a function name after 'fn'")?;
+        // Declared before the body so that recursive calls refer to this function
+        self.declare(name.clone(), self.previous.span.clone(), DeclarationKind::Function);
+        let open = self.consume(TokenKind::LeftParen, "Expected '(' to start the parameter list")?.span.clone();
+        let mut params = Vec::new();
+        while !self.check(TokenKind::RightParen) {
+            params.push(self.consume_identifier("Expected a parameter name")?);
+            if !self.check(TokenKind::Comma) {
+                break;
+            }
+            self.advance();
+        }
+        self.consume_closing(TokenKind::RightParen, &open, "Expected ')' to close the parameter list")?;
+        // Loops outside the function can't be controlled from inside of it
+        let loop_depth = std::mem::replace(&mut self.loop_depth, 0);
+        self.function_depth += 1;
+        // Parameters shadow outer names inside the body, but are never warned about
+        let parameters = params.iter().map(|param: &Symbol| {
+            let declaration = Declaration { span: start.clone(), kind: DeclarationKind::Variable, used: true };
+            (param.clone(), declaration)
+        }).collect();
+        self.scopes.push(parameters);
+        let body = self.parse_block();
+        self.scopes.pop();
+        self.function_depth -= 1;
+        self.loop_depth = loop_depth;
+        let body = body?;
+        Ok(Rc::new(AST::new(start.extend(body.span.clone()), ASTKind::Function { name, params, body })))
+    }
+
+    fn parse_return(&mut self) -> Result<Rc<AST>> {
+        let start = self.consume(TokenKind::Return, "Expected 'return'")?.span.clone();
+        if self.function_depth == 0 {
+            let e = ErrorReport::new(ErrorReportKind::SyntaxError, start.clone(), "Return Outside Function".to_string())
+                .with_label(Label::new(start).with_message("Can only return from inside a function body").with_color(Color::Red));
+            return Err(e);
+        }
+        match self.current.kind {
+            TokenKind::SemiColon | TokenKind::RightBrace | TokenKind::EOF => Ok(Rc::new(AST::new(start, ASTKind::Return(None)))),
+            _ => {
+                let value = self.parse_expression()?;
+                Ok(Rc::new(AST::new(start.extend(value.span.clone()), ASTKind::Return(Some(value)))))
+            }
+        }
+    }
+
+    fn parse_import(&mut self) -> Result<Rc<AST>> {
+        let start = self.consume(TokenKind::Import, "Expected 'import'")?.span.clone();
+        // Imported declarations are spliced into the program, which only makes sense at the top
+        if self.scopes.len() > 1 {
+            let e = ErrorReport::new(ErrorReportKind::SyntaxError, start.clone(), "Import Outside Top Level".to_string())
+                .with_label(Label::new(start).with_message("Can only import at the top level of a file").with_color(Color::Red));
+            return Err(e);
+        }
+        let path = self.consume(TokenKind::StringLiteral, "Expected a path to import")?;
+        Ok(Rc::new(AST::new(start.extend(path.span.clone()), ASTKind::Import(string_value(path.text)))))
+    }
+
+    fn parse_block(&mut self) -> Result<Rc<AST>> {
+        let start = self.consume(TokenKind::LeftBrace, "Expected '{' to start a block")?.span.clone();
+        self.scopes.push(HashMap::new());
+        let statements = self.parse_statements(TokenKind::RightBrace);
+        self.pop_scope();
+        let end = self.consume(TokenKind::RightBrace, "Expected '}' to close the block")?.span.clone();
+        Ok(Rc::new(AST::new(start.extend(end), ASTKind::Block(statements))))
+    }
+
+    fn parse_record(&mut self) -> Result<Rc<AST>> {
+        let open = self.consume(TokenKind::LeftBrace, "Expected '{' to start a record")?.span.clone();
+        let mut fields: Vec<(Symbol, Rc<AST>)> = Vec::new();
+        let mut spans: HashMap<Symbol, Span> = HashMap::new();
+        while !self.check(TokenKind::RightBrace) {
+            let name = self.consume_identifier("Expected a field name")?;
+            let name_span = self.previous.span.clone();
+            if let Some(first) = spans.insert(name.clone(), name_span.clone()) {
+                let e = ErrorReport::new(ErrorReportKind::SyntaxError, name_span.clone(), format!("Duplicate field `{}`", name))
+                    .with_label(Label::new(name_span).with_message("Defined again here").with_color(Color::Red))
+                    .with_secondary_label(first, "First defined here");
+                return Err(e);
+            }
+            self.consume(TokenKind::Colon, "Expected ':' after the field name")?;
+            fields.push((name, self.parse_expression()?));
+            if !self.check(TokenKind::Comma) {
+                break;
+            }
+            self.advance();
+        }
+        let end = self.consume_closing(TokenKind::RightBrace, &open, "Expected '}' to close the record")?.span.clone();
+        Ok(Rc::new(AST::new(open.extend(end), ASTKind::RecordLiteral(fields))))
+    }
+
+    fn parse_if(&mut self) -> Result<Rc<AST>> {
+        let start = self.consume(TokenKind::If, "Expected 'if'")?.span.clone();
+        let cond = self.parse_expression()?;
+        let then_branch = self.parse_block()?;
+        let else_branch = if self.check(TokenKind::Else) {
+            self.advance();
+            // `else if` chains without needing braces around each inner `if`, each one a level deeper
+            if self.check(TokenKind::If) {
+                let span = self.current.span.clone();
+                Some(self.restoring_depth(|parser| {
+                    parser.nest(&span)?;
+                    parser.parse_if()
+                })?)
+            } else {
+                Some(self.parse_block()?)
+            }
+        } else {
+            None
+        };
+        let end = else_branch.as_ref().unwrap_or(&then_branch).span.clone();
+        Ok(Rc::new(AST::new(start.extend(end), ASTKind::If { cond, then_branch, else_branch })))
+    }
+
+    fn parse_while(&mut self) -> Result<Rc<AST>> {
+        let start = self.consume(TokenKind::While, "Expected 'while'")?.span.clone();
+        let cond = self.parse_expression()?;
+        self.loop_depth += 1;
+        let body = self.parse_block();
+        self.loop_depth -= 1;
+        let body = body?;
+        Ok(Rc::new(AST::new(start.extend(body.span.clone()), ASTKind::While { cond, body })))
+    }
+
+    fn parse_match(&mut self) -> Result<Rc<AST>> {
+        let start = self.consume(TokenKind::Match, "Expected 'match'")?.span.clone();
+        let scrutinee = self.parse_expression()?;
+        let open = self.consume(TokenKind::LeftBrace, "Expected '{' to start the match arms")?.span.clone();
+        let mut arms = Vec::new();
+        while !self.check(TokenKind::RightBrace) {
+            let pattern = self.parse_pattern()?;
+            self.consume(TokenKind::FatArrow, "Expected '=>' after the pattern")?;
+            arms.push((pattern, self.parse_expression()?));
+            if !self.check(TokenKind::Comma) {
+                break;
+            }
+            self.advance();
+        }
+        let end = self.consume_closing(TokenKind::RightBrace, &open, "Expected '}' to close the match")?.span.clone();
+        Ok(Rc::new(AST::new(start.extend(end), ASTKind::Match { scrutinee, arms })))
+    }
+
+    /// Patterns are written like the literals they match, so parse one and check what it was.
+    fn parse_pattern(&mut self) -> Result<Pattern> {
+        // A negative number is the only pattern that isn't a single atom
+        let minus = self.check(TokenKind::Minus).then(|| self.advance().span.clone());
+        let negative = minus.is_some();
+        let node = match self.current.kind {
+            TokenKind::IntegerLiteral(base) if negative => self.parse_integer(base, minus)?,
+            _ => self.parse_atom()?
+        };
+        match &node.kind {
+            // Already negated by `parse_integer`
+            ASTKind::IntegerLiteral(val) if negative => Ok(Pattern::Int(*val)),
+            ASTKind::FloatLiteral(val) if negative => Ok(Pattern::Float(-val)),
+            _ if negative => {
+                let e = ErrorReport::new(ErrorReportKind::SyntaxError, node.span.clone(), "Invalid Pattern".to_string())
+                    .with_label(Label::new(node.span.clone()).with_message("Only numbers can be negated in a pattern").with_color(Color::Red));
+                Err(e)
+            }
+            ASTKind::IntegerLiteral(val) => Ok(Pattern::Int(*val)),
+            ASTKind::FloatLiteral(val) => Ok(Pattern::Float(*val)),
+            ASTKind::StringLiteral(val) => Ok(Pattern::Str(val.clone())),
+            ASTKind::BoolLiteral(val) => Ok(Pattern::Bool(*val)),
+            ASTKind::Wildcard => Ok(Pattern::Wildcard),
+            _ => {
+                let e = ErrorReport::new(ErrorReportKind::SyntaxError, node.span.clone(), "Invalid Pattern".to_string())
+                    .with_label(Label::new(node.span.clone()).with_message("Expected a literal or `_`").with_color(Color::Red));
+                Err(e)
+            }
+        }
+    }
+
+    fn parse_loop_control(&mut self) -> Result<Rc<AST>> {
+        let Token { kind, span, text, .. } = self.consume_any(&[TokenKind::Break, TokenKind::Continue], "Expected 'break' or 'continue'")?;
+        if self.loop_depth == 0 {
+            let e = ErrorReport::new(ErrorReportKind::SyntaxError, span.clone(), format!("`{}` Outside Loop", text))
+                .with_label(Label::new(span.clone()).with_message(format!("Can only {} inside a loop body", text)).with_color(Color::Red));
+            return Err(e);
+        }
+        let kind = if *kind == TokenKind::Break { ASTKind::Break } else { ASTKind::Continue };
+        Ok(Rc::new(AST::new(span.clone(), kind)))
+    }
+
+    /// A string literal, split into parts if it has any `${...}` in it. Each embedded expression is
+    /// lexed and parsed on its own, with its spans pointing back into the string.
+    fn parse_string(&mut self, token: RefToken<'a>) -> Result<Rc<AST>> {
+        let text = token.text;
+        let literal = || Rc::new(AST::new(token.span.clone(), ASTKind::StringLiteral(string_value(text))));
+        if is_triple_quoted(text) {
+            let value = string_value(text);
+            let value = match value.strip_prefix('\n').or_else(|| value.strip_prefix("\r\n")) {
+                Some(trimmed) if self.trim_leading_newline => trimmed.to_string(),
+                _ => value
+            };
+            return Ok(Rc::new(AST::new(token.span.clone(), ASTKind::StringLiteral(value))));
+        }
+        if text.starts_with('r') || !text.contains("${") {
+            return Ok(literal());
+        }
+        let body_end = text.len() - 1;
+        let mut parts = Vec::new();
+        let mut literal_start = 1;
+        let mut index = 1;
+        while index < body_end {
+            let rest = &text[index..];
+            if let Some(escaped) = rest.strip_prefix('\\') {
+                index += 1 + escaped.chars().next().map_or(0, char::len_utf8);
+            } else if let Some(inner) = rest.strip_prefix("${") {
+                if literal_start < index {
+                    parts.push(StringPart::Literal(unescape(&text[literal_start..index])));
+                }
+                let end = interpolation_end(inner).expect("Lexer produced an unterminated interpolation.");
+                let expr = self.parse_interpolation(&inner[..end], token.span.start + index + 2, &token.span.filename)?;
+                parts.push(StringPart::Expr(expr));
+                index += end + 3;
+                literal_start = index;
+            } else {
+                index += rest.chars().next().map_or(1, char::len_utf8);
+            }
+        }
+        if literal_start < body_end {
+            parts.push(StringPart::Literal(unescape(&text[literal_start..body_end])));
+        }
+        // Every `${` was escaped, so there is nothing to interpolate after all
+        if parts.iter().all(|part| matches!(part, StringPart::Literal(_))) {
+            return Ok(literal());
+        }
+        Ok(Rc::new(AST::new(token.span.clone(), ASTKind::InterpolatedString(parts))))
+    }
+
+    /// Parse the `source` of one `${...}`, which starts `offset` bytes into the file.
+    fn parse_interpolation(&mut self, source: &'a str, offset: usize, filename: &Arc<str>) -> Result<Rc<AST>> {
+        let mut lexer = Lexer::new(filename.clone(), source, self.reports.clone()).with_offset(offset);
+        lexer.lex_tokens();
+        self.had_error |= lexer.had_error;
+        let tokens = lexer.tokens;
+        if !lexer.had_error && tokens.iter().all(|token| token.kind == TokenKind::EOF) {
+            let span = Span::new(offset - 2, offset + source.len(), filename.clone());
+            let e = ErrorReport::new(ErrorReportKind::SyntaxError, span.clone(), "Expected an expression inside `${}`".to_string())
+                .with_label(Label::new(span).with_message("Empty interpolation").with_color(Color::Red))
+                .with_help("Write `\\${` for a literal `${`.".to_string());
+            return Err(e);
+        }
+        let mut parser = Parser::new(&tokens, self.reports.clone()).with_max_depth(self.max_depth.saturating_sub(self.depth));
+        // Share the scopes, so names used in the interpolation count as used
+        parser.scopes = std::mem::take(&mut self.scopes);
+        let expr = parser.parse_expression();
+        self.scopes = std::mem::take(&mut parser.scopes);
+        let expr = expr?;
+        self.had_error |= parser.had_error;
+        if !parser.check(TokenKind::EOF) {
+            let span = parser.current.span.clone();
+            let e = ErrorReport::new(ErrorReportKind::SyntaxError, span.clone(), "Expected `}` to end the interpolation".to_string())
+                .with_label(Label::new(span).with_message(format!("Unexpected `{}`", parser.current.text)).with_color(Color::Red))
+                .with_note("An interpolation holds a single expression.".to_string());
+            return Err(e);
+        }
+        Ok(expr)
+    }
+
+    /// An integer literal, negated if `minus` is the span of a `-` just before it. The sign is applied
+    /// before checking the value fits, since `-128i8` is in range and `128i8` isn't.
+    fn parse_integer(&mut self, base: Base, minus: Option<Span>) -> Result<Rc<AST>> {
+        let Token { text, .. } = self.current;
+        let sign = if minus.is_some() { -1 } else { 1 };
+        let span = &match minus {
+            Some(minus) => minus.extend(self.current.span.clone()),
+            None => self.current.span.clone()
+        };
+        self.advance();
+        let digits = &text[base.prefix_length()..];
+        // Neither `i` nor `u` is a digit in any base, so either one starts the type suffix
+        let (digits, suffix) = digits.split_at(digits.find(['i', 'u']).unwrap_or(digits.len()));
+        match i128::from_str_radix(&digits.replace('_', ""), base.radix()).map(|value| sign * value) {
+            Ok(value) if !suffix.is_empty() => {
+                let range = integer_suffix_range(suffix).expect("Lexer produced an unknown integer suffix.");
+                if !range.contains(&value) {
+                    let e = ErrorReport::new(ErrorReportKind::SyntaxError, span.clone(), "Literal Out Of Range For Type".to_string())
+                        .with_label(Label::new(span.clone()).with_message(format!("Does not fit in `{}`", suffix)).with_color(Color::Red))
+                        .with_note(format!("`{}` literals must be in `{}..={}`.", suffix, range.start(), range.end()));
+                    return Err(e);
+                }
+                self.integer_literal(span, value)
+            }
+            Ok(value) => self.integer_literal(span, value),
+            Err(error) if *error.kind() == IntErrorKind::Empty => {
+                let e = ErrorReport::new(ErrorReportKind::SyntaxError, span.clone(), "Invalid Integer Literal".to_string())
+                    .with_label(Label::new(span.clone()).with_message("Expected digits after the prefix").with_color(Color::Red));
+                Err(e)
             }
+            Err(_) => Err(Self::integer_too_large(span))
         }
     }
 
+    fn parse_float(&mut self) -> Result<Rc<AST>> {
+        let Token { span, text, .. } = self.current;
+        self.advance();
+        // An exponent is `e` and hex digits can't reach a float, so an `f` always starts the suffix
+        let (digits, suffix) = text.split_at(text.find('f').unwrap_or(text.len()));
+        let value = digits.replace('_', "").parse::<f64>().expect("Lexer produced an invalid float literal.");
+        if suffix == "f32" && (value as f32).is_infinite() {
+            let e = ErrorReport::new(ErrorReportKind::SyntaxError, span.clone(), "Literal Out Of Range For Type".to_string())
+                .with_label(Label::new(span.clone()).with_message("Does not fit in `f32`").with_color(Color::Red))
+                .with_note(format!("`f32` literals must be at most {:e} in magnitude.", f32::MAX));
+            return Err(e);
+        }
+        Ok(Rc::new(AST::new(span.clone(), ASTKind::FloatLiteral(value))))
+    }
+
+    /// Whatever its suffix allows, a literal still has to fit the interpreter's integers.
+    fn integer_literal(&self, span: &Span, value: i128) -> Result<Rc<AST>> {
+        let value = isize::try_from(value).map_err(|_| Self::integer_too_large(span))?;
+        Ok(Rc::new(AST::new(span.clone(), ASTKind::IntegerLiteral(value))))
+    }
+
+    fn integer_too_large(span: &Span) -> ErrorReport {
+        ErrorReport::new(ErrorReportKind::SyntaxError, span.clone(), "Integer Literal Too Large".to_string())
+            .with_label(Label::new(span.clone()).with_message(format!("Does not fit in {} bits", isize::BITS)).with_color(Color::Red))
+    }
+
     pub fn parse_atom(&mut self) -> Result<Rc<AST>> {
         match self.current {
-            Token { kind: TokenKind::StringLiteral, span, text, .. } => {
+            Token { kind: TokenKind::IntegerLiteral(base), .. } => self.parse_integer(*base, None),
+            Token { kind: TokenKind::FloatLiteral, .. } => self.parse_float(),
+            token @ Token { kind: TokenKind::StringLiteral, .. } => {
+                self.advance();
+                self.parse_string(token)
+            }
+            Token { kind: TokenKind::Identifier, span, text: "_", .. } => {
+                self.advance();
+                Ok(Rc::new(AST::new(span.clone(), ASTKind::Wildcard)))
+            }
+            Token { kind: TokenKind::Identifier, span, text, .. } => {
+                self.advance();
+                let name = self.interner.intern(text);
+                self.use_name(&name);
+                Ok(Rc::new(AST::new(span.clone(), ASTKind::Identifier(name))))
+            }
+            Token { kind: kind @ (TokenKind::True | TokenKind::False), span, .. } => {
+                self.advance();
+                Ok(Rc::new(AST::new(span.clone(), ASTKind::BoolLiteral(*kind == TokenKind::True))))
+            }
+            Token { kind: TokenKind::LeftBracket, span, .. } => {
                 self.advance();
-                Ok(Rc::new(AST::new(span.clone(), ASTKind::StringLiteral(text.to_string()))))
+                let elements = self.parse_list(TokenKind::RightBracket)?;
+                let end = self.consume_closing(TokenKind::RightBracket, span, "Expected ']' to close the array")?.span.clone();
+                let span = Span::merge(&[span.clone(), end]).expect("Brackets come from the same file");
+                Ok(Rc::new(AST::new(span, ASTKind::ArrayLiteral(elements))))
+            }
+            // `()` is the unit value, not an empty group
+            Token { kind: TokenKind::LeftParen, span, .. } if self.peek(0).kind == TokenKind::RightParen => {
+                let end = self.advance().span.clone();
+                self.advance();
+                Ok(Rc::new(AST::new(span.clone().extend(end), ASTKind::UnitLiteral)))
+            }
+            Token { kind: TokenKind::LeftParen, span, .. } => {
+                self.advance();
+                let inner = self.parse_expression()?;
+                let end = self.consume_closing(TokenKind::RightParen, span, "Expected ')' to close the group")?.span.clone();
+                Ok(Rc::new(AST::new(span.clone().extend(end), ASTKind::Grouping(inner))))
+            }
+            // `{ x: ...` can only be a record, since a block can never start with `x:`
+            Token { kind: TokenKind::LeftBrace, .. } if self.peek(0).kind == TokenKind::Identifier && self.peek(1).kind == TokenKind::Colon => self.parse_record(),
+            Token { kind: TokenKind::LeftBrace, .. } => self.parse_block(),
+            Token { kind: TokenKind::If, .. } => self.parse_if(),
+            Token { kind: TokenKind::While, .. } => self.parse_while(),
+            Token { kind: TokenKind::Match, .. } => self.parse_match(),
+            // `-` never gets here, parse_unary takes it as a negation
+            Token { kind, span, text, .. } if kind.binary_binding_power().is_some() => {
+                let e = ErrorReport::new(ErrorReportKind::SyntaxError, span.clone(), "Missing Left-Hand Operand".to_string())
+                    .with_label(Label::new(span.clone()).with_message("Expected an expression before this").with_color(Color::Red))
+                    .with_note(format!("Binary operator `{}` has no left-hand operand.", text));
+                Err(e)
             }
             Token { kind: TokenKind::EOF, span, .. } => {
-                let e = ErrorReport::new(ErrorReportKind::Custom, span.clone(), "Unexpected EOF".to_string());
+                let e = ErrorReport::new(ErrorReportKind::Custom, span.clone(), "Unexpected EOF".to_string())
+                    .with_label(Label::new(span.clone()).with_message("Expected an expression").with_color(Color::Red));
                 Err(e)
             }
             Token { kind, span, .. } => {
@@ -94,4 +928,505 @@ impl<'a> Parser<'a> {
             }
         }
     }
-}
\ No newline at end of file
+}
+
+/// The operator a token stands for, which must be one with a binary binding power.
+fn binary_op(kind: &TokenKind) -> BinaryOp {
+    match kind {
+        TokenKind::PipePipe => BinaryOp::Or,
+        TokenKind::AmpAmp => BinaryOp::And,
+        TokenKind::EqualsEquals => BinaryOp::Eq,
+        TokenKind::BangEquals => BinaryOp::NotEq,
+        TokenKind::Less => BinaryOp::Less,
+        TokenKind::LessEquals => BinaryOp::LessEq,
+        TokenKind::Greater => BinaryOp::Greater,
+        TokenKind::GreaterEquals => BinaryOp::GreaterEq,
+        TokenKind::Plus => BinaryOp::Add,
+        TokenKind::Minus => BinaryOp::Sub,
+        TokenKind::Star => BinaryOp::Mul,
+        TokenKind::Slash => BinaryOp::Div,
+        _ => unreachable!("{:?} is not a binary operator.", kind)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(source: &str) -> (Option<std::rc::Rc<crate::ast::AST>>, Vec<ErrorReport>) {
+        crate::parse(std::sync::Arc::from("test"), source)
+    }
+
+    /// Parse `source`, which must not make any report.
+    fn parse_ok(source: &str) -> Rc<AST> {
+        let (ast, reports) = parse(source);
+        assert!(reports.is_empty(), "{:?}", titles(&reports));
+        ast.unwrap()
+    }
+
+    /// The first statement of a program.
+    fn first(ast: &AST) -> &AST {
+        match ast.kind() {
+            ASTKind::Block(statements) => &statements[0],
+            _ => unreachable!("A program is always a block")
+        }
+    }
+
+    fn titles(reports: &[ErrorReport]) -> Vec<&str> {
+        reports.iter().map(ErrorReport::title).collect()
+    }
+
+    #[test]
+    fn recovers_to_report_every_broken_statement() {
+        let (ast, reports) = parse("let x = ;\nlet y = 1;\nlet = 2;\nprint(y);");
+        assert!(ast.is_none());
+        assert_eq!(titles(&reports), ["UnexpectedToken[E0003]: SemiColon", "UnexpectedToken[E0003]: got Equals"]);
+    }
+
+    #[test]
+    fn stray_closing_braces_are_reported_on_their_own() {
+        let (_, reports) = parse("}}} let x = ;");
+        assert_eq!(titles(&reports), ["SyntaxError[E0001]: Unbalanced Delimiter", "UnexpectedToken[E0003]: SemiColon"]);
+        assert_eq!((reports[0].span().start, reports[0].span().end), (0, 2));
+    }
+
+    #[test]
+    fn recovery_stays_inside_the_block() {
+        let (_, reports) = parse("fn f() { let a = ; } let b = ;");
+        assert_eq!(reports.len(), 2, "{:?}", titles(&reports));
+    }
+
+    #[test]
+    fn gives_up_after_too_many_errors() {
+        let reports = Rc::new(RefCell::new(Vec::new()));
+        let (tokens, _) = Lexer::lex_str(Arc::from("test"), "let = 1; let = 2; let = 3; let = 4;");
+        let mut parser = Parser::new(&tokens, reports.clone()).with_max_errors(2);
+        assert!(parser.parse().is_none());
+        assert_eq!(reports.take().iter().filter(|report| report.is_error()).count(), 3);
+    }
+
+    #[test]
+    fn zero_max_errors_is_no_limit() {
+        let reports = Rc::new(RefCell::new(Vec::new()));
+        let (tokens, _) = Lexer::lex_str(Arc::from("test"), "let = 1; let = 2; let = 3; let = 4;");
+        let mut parser = Parser::new(&tokens, reports.clone()).with_max_errors(0);
+        parser.parse();
+        assert!(parser.had_error);
+        assert_eq!(reports.take().iter().filter(|report| report.is_error()).count(), 4);
+    }
+
+    #[test]
+    fn unused_let_is_a_warning() {
+        let (ast, reports) = parse("fn f() { let x = 1; 2 }");
+        assert!(ast.is_some(), "a warning must not fail the parse");
+        assert_eq!(titles(&reports), ["NameError[E0006]: Unused variable `x`"]);
+        assert_eq!(reports[0].severity, Severity::Warning);
+        assert_eq!((reports[0].span().start, reports[0].span().end), (13, 13));
+    }
+
+    #[test]
+    fn used_and_underscored_lets_are_not_warned_about() {
+        let (_, reports) = parse("fn f(a) { let x = a; let _y = 1; let z = 2; print(\"${z}\"); x }");
+        assert!(reports.is_empty(), "{:?}", titles(&reports));
+    }
+
+    #[test]
+    fn top_level_lets_are_never_unused() {
+        let (_, reports) = parse("let x = 1;");
+        assert!(reports.is_empty());
+    }
+
+    #[test]
+    fn parses_bool_literals() {
+        let ast = parse_ok("true; false; trueish");
+        let ASTKind::Block(statements) = ast.kind() else { unreachable!() };
+        assert!(matches!(statements[0].kind(), ASTKind::BoolLiteral(true)));
+        assert!(matches!(statements[1].kind(), ASTKind::BoolLiteral(false)));
+        assert!(matches!(statements[2].kind(), ASTKind::Identifier(name) if &**name == "trueish"));
+    }
+
+    #[test]
+    fn parses_if_with_and_without_else() {
+        let ast = parse_ok("if a { 1 } else { 2 }");
+        assert!(matches!(first(&ast).kind(), ASTKind::If { else_branch: Some(_), .. }));
+        let ast = parse_ok("if a { 1 }");
+        assert!(matches!(first(&ast).kind(), ASTKind::If { else_branch: None, .. }));
+    }
+
+    #[test]
+    fn parses_a_function_declaration() {
+        let ast = parse_ok("fn add(a, b) { a + b }");
+        let ASTKind::Function { name, params, body } = first(&ast).kind() else { panic!("Expected a function") };
+        assert_eq!(&**name, "add");
+        assert_eq!(params.iter().map(|param| &**param).collect::<Vec<_>>(), ["a", "b"]);
+        assert!(matches!(body.kind(), ASTKind::Block(statements) if statements.len() == 1));
+    }
+
+    #[test]
+    fn parses_a_function_without_parameters() {
+        let ast = parse_ok("fn nothing() { }");
+        assert!(matches!(first(&ast).kind(), ASTKind::Function { params, .. } if params.is_empty()));
+    }
+
+    #[test]
+    fn function_without_a_name_is_an_error() {
+        let (_, reports) = parse("fn (a) { a }");
+        assert_eq!(titles(&reports), ["UnexpectedToken[E0003]: got LeftParen"]);
+        assert_eq!(reports[0].labels()[0].message(), Some("Expected a function name after 'fn'"));
+    }
+
+    #[test]
+    fn unbalanced_parameter_list_points_at_the_open_paren() {
+        let (_, reports) = parse("fn f(a, b { a }");
+        assert_eq!(titles(&reports), ["SyntaxError[E0001]: Unbalanced Delimiter"]);
+        let labels: Vec<_> = reports[0].labels().iter().map(|label| (label.span().start, label.message().unwrap())).collect();
+        assert_eq!(labels, [(4, "Opened here"), (10, "Expected ')' to close the parameter list")]);
+    }
+
+    #[test]
+    fn return_outside_a_function_is_an_error() {
+        let (_, reports) = parse("return 1;");
+        assert_eq!(titles(&reports), ["SyntaxError[E0001]: Return Outside Function"]);
+        assert_eq!((reports[0].span().start, reports[0].span().end), (0, 5));
+    }
+
+    #[test]
+    fn loop_control_outside_a_loop_is_an_error() {
+        let (_, reports) = parse("break; continue;");
+        assert_eq!(titles(&reports), ["SyntaxError[E0001]: `break` Outside Loop", "SyntaxError[E0001]: `continue` Outside Loop"]);
+        assert_eq!(reports[1].labels()[0].message(), Some("Can only continue inside a loop body"));
+    }
+
+    #[test]
+    fn loops_outside_a_function_do_not_count_inside_it() {
+        let (_, reports) = parse("while true { fn f() { break; } }");
+        assert_eq!(titles(&reports), ["SyntaxError[E0001]: `break` Outside Loop"]);
+    }
+
+    #[test]
+    fn lexer_and_parser_share_one_report_list() {
+        let reports = Rc::new(RefCell::new(Vec::new()));
+        let mut lexer = crate::lexer::Lexer::new(std::sync::Arc::from("test"), "let x = 0b12;\nlet = 1;", reports.clone());
+        lexer.lex_tokens();
+        Parser::new(&lexer.tokens, reports.clone()).parse();
+        assert_eq!(titles(&reports.take()), ["SyntaxError[E0001]: Invalid Integer Literal", "UnexpectedToken[E0003]: got Equals"]);
+    }
+
+    #[test]
+    fn literal_spans_can_be_read_back() {
+        let ast = parse_ok("let answer = 42;");
+        let ASTKind::Let { value, .. } = first(&ast).kind() else { panic!("Expected a let") };
+        assert!(matches!(value.kind(), ASTKind::IntegerLiteral(42)));
+        assert_eq!((value.span().start, value.span().end), (13, 14));
+    }
+
+    #[test]
+    fn deep_parentheses_are_reported() {
+        // a low limit, as every level recurses and test threads have a small stack
+        let source = format!("{}1{};", "(".repeat(40), ")".repeat(40));
+        let (tokens, _) = crate::lexer::Lexer::lex_str(std::sync::Arc::from("test"), &source);
+        let reports = Rc::new(RefCell::new(Vec::new()));
+        let mut parser = Parser::new(&tokens, reports.clone()).with_max_depth(32);
+        parser.parse();
+        assert!(parser.had_error);
+        assert_eq!(titles(&reports.take()), ["SyntaxError[E0001]: Expression Nested Too Deeply"]);
+    }
+
+    #[test]
+    fn long_binary_chains_count_towards_the_depth() {
+        let source = vec!["1"; 3000].join(" + ");
+        let (ast, reports) = parse(&source);
+        assert!(ast.is_none());
+        assert_eq!(titles(&reports), ["SyntaxError[E0001]: Expression Nested Too Deeply"]);
+    }
+
+    #[test]
+    fn long_postfix_and_cast_chains_count_towards_the_depth() {
+        for source in [format!("f{}", "()".repeat(300)), format!("x{}", " as int".repeat(300)), format!("a{}", ".b".repeat(300))] {
+            let (_, reports) = parse(&source);
+            assert_eq!(titles(&reports), ["SyntaxError[E0001]: Expression Nested Too Deeply"], "{}", source);
+        }
+    }
+
+    #[test]
+    fn depth_is_restored_after_a_chain() {
+        // each statement is under the limit on its own, but not all of them together
+        let statement = vec!["1"; 200].join(" + ");
+        let source = format!("{0};\n{0};\n{0};", statement);
+        parse_ok(&source);
+    }
+
+    #[test]
+    fn leading_operator_has_a_tailored_message() {
+        let (_, reports) = parse("* 3");
+        assert_eq!(titles(&reports), ["SyntaxError[E0001]: Missing Left-Hand Operand"]);
+        assert_eq!(reports[0].note(), Some("Binary operator `*` has no left-hand operand."));
+    }
+
+    #[test]
+    fn member_access_chains_to_the_left() {
+        let ast = parse_ok("a.b.c;");
+        let ASTKind::Member { object, name } = first(&ast).kind() else { panic!("Expected a member access") };
+        assert_eq!(&**name, "c");
+        let ASTKind::Member { object, name } = object.kind() else { panic!("Expected a member access") };
+        assert_eq!(&**name, "b");
+        assert!(matches!(object.kind(), ASTKind::Identifier(name) if &**name == "a"));
+        assert_eq!((first(&ast).span().start, first(&ast).span().end), (0, 4));
+    }
+
+    #[test]
+    fn ranges_may_leave_out_either_bound() {
+        for (source, has_start, has_end) in [("1..3;", true, true), ("..3;", false, true), ("1..;", true, false)] {
+            let ast = parse_ok(source);
+            let ASTKind::Range { start, end } = first(&ast).kind() else { panic!("Expected a range for {}", source) };
+            assert_eq!((start.is_some(), end.is_some()), (has_start, has_end), "{}", source);
+        }
+    }
+
+    #[test]
+    fn trailing_commas_are_allowed() {
+        let ast = parse_ok("[1, 2, 3,];");
+        assert!(matches!(first(&ast).kind(), ASTKind::ArrayLiteral(elements) if elements.len() == 3));
+        let ast = parse_ok("f(a, b,);");
+        assert!(matches!(first(&ast).kind(), ASTKind::Call { args, .. } if args.len() == 2));
+    }
+
+    #[test]
+    fn double_commas_are_rejected() {
+        let (_, reports) = parse("[1,,2];");
+        assert_eq!(titles(&reports), ["SyntaxError[E0001]: Unexpected Comma"]);
+        assert_eq!(reports[0].labels()[0].message(), Some("Expected an expression between the commas"));
+        assert_eq!((reports[0].span().start, reports[0].span().end), (3, 3));
+    }
+
+    #[test]
+    fn every_leading_binary_operator_gets_the_hint() {
+        for op in ["+", "*", "/", "<", "<=", ">", ">=", "==", "!=", "&&", "||"] {
+            let (_, reports) = parse(&format!("{} 3;", op));
+            assert_eq!(titles(&reports), ["SyntaxError[E0001]: Missing Left-Hand Operand"], "{}", op);
+            assert_eq!(reports[0].note(), Some(format!("Binary operator `{}` has no left-hand operand.", op).as_str()));
+        }
+    }
+
+    #[test]
+    fn redefining_in_the_same_scope_is_allowed() {
+        parse_ok("let x = 1; let x = x + 1; x;");
+        let (_, reports) = parse("fn f() { let x = 1; let x = 2; x }");
+        // the first `x` is shadowed before it is ever read
+        assert_eq!(titles(&reports), ["NameError[E0006]: Unused variable `x`"]);
+        assert_eq!(reports[0].span().start, 13);
+    }
+
+    #[test]
+    fn duplicate_field_points_at_both_definitions() {
+        let (_, reports) = parse("let r = { a: 1, a: 2 };");
+        assert_eq!(titles(&reports), ["SyntaxError[E0001]: Duplicate field `a`"]);
+        let labels: Vec<_> = reports[0].labels().iter().map(|label| (label.span().start, label.message())).collect();
+        assert_eq!(labels, [(16, Some("Defined again here")), (10, Some("First defined here"))]);
+    }
+
+    #[test]
+    fn recovery_skips_braces_the_broken_statement_opened() {
+        let (_, reports) = parse("let r = { a: 1, a: 2 };\nlet y = ;\nlet s = { b: }; let t = 1;");
+        assert_eq!(titles(&reports), ["SyntaxError[E0001]: Duplicate field `a`", "UnexpectedToken[E0003]: SemiColon", "UnexpectedToken[E0003]: RightBrace"]);
+    }
+
+    #[test]
+    fn consume_any_takes_any_listed_kind() {
+        let (tokens, _) = crate::lexer::Lexer::lex_str(std::sync::Arc::from("test"), "+ -");
+        let mut parser = Parser::new(&tokens, Rc::new(RefCell::new(Vec::new())));
+        assert!(parser.check(TokenKind::Plus));
+        assert!(!parser.check_any(&[TokenKind::Minus, TokenKind::Star]));
+        let kinds = [TokenKind::Plus, TokenKind::Minus];
+        assert_eq!(parser.consume_any(&kinds, "Expected a sign").unwrap().kind, TokenKind::Plus);
+        assert_eq!(parser.consume_any(&kinds, "Expected a sign").unwrap().kind, TokenKind::Minus);
+        assert!(parser.check(TokenKind::EOF));
+    }
+
+    #[test]
+    fn consume_any_lists_the_expected_kinds() {
+        let (tokens, _) = crate::lexer::Lexer::lex_str(std::sync::Arc::from("test"), "1");
+        let mut parser = Parser::new(&tokens, Rc::new(RefCell::new(Vec::new())));
+        let error = parser.consume_any(&[TokenKind::Plus, TokenKind::Minus], "Expected a sign").unwrap_err();
+        assert_eq!(error.title(), "UnexpectedToken[E0003]: got IntegerLiteral(Dec)");
+        assert_eq!(error.labels()[0].message(), Some("Expected a sign"));
+        assert_eq!(error.note(), Some("Expected one of Plus, Minus."));
+        // a single kind has nothing to list
+        assert_eq!(parser.consume(TokenKind::Plus, "Expected a sign").unwrap_err().note(), None);
+    }
+
+    #[test]
+    fn missing_semicolon_points_at_where_it_goes() {
+        let (_, reports) = parse("let x = 1 let y = 2");
+        assert_eq!(titles(&reports), ["UnexpectedToken[E0003]: Expected end of line but got `let`"]);
+        let labels = reports[0].labels();
+        assert_eq!(labels.len(), 2);
+        assert_eq!((labels[0].span().start, labels[0].span().end), (10, 12));
+        assert_eq!(labels[0].message(), Some("Expected `;` before this"));
+        assert_eq!((labels[1].span().start, labels[1].span().end), (9, 9));
+        assert_eq!(labels[1].message(), Some("insert `;` here"));
+    }
+
+    #[test]
+    fn patterns_must_be_literals() {
+        let (_, reports) = parse("match 1 { x => 1 }");
+        assert_eq!(titles(&reports), ["SyntaxError[E0001]: Invalid Pattern"]);
+        let (_, reports) = parse("match 1 { -\"a\" => 1 }");
+        assert_eq!(reports[0].labels()[0].message(), Some("Only numbers can be negated in a pattern"));
+    }
+
+    #[test]
+    fn else_if_nests_in_the_else_branch() {
+        let ast = parse_ok("if a { 1; } else if b { 2; } else { 3; }");
+        let ASTKind::If { else_branch: Some(else_branch), .. } = first(&ast).kind() else { panic!("Expected an if with an else") };
+        let ASTKind::If { cond, else_branch: Some(last), .. } = else_branch.kind() else { panic!("Expected an else if") };
+        assert!(matches!(cond.kind(), ASTKind::Identifier(name) if &**name == "b"));
+        assert!(matches!(last.kind(), ASTKind::Block(_)));
+        assert_eq!(first(&ast).to_string(), "if a { 1; } else if b { 2; } else { 3; }");
+    }
+
+    #[test]
+    fn long_else_if_chains_parse() {
+        let source = format!("if a {{ 0 }}{} else {{ 0 }}", " else if a { 0 }".repeat(20));
+        parse_ok(&source);
+    }
+
+    #[test]
+    fn unexpected_eof_points_at_the_end_of_the_file() {
+        let (_, reports) = parse("let x =");
+        assert_eq!(titles(&reports), ["Unexpected EOF"]);
+        assert_eq!((reports[0].span().start, reports[0].span().end), (7, 7));
+        let (_, reports) = parse("let x =\n");
+        assert_eq!((reports[0].span().start, reports[0].span().end), (8, 8));
+    }
+
+    #[test]
+    fn unexpected_eof_on_empty_input_points_at_the_start() {
+        let tokens = [Token::new(TokenKind::EOF, Span::new(0, 0, std::sync::Arc::from("test")), "")];
+        let mut parser = Parser::new(&tokens, Rc::new(RefCell::new(Vec::new())));
+        let Err(error) = parser.parse_expression() else { panic!("Expected an error") };
+        assert_eq!(error.title(), "Unexpected EOF");
+        assert_eq!((error.span().start, error.span().end), (0, 0));
+    }
+
+    #[test]
+    fn interpolation_holds_one_expression() {
+        let (_, reports) = parse("\"${1 2}\";");
+        assert_eq!(titles(&reports), ["SyntaxError[E0001]: Expected `}` to end the interpolation"]);
+        // spans inside the interpolation are offsets into the whole file
+        assert_eq!((reports[0].span().start, reports[0].span().end), (5, 5));
+    }
+
+    #[test]
+    fn over_long_token_in_an_interpolation_is_spanned_in_the_file() {
+        let source = format!("// {}\n\"${{{}}}\";", "x".repeat(6000), "a".repeat(5000));
+        let (_, reports) = parse(&source);
+        assert_eq!(reports[0].title(), "SyntaxError[E0001]: Token Too Long");
+        assert_eq!((reports[0].span().start, reports[0].span().end), (6007, 6007));
+    }
+
+    #[test]
+    fn empty_interpolation_is_reported() {
+        let (_, reports) = parse("\"a${ }b\";");
+        assert_eq!(titles(&reports), ["SyntaxError[E0001]: Expected an expression inside `${}`"]);
+        assert_eq!((reports[0].span().start, reports[0].span().end), (2, 5));
+    }
+
+    #[test]
+    fn triple_string_leading_newline_is_trimmed_by_default() {
+        let source = "\"\"\"\nline\"\"\";";
+        let ast = parse_ok(source);
+        assert!(matches!(first(&ast).kind(), ASTKind::StringLiteral(val) if val == "line"));
+        let (tokens, _) = crate::lexer::Lexer::lex_str(std::sync::Arc::from("test"), source);
+        let ast = Parser::new(&tokens, Rc::new(RefCell::new(Vec::new()))).with_trim_leading_newline(false).parse().unwrap();
+        assert!(matches!(first(&ast).kind(), ASTKind::StringLiteral(val) if val == "\nline"));
+    }
+
+    #[test]
+    fn empty_parens_are_unit() {
+        let ast = parse_ok("( );");
+        assert!(matches!(first(&ast).kind(), ASTKind::UnitLiteral));
+        assert_eq!((first(&ast).span().start, first(&ast).span().end), (0, 2));
+        assert!(matches!(first(&parse_ok("(1);")).kind(), ASTKind::Grouping(_)));
+    }
+
+    #[test]
+    fn suffixed_literal_must_fit_its_type() {
+        let (_, reports) = parse("256u8;");
+        assert_eq!(titles(&reports), ["SyntaxError[E0001]: Literal Out Of Range For Type"]);
+        assert_eq!(reports[0].note(), Some("`u8` literals must be in `0..=255`."));
+        assert!(matches!(first(&parse_ok("255u8;")).kind(), ASTKind::IntegerLiteral(255)));
+        assert!(matches!(first(&parse_ok("0x7Fi8;")).kind(), ASTKind::IntegerLiteral(127)));
+        assert_eq!(titles(&parse("128i8;").1), ["SyntaxError[E0001]: Literal Out Of Range For Type"]);
+    }
+
+    #[test]
+    fn negative_literal_is_checked_with_its_sign() {
+        let ast = parse_ok("-128i8;");
+        assert!(matches!(first(&ast).kind(), ASTKind::IntegerLiteral(-128)));
+        assert_eq!((first(&ast).span().start, first(&ast).span().end), (0, 5));
+        let (_, reports) = parse("-129i8;");
+        assert_eq!(titles(&reports), ["SyntaxError[E0001]: Literal Out Of Range For Type"]);
+        assert_eq!((reports[0].span().start, reports[0].span().end), (0, 5));
+        assert_eq!(titles(&parse("-1u8;").1), ["SyntaxError[E0001]: Literal Out Of Range For Type"]);
+        let ast = parse_ok(&format!("{};", isize::MIN));
+        assert!(matches!(first(&ast).kind(), ASTKind::IntegerLiteral(isize::MIN)));
+    }
+
+    #[test]
+    fn negative_pattern_is_checked_with_its_sign() {
+        let ast = parse_ok("match 1 { -128i8 => 1, _ => 0 }");
+        let ASTKind::Match { arms, .. } = first(&ast).kind() else { panic!("Expected a match") };
+        assert!(matches!(arms[0].0, Pattern::Int(-128)));
+        assert_eq!(titles(&parse("match 1 { -129i8 => 1, _ => 0 }").1), ["SyntaxError[E0001]: Literal Out Of Range For Type"]);
+    }
+
+    #[test]
+    fn negated_literal_with_a_postfix_stays_a_negation() {
+        let ast = parse_ok("let a = [1]; -a[0]; -1.5;");
+        let ASTKind::Block(statements) = ast.kind() else { unreachable!() };
+        assert!(matches!(statements[1].kind(), ASTKind::Unary { .. }));
+        assert!(matches!(statements[2].kind(), ASTKind::Unary { .. }));
+    }
+
+    #[test]
+    fn unsuffixed_literal_only_has_to_fit_an_int() {
+        assert!(matches!(first(&parse_ok("256;")).kind(), ASTKind::IntegerLiteral(256)));
+        let (_, reports) = parse(&format!("{}0;", isize::MAX));
+        assert_eq!(titles(&reports), ["SyntaxError[E0001]: Integer Literal Too Large"]);
+    }
+
+    #[test]
+    fn f32_literal_must_fit() {
+        let (_, reports) = parse("1e39f32;");
+        assert_eq!(titles(&reports), ["SyntaxError[E0001]: Literal Out Of Range For Type"]);
+        parse_ok("1e39f64;");
+    }
+
+    #[test]
+    fn exponent_with_a_float_suffix_is_a_float() {
+        assert!(matches!(first(&parse_ok("1e3f64;")).kind(), ASTKind::FloatLiteral(val) if *val == 1000.0));
+        assert!(matches!(first(&parse_ok("2.5e-1f32;")).kind(), ASTKind::FloatLiteral(val) if *val == 0.25));
+    }
+
+    #[test]
+    fn every_base_parses_to_its_value() {
+        for (source, value) in [("0b1010;", 10), ("0o17;", 15), ("0xff;", 255), ("0xFF;", 255), ("1_000;", 1000), ("0b1111_0000u8;", 240)] {
+            assert!(matches!(first(&parse_ok(source)).kind(), ASTKind::IntegerLiteral(val) if *val == value), "{}", source);
+        }
+    }
+
+    #[test]
+    fn prefix_without_digits_is_an_error() {
+        let (_, reports) = parse("0x;");
+        assert_eq!(titles(&reports), ["SyntaxError[E0001]: Invalid Integer Literal"]);
+    }
+
+    #[test]
+    fn negation_binds_tighter_than_binary_operators() {
+        let ast = parse_ok("-a * b;");
+        let ASTKind::Binary { op: BinaryOp::Mul, lhs, .. } = first(&ast).kind() else { panic!("Expected a multiplication") };
+        assert!(matches!(lhs.kind(), ASTKind::Unary { op: UnaryOp::Neg, .. }));
+        assert_eq!((lhs.span().start, lhs.span().end), (0, 1));
+    }
+}
@@ -0,0 +1,126 @@
+use std::cell::RefCell;
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::sync::Arc;
+use ariadne::Color;
+use crate::ast::{AST, ASTKind};
+use crate::error::{ErrorReport, ErrorReportKind, Label, SourceCache};
+use crate::lexer::Lexer;
+use crate::parser::Parser;
+
+/// Loads every `import` in a program, replacing it with the statements of the imported file. Paths
+/// are relative to the file doing the importing, and each file is only spliced in the first time it
+/// is imported. Every file read is added to the source cache, so reports can point into it.
+pub struct Resolver<'s> {
+    sources: &'s mut SourceCache,
+    reports: Rc<RefCell<Vec<ErrorReport>>>,
+    /// Files currently being resolved, outermost first, to catch a file that imports itself. Each
+    /// is kept with the name it was imported by, for reports.
+    stack: Vec<(PathBuf, Arc<str>)>,
+    imported: HashSet<PathBuf>,
+    pub had_error: bool
+}
+
+impl<'s> Resolver<'s> {
+    pub fn new(sources: &'s mut SourceCache, reports: Rc<RefCell<Vec<ErrorReport>>>) -> Self {
+        Resolver {
+            sources,
+            reports,
+            stack: Vec::new(),
+            imported: HashSet::new(),
+            had_error: false
+        }
+    }
+
+    /// Resolve the imports of a parsed program, whose top level is a block.
+    pub fn resolve(&mut self, ast: Rc<AST>) -> Rc<AST> {
+        let ASTKind::Block(statements) = &ast.kind else { return ast; };
+        // Input from stdin has no path, so it can't be imported again
+        let path = Path::new(&*ast.span.filename).canonicalize().ok();
+        if let Some(path) = &path {
+            self.stack.push((path.clone(), ast.span.filename.clone()));
+            self.imported.insert(path.clone());
+        }
+        let statements = self.resolve_statements(statements);
+        if path.is_some() {
+            self.stack.pop();
+        }
+        Rc::new(AST::new(ast.span.clone(), ASTKind::Block(statements)))
+    }
+
+    fn resolve_statements(&mut self, statements: &[Rc<AST>]) -> Vec<Rc<AST>> {
+        let mut resolved = Vec::with_capacity(statements.len());
+        for statement in statements {
+            match &statement.kind {
+                ASTKind::Import(path) => resolved.extend(self.import(statement, path)),
+                _ => resolved.push(statement.clone())
+            }
+        }
+        resolved
+    }
+
+    /// The statements of the file imported by `node`, or none if it was already imported or failed to load.
+    fn import(&mut self, node: &AST, path: &str) -> Vec<Rc<AST>> {
+        let relative_to = Path::new(&*node.span.filename).parent().unwrap_or(Path::new(""));
+        let joined = relative_to.join(path);
+        let canonical = match joined.canonicalize() {
+            Ok(canonical) => canonical,
+            Err(error) => {
+                let e = ErrorReport::new(ErrorReportKind::ImportError, node.span.clone(), format!("Could not find `{}`", path))
+                    .with_label(Label::new(node.span.clone()).with_message(format!("Looked for {}", joined.display())).with_color(Color::Red))
+                    .with_note(format!("{}.", error));
+                self.push_report(e);
+                return Vec::new();
+            }
+        };
+        let filename: Arc<str> = Arc::from(joined.to_string_lossy());
+        if let Some(position) = self.stack.iter().position(|(importing, _)| *importing == canonical) {
+            let chain = self.stack[position..].iter()
+                .map(|(_, name)| name)
+                .chain(std::iter::once(&filename))
+                .map(|name| format!("`{}`", name))
+                .collect::<Vec<_>>()
+                .join(" -> ");
+            let e = ErrorReport::new(ErrorReportKind::ImportError, node.span.clone(), format!("Circular import of `{}`", path))
+                .with_label(Label::new(node.span.clone()).with_message("This file is already being imported").with_color(Color::Red))
+                .with_note(format!("The imports form a cycle: {}.", chain));
+            self.push_report(e);
+            return Vec::new();
+        }
+        if !self.imported.insert(canonical.clone()) {
+            return Vec::new();
+        }
+        let contents = match std::fs::read_to_string(&canonical) {
+            Ok(contents) => contents,
+            Err(error) => {
+                let e = ErrorReport::new(ErrorReportKind::ImportError, node.span.clone(), format!("Could not read `{}`", path))
+                    .with_label(Label::new(node.span.clone()).with_message(error.to_string()).with_color(Color::Red));
+                self.push_report(e);
+                return Vec::new();
+            }
+        };
+        self.sources.insert(filename.clone(), &contents);
+
+        let mut lexer = Lexer::new(filename.clone(), &contents, self.reports.clone());
+        lexer.lex_tokens();
+        if lexer.had_error {
+            self.had_error = true;
+            return Vec::new();
+        }
+        let mut parser = Parser::new(&lexer.tokens, self.reports.clone());
+        let ast = parser.parse();
+        self.had_error |= parser.had_error;
+        let Some(ASTKind::Block(statements)) = ast.as_ref().map(|ast| &ast.kind) else { return Vec::new(); };
+
+        self.stack.push((canonical, filename));
+        let statements = self.resolve_statements(statements);
+        self.stack.pop();
+        statements
+    }
+
+    fn push_report(&mut self, report: ErrorReport) {
+        self.had_error |= report.is_error();
+        self.reports.borrow_mut().push(report);
+    }
+}
@@ -28,13 +28,95 @@ impl Span {
     pub fn location(index: usize, filename: std::sync::Arc<str>) -> Self {
         Self::new(index, index, filename)
     }
+    pub fn same_file(&self, other: &Span) -> bool {
+        std::sync::Arc::ptr_eq(&self.filename, &other.filename) || self.filename == other.filename
+    }
+
+    /// Whether the byte at `index` is inside this span. `end` is inclusive, so this is the same as
+    /// `start <= index < end + 1`, the half-open range ariadne sees.
+    pub fn contains(&self, index: usize) -> bool {
+        self.start <= index && index <= self.end
+    }
+
+    /// Whether the two spans share at least one byte. Spans in different files never overlap.
+    pub fn overlaps(&self, other: &Span) -> bool {
+        self.same_file(other) && self.start <= other.end && other.start <= self.end
+    }
+
+    /// Shrink the span to leave out whitespace at either end of the text it covers in `source`, so
+    /// a label underlines the text and not the blanks around it. A span covering nothing but
+    /// whitespace, or reaching past the end of `source`, is returned unchanged.
+    pub fn trim(&self, source: &str) -> Span {
+        let Some(text) = source.get(self.start..=self.end) else { return self.clone(); };
+        let leading = text.len() - text.trim_start().len();
+        if leading == text.len() {
+            return self.clone();
+        }
+        Span {
+            start: self.start + leading,
+            end: self.start + text.trim_end().len() - 1,
+            filename: self.filename.clone()
+        }
+    }
+
     pub fn extend(self, other: Span) -> Self {
+        debug_assert!(self.same_file(&other), "Cannot extend a span in {} with a span in {}", self.filename, other.filename);
         Span {
             start: self.start,
             end: other.end,
             filename: self.filename
         }
     }
+
+    /// Like `extend`, but returns `None` instead of mixing spans from different files.
+    pub fn try_extend(self, other: Span) -> Option<Self> {
+        if self.same_file(&other) {
+            Some(self.extend(other))
+        } else {
+            None
+        }
+    }
+
+    /// The smallest span covering all of `spans`, or `None` if there are none or they are in different files.
+    pub fn merge(spans: &[Span]) -> Option<Self> {
+        let (first, rest) = spans.split_first()?;
+        let mut merged = first.clone();
+        for span in rest {
+            if !merged.same_file(span) {
+                return None;
+            }
+            merged.start = merged.start.min(span.start);
+            merged.end = merged.end.max(span.end);
+        }
+        Some(merged)
+    }
+}
+
+/// Converts byte offsets into 1-based line and column numbers for one source file.
+pub struct LineIndex<'a> {
+    source: &'a str,
+    line_starts: Vec<usize>
+}
+
+impl<'a> LineIndex<'a> {
+    pub fn new(source: &'a str) -> Self {
+        let line_starts = std::iter::once(0)
+            .chain(source.match_indices('\n').map(|(i, _)| i + 1))
+            .collect();
+        LineIndex {
+            source,
+            line_starts
+        }
+    }
+
+    /// Columns count chars, not bytes, so they line up with what an editor shows.
+    pub fn line_col(&self, offset: usize) -> (usize, usize) {
+        let line = self.line_starts.partition_point(|start| *start <= offset) - 1;
+        let line_start = self.line_starts[line];
+        let offset = offset.min(self.source.len());
+        let column = self.source.get(line_start..offset).map_or(offset - line_start, |text| text.chars().count());
+        (line + 1, column + 1)
+    }
 }
 
 impl std::fmt::Display for Span {
@@ -42,3 +124,90 @@ impl std::fmt::Display for Span {
         write!(f, "{}:{}:{}", self.filename, self.start, self.end)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+
+    #[test]
+    fn extend_keeps_the_start_and_takes_the_end() {
+        let file: Arc<str> = Arc::from("test");
+        let span = Span::new(2, 4, file.clone()).extend(Span::new(7, 9, file));
+        assert_eq!((span.start, span.end), (2, 9));
+    }
+
+    #[test]
+    fn try_extend_refuses_spans_in_other_files() {
+        let span = Span::new(2, 4, Arc::from("a"));
+        assert!(span.clone().try_extend(Span::new(7, 9, Arc::from("b"))).is_none());
+        // Same name in a different allocation is still the same file
+        assert!(span.try_extend(Span::new(7, 9, Arc::from("a"))).is_some());
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "Cannot extend a span in a with a span in b")]
+    fn extend_across_files_is_caught() {
+        let _ = Span::new(2, 4, Arc::from("a")).extend(Span::new(7, 9, Arc::from("b")));
+    }
+
+    #[test]
+    fn merge_covers_every_span() {
+        let file: Arc<str> = Arc::from("test");
+        let spans = [Span::new(5, 6, file.clone()), Span::new(1, 2, file.clone()), Span::new(8, 12, file)];
+        let merged = Span::merge(&spans).unwrap();
+        assert_eq!((merged.start, merged.end), (1, 12));
+    }
+
+    #[test]
+    fn merge_of_nothing_or_of_several_files_is_none() {
+        assert!(Span::merge(&[]).is_none());
+        assert!(Span::merge(&[Span::new(1, 2, Arc::from("a")), Span::new(3, 4, Arc::from("b"))]).is_none());
+    }
+
+    #[test]
+    fn columns_count_chars_not_bytes() {
+        let source = "let é = 1;\n\"日本\" + x";
+        let lines = LineIndex::new(source);
+        assert_eq!(lines.line_col(source.find('=').unwrap()), (1, 7));
+        assert_eq!(lines.line_col(source.find('x').unwrap()), (2, 8));
+    }
+
+    #[test]
+    fn contains_is_inclusive_of_both_ends() {
+        let span = Span::new(3, 5, Arc::from("test"));
+        assert!(!span.contains(2));
+        assert!(span.contains(3));
+        assert!(span.contains(5));
+        assert!(!span.contains(6));
+    }
+
+    #[test]
+    fn overlaps_at_the_boundaries() {
+        let file: Arc<str> = Arc::from("test");
+        let span = Span::new(3, 5, file.clone());
+        assert!(span.overlaps(&Span::new(5, 8, file.clone())));
+        assert!(span.overlaps(&Span::new(0, 3, file.clone())));
+        assert!(span.overlaps(&Span::new(4, 4, file.clone())));
+        assert!(!span.overlaps(&Span::new(6, 8, file.clone())));
+        assert!(!span.overlaps(&Span::new(0, 2, file)));
+        assert!(!span.overlaps(&Span::new(3, 5, Arc::from("other"))));
+    }
+
+    #[test]
+    fn trim_shrinks_to_the_text() {
+        let source = "let   x   = 1;";
+        let span = Span::new(3, 9, Arc::from("test")).trim(source);
+        assert_eq!((span.start, span.end), (6, 6));
+    }
+
+    #[test]
+    fn trim_leaves_blank_or_out_of_range_spans_alone() {
+        let source = "a    b";
+        let blank = Span::new(1, 4, Arc::from("test"));
+        assert_eq!((blank.trim(source).start, blank.trim(source).end), (1, 4));
+        let past_end = Span::new(4, 20, Arc::from("test"));
+        assert_eq!((past_end.trim(source).start, past_end.trim(source).end), (4, 20));
+    }
+}
@@ -0,0 +1,53 @@
+use std::collections::HashSet;
+use std::rc::Rc;
+
+/// An interned name. Every occurrence of the same name from one `Interner` shares one allocation.
+pub type Symbol = Rc<str>;
+
+#[derive(Default)]
+pub struct Interner {
+    symbols: HashSet<Symbol>
+}
+
+impl Interner {
+    pub fn new() -> Self {
+        Interner {
+            symbols: HashSet::new()
+        }
+    }
+
+    pub fn intern(&mut self, name: &str) -> Symbol {
+        if let Some(symbol) = self.symbols.get(name) {
+            return symbol.clone();
+        }
+        let symbol: Symbol = Rc::from(name);
+        self.symbols.insert(symbol.clone());
+        symbol
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use crate::ast::ASTKind;
+    use super::*;
+
+    #[test]
+    fn same_name_shares_one_allocation() {
+        let mut interner = Interner::new();
+        let a = interner.intern("name");
+        let b = interner.intern("name");
+        assert!(Rc::ptr_eq(&a, &b));
+        assert!(!Rc::ptr_eq(&a, &interner.intern("other")));
+    }
+
+    #[test]
+    fn parsed_identifiers_are_interned() {
+        let (ast, _) = crate::parse(Arc::from("test"), "x; x;");
+        let ast = ast.unwrap();
+        let ASTKind::Block(statements) = ast.kind() else { unreachable!("A program is always a block") };
+        let [ASTKind::Identifier(a), ASTKind::Identifier(b)] = [statements[0].kind(), statements[1].kind()] else { panic!("Expected two identifiers") };
+        assert_eq!(a, b);
+        assert!(Rc::ptr_eq(a, b));
+    }
+}
@@ -5,26 +5,142 @@ use crate::span::Span;
 pub enum TokenKind {
     // Misc
     Period,
+    DotDot,
+    Plus,
+    Minus,
+    Star,
     Slash,
     Equals,
+    PlusEquals,
+    MinusEquals,
+    StarEquals,
+    SlashEquals,
+    EqualsEquals,
+    FatArrow,
+    BangEquals,
+    Less,
+    LessEquals,
+    Greater,
+    GreaterEquals,
+    AmpAmp,
+    PipePipe,
     SemiColon,
+    Colon,
+    Comma,
+    LeftParen,
+    RightParen,
+    LeftBrace,
+    RightBrace,
+    LeftBracket,
+    RightBracket,
 
     // Keywords
     Let,
+    True,
+    False,
+    If,
+    Else,
+    While,
+    Fn,
+    Return,
+    Break,
+    Continue,
+    As,
+    Match,
+    Import,
 
     // Literals
     Identifier,
     StringLiteral,
-    IntegerLiteralBin,
-    IntegerLiteralHex,
-    IntegerLiteralOct,
-    IntegerLiteralDec,
+    IntegerLiteral(Base),
     FloatLiteral,
 
+    // Comments, only produced when the lexer is asked to keep them
+    LineComment,
+    BlockComment,
+
     // Errors and misc tokens
     EOF
 }
 
+impl TokenKind {
+    pub fn is_comment(&self) -> bool {
+        matches!(self, TokenKind::LineComment | TokenKind::BlockComment)
+    }
+
+    /// How tightly a binary operator holds its left and right operands, or `None` if this isn't one.
+    /// Higher binds tighter, and the right side being higher makes every operator left-associative.
+    pub fn binary_binding_power(&self) -> Option<(u8, u8)> {
+        let power = match self {
+            TokenKind::PipePipe => 1,
+            TokenKind::AmpAmp => 3,
+            TokenKind::EqualsEquals | TokenKind::BangEquals => 5,
+            TokenKind::Less | TokenKind::LessEquals | TokenKind::Greater | TokenKind::GreaterEquals => 7,
+            TokenKind::Plus | TokenKind::Minus => 9,
+            TokenKind::Star | TokenKind::Slash => 11,
+            _ => return None
+        };
+        Some((power, power + 1))
+    }
+
+    /// Whether this token can start an expression as a prefix operator, like the `-` of `-x`.
+    pub fn is_prefix_operator(&self) -> bool {
+        matches!(self, TokenKind::Minus)
+    }
+}
+
+/// The base an integer literal is written in, from its `0b`, `0o` or `0x` prefix.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum Base {
+    Bin,
+    Oct,
+    Dec,
+    Hex,
+}
+
+impl Base {
+    pub fn to_string(self) -> &'static str {
+        match self {
+            Base::Bin => "Binary",
+            Base::Oct => "Octal",
+            Base::Dec => "Decimal",
+            Base::Hex => "Hexadecimal"
+        }
+    }
+
+    pub fn radix(self) -> u32 {
+        match self {
+            Base::Bin => 2,
+            Base::Oct => 8,
+            Base::Dec => 10,
+            Base::Hex => 16
+        }
+    }
+
+    /// The length of the prefix before the digits, which decimal literals don't have.
+    pub fn prefix_length(self) -> usize {
+        if self == Base::Dec { 0 } else { 2 }
+    }
+
+    pub fn is_digit(self, byte: u8) -> bool {
+        match self {
+            Base::Bin => matches!(byte, b'0'..=b'1'),
+            Base::Oct => matches!(byte, b'0'..=b'7'),
+            Base::Dec => byte.is_ascii_digit(),
+            Base::Hex => byte.is_ascii_hexdigit()
+        }
+    }
+
+    pub fn valid_digits(self) -> &'static str {
+        match self {
+            Base::Bin => "0 and 1",
+            Base::Oct => "0 through 7",
+            Base::Dec => "0 through 9",
+            Base::Hex => "0 through 9 and a through f"
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct Token<'a> {
     pub kind: TokenKind,
@@ -52,4 +168,28 @@ impl<'a> Display for Token<'a> {
         }
         write!(f, "}}")
     }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn multiplication_binds_tighter_than_addition() {
+        assert_eq!(TokenKind::Plus.binary_binding_power(), Some((9, 10)));
+        assert_eq!(TokenKind::Star.binary_binding_power(), Some((11, 12)));
+        assert_eq!(TokenKind::PipePipe.binary_binding_power(), Some((1, 2)));
+    }
+
+    #[test]
+    fn non_operators_have_no_binding_power() {
+        assert_eq!(TokenKind::Identifier.binary_binding_power(), None);
+        assert_eq!(TokenKind::Equals.binary_binding_power(), None);
+    }
+
+    #[test]
+    fn only_minus_is_a_prefix_operator() {
+        assert!(TokenKind::Minus.is_prefix_operator());
+        assert!(!TokenKind::Plus.is_prefix_operator());
+        assert!(!TokenKind::Identifier.is_prefix_operator());
+    }
+}
@@ -0,0 +1,234 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fmt::{Debug, Display, Formatter};
+use std::io::Write;
+use std::rc::Rc;
+use crate::ast::AST;
+use crate::environment::Environment;
+use ariadne::Color;
+use crate::error::{ErrorReport, ErrorReportKind, Label, Result};
+use crate::lexer::quote;
+use crate::span::Span;
+use crate::symbol::Symbol;
+
+#[derive(Clone, Debug)]
+pub enum Value {
+    Int(isize),
+    Float(f64),
+    Str(String),
+    Bool(bool),
+    Array(Rc<RefCell<Vec<Value>>>),
+    Record(Rc<HashMap<Symbol, Value>>),
+    Function(Rc<Function>),
+    NativeFunction(NativeFunction),
+    Range(Option<isize>, Option<isize>),
+    Unit
+}
+
+/// A built-in receives the interpreter's output sink and each argument alongside the span it was evaluated from.
+pub type Builtin = fn(&mut dyn Write, &[(Value, Span)]) -> Result<Value>;
+
+#[derive(Clone, Debug)]
+pub struct NativeFunction {
+    pub name: &'static str,
+    pub arity: usize,
+    pub function: Builtin
+}
+
+pub struct Function {
+    pub name: Symbol,
+    pub params: Vec<Symbol>,
+    pub body: Rc<AST>,
+    pub closure: Rc<RefCell<Environment>>
+}
+
+impl Debug for Function {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Function({})", self.name)
+    }
+}
+
+impl Value {
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Value::Int(_) => "int",
+            Value::Float(_) => "float",
+            Value::Str(_) => "string",
+            Value::Bool(_) => "bool",
+            Value::Array(_) => "array",
+            Value::Record(_) => "record",
+            Value::Function(_) | Value::NativeFunction(_) => "function",
+            Value::Range(..) => "range",
+            Value::Unit => "unit"
+        }
+    }
+
+    /// The value of a condition, for `if`, `while`, `&&` and `||`. There is no truthiness: only
+    /// bools can be used, anything else is a type error rather than being coerced.
+    pub fn as_bool(&self, span: &Span) -> Result<bool> {
+        match self {
+            Value::Bool(value) => Ok(*value),
+            value => {
+                let e = ErrorReport::new(ErrorReportKind::TypeError, span.clone(), format!("Expected a bool condition but got {}", value.type_name()))
+                    .with_label(Label::new(span.clone()).with_message(format!("This has type {}", value.type_name())).with_color(Color::Red));
+                let e = match value {
+                    Value::Int(_) | Value::Float(_) => e.with_help("Compare it to get a bool, as in `x != 0`.".to_string()),
+                    Value::Str(_) | Value::Array(_) => e.with_help("Check its length to get a bool, as in `len(x) != 0`.".to_string()),
+                    _ => e
+                };
+                Err(e)
+            }
+        }
+    }
+
+    /// Equality across every type. Ints and floats compare by value, functions by identity,
+    /// and values of unrelated types are simply unequal rather than an error.
+    pub fn equals(&self, other: &Value) -> bool {
+        match (self, other) {
+            (Value::Int(lhs), Value::Int(rhs)) => lhs == rhs,
+            (Value::Int(lhs), Value::Float(rhs)) => *lhs as f64 == *rhs,
+            (Value::Float(lhs), Value::Int(rhs)) => *lhs == *rhs as f64,
+            (Value::Float(lhs), Value::Float(rhs)) => lhs == rhs,
+            (Value::Str(lhs), Value::Str(rhs)) => lhs == rhs,
+            (Value::Bool(lhs), Value::Bool(rhs)) => lhs == rhs,
+            (Value::Array(lhs), Value::Array(rhs)) => {
+                let (lhs, rhs) = (lhs.borrow(), rhs.borrow());
+                lhs.len() == rhs.len() && lhs.iter().zip(rhs.iter()).all(|(lhs, rhs)| lhs.equals(rhs))
+            }
+            (Value::Record(lhs), Value::Record(rhs)) =>
+                lhs.len() == rhs.len() && lhs.iter().all(|(name, lhs)| rhs.get(name).is_some_and(|rhs| lhs.equals(rhs))),
+            (Value::Function(lhs), Value::Function(rhs)) => Rc::ptr_eq(lhs, rhs),
+            (Value::NativeFunction(lhs), Value::NativeFunction(rhs)) => lhs.name == rhs.name,
+            (Value::Range(lhs_start, lhs_end), Value::Range(rhs_start, rhs_end)) => lhs_start == rhs_start && lhs_end == rhs_end,
+            (Value::Unit, Value::Unit) => true,
+            _ => false
+        }
+    }
+
+    /// How a value is shown as a result, as opposed to `Display` which is how `print` shows it.
+    /// The two only differ for strings, which are written as a literal that lexes back to the same string.
+    pub fn repr(&self) -> String {
+        match self {
+            Value::Str(val) => quote(val),
+            value => value.to_string()
+        }
+    }
+}
+
+impl Display for Value {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Value::Int(val) => write!(f, "{}", val),
+            // debug formatting always keeps a decimal point or exponent, so `3.0` does not print as `3`
+            Value::Float(val) => write!(f, "{:?}", val),
+            Value::Str(val) => write!(f, "{}", val),
+            Value::Bool(val) => write!(f, "{}", val),
+            Value::Array(values) => {
+                write!(f, "[")?;
+                for (i, value) in values.borrow().iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}", value.repr())?;
+                }
+                write!(f, "]")
+            }
+            Value::Record(fields) => {
+                // fields are unordered, sort them so the output is stable
+                let mut fields: Vec<_> = fields.iter().collect();
+                fields.sort_by_key(|(name, _)| *name);
+                write!(f, "{{ ")?;
+                for (i, (name, value)) in fields.into_iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}: {}", name, value.repr())?;
+                }
+                write!(f, " }}")
+            }
+            Value::Function(function) => write!(f, "<fn {}>", function.name),
+            Value::NativeFunction(function) => write!(f, "<builtin fn {}>", function.name),
+            Value::Range(start, end) => {
+                if let Some(start) = start {
+                    write!(f, "{}", start)?;
+                }
+                write!(f, "..")?;
+                if let Some(end) = end {
+                    write!(f, "{}", end)?;
+                }
+                Ok(())
+            }
+            Value::Unit => write!(f, "()")
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn floats_always_show_a_decimal_point() {
+        assert_eq!(Value::Float(3.0).to_string(), "3.0");
+        assert_eq!(Value::Float(0.5).to_string(), "0.5");
+    }
+
+    #[test]
+    fn ints_bools_and_unit_display_plainly() {
+        assert_eq!(Value::Int(-7).to_string(), "-7");
+        assert_eq!(Value::Bool(true).to_string(), "true");
+        assert_eq!(Value::Bool(false).to_string(), "false");
+        assert_eq!(Value::Unit.to_string(), "()");
+    }
+
+    #[test]
+    fn strings_are_only_quoted_as_a_repr() {
+        let value = Value::Str("a \"b\"".to_string());
+        assert_eq!(value.to_string(), "a \"b\"");
+        assert_eq!(value.repr(), "\"a \\\"b\\\"\"");
+    }
+
+    #[test]
+    fn array_elements_are_shown_as_reprs() {
+        let value = Value::Array(Rc::new(RefCell::new(vec![Value::Int(1), Value::Str("x".to_string())])));
+        assert_eq!(value.to_string(), "[1, \"x\"]");
+    }
+
+    #[test]
+    fn ranges_leave_out_missing_bounds() {
+        assert_eq!(Value::Range(Some(1), Some(3)).to_string(), "1..3");
+        assert_eq!(Value::Range(None, Some(3)).to_string(), "..3");
+        assert_eq!(Value::Range(Some(1), None).to_string(), "1..");
+    }
+
+    #[test]
+    fn only_bools_are_conditions() {
+        let span = Span::new(0, 0, std::sync::Arc::from("test"));
+        assert!(Value::Bool(true).as_bool(&span).unwrap());
+        assert!(!Value::Bool(false).as_bool(&span).unwrap());
+        let error = Value::Int(0).as_bool(&span).unwrap_err();
+        assert_eq!(error.help(), Some("Compare it to get a bool, as in `x != 0`."));
+        let error = Value::Str(String::new()).as_bool(&span).unwrap_err();
+        assert_eq!(error.title(), "TypeError[E0005]: Expected a bool condition but got string");
+        assert_eq!(error.help(), Some("Check its length to get a bool, as in `len(x) != 0`."));
+        assert_eq!(Value::Unit.as_bool(&span).unwrap_err().help(), None);
+    }
+
+    #[test]
+    fn repr_escapes_round_trip() {
+        let text = "line1\nline2 \"quoted\" \\ ${not} \t";
+        let repr = Value::Str(text.to_string()).repr();
+        assert!(!repr.contains('\n'));
+        let (tokens, reports) = crate::lexer::Lexer::lex_str(std::sync::Arc::from("test"), &repr);
+        assert!(reports.is_empty());
+        assert_eq!(tokens.len(), 2);
+        assert_eq!(crate::lexer::string_value(tokens[0].text), text);
+    }
+
+    #[test]
+    fn repr_evaluates_back_to_the_same_string() {
+        let text = "a\nb \"c\" $5 ${x}";
+        let (value, _) = crate::eval(std::sync::Arc::from("test"), &Value::Str(text.to_string()).repr());
+        assert_eq!(value.unwrap().to_string(), text);
+    }
+}
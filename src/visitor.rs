@@ -0,0 +1,260 @@
+use crate::ast::{AST, ASTKind, BinaryOp, StringPart, TypeName, UnaryOp};
+use crate::span::Span;
+use crate::symbol::Symbol;
+
+/// Hooks called by `walk` for each node of a tree. Every hook does nothing by default, so an
+/// analysis only needs to implement the ones it cares about. Children are always walked afterwards.
+#[allow(unused_variables)]
+pub trait Visitor {
+    /// Called for every node, before the hook for its kind.
+    fn visit(&mut self, node: &AST) {}
+
+    fn visit_literal(&mut self, node: &AST) {}
+
+    fn visit_interpolated(&mut self, node: &AST) {}
+
+    fn visit_identifier(&mut self, node: &AST, name: &str) {}
+
+    fn visit_unary(&mut self, node: &AST, op: UnaryOp) {}
+
+    fn visit_binary(&mut self, node: &AST, op: BinaryOp) {}
+
+    fn visit_grouping(&mut self, node: &AST) {}
+
+    fn visit_block(&mut self, node: &AST) {}
+
+    fn visit_if(&mut self, node: &AST) {}
+
+    fn visit_while(&mut self, node: &AST) {}
+
+    fn visit_let(&mut self, node: &AST, name: &str) {}
+
+    fn visit_assign(&mut self, node: &AST, name: &str) {}
+
+    fn visit_function(&mut self, node: &AST, name: &str, params: &[Symbol]) {}
+
+    fn visit_call(&mut self, node: &AST) {}
+
+    fn visit_array(&mut self, node: &AST) {}
+
+    fn visit_record(&mut self, node: &AST) {}
+
+    fn visit_index(&mut self, node: &AST) {}
+
+    fn visit_member(&mut self, node: &AST, name: &str) {}
+
+    fn visit_range(&mut self, node: &AST) {}
+
+    fn visit_cast(&mut self, node: &AST, target: TypeName) {}
+
+    fn visit_match(&mut self, node: &AST) {}
+
+    fn visit_return(&mut self, node: &AST) {}
+
+    fn visit_import(&mut self, node: &AST, path: &str) {}
+
+    fn visit_loop_control(&mut self, node: &AST) {}
+}
+
+/// Visit `node` and then everything below it, in source order.
+pub fn walk(node: &AST, v: &mut impl Visitor) {
+    v.visit(node);
+    match node.kind() {
+        ASTKind::StringLiteral(_)
+        | ASTKind::IntegerLiteral(_)
+        | ASTKind::FloatLiteral(_)
+        | ASTKind::BoolLiteral(_)
+        | ASTKind::UnitLiteral => v.visit_literal(node),
+        ASTKind::InterpolatedString(parts) => {
+            v.visit_interpolated(node);
+            for part in parts {
+                if let StringPart::Expr(expr) = part {
+                    walk(expr, v);
+                }
+            }
+        }
+        ASTKind::Identifier(name) => v.visit_identifier(node, name),
+        ASTKind::Wildcard => {}
+        ASTKind::Grouping(inner) => {
+            v.visit_grouping(node);
+            walk(inner, v);
+        }
+        ASTKind::Unary { op, operand } => {
+            v.visit_unary(node, *op);
+            walk(operand, v);
+        }
+        ASTKind::Binary { op, lhs, rhs, .. } => {
+            v.visit_binary(node, *op);
+            walk(lhs, v);
+            walk(rhs, v);
+        }
+        ASTKind::Block(statements) => {
+            v.visit_block(node);
+            for statement in statements {
+                walk(statement, v);
+            }
+        }
+        ASTKind::If { cond, then_branch, else_branch } => {
+            v.visit_if(node);
+            walk(cond, v);
+            walk(then_branch, v);
+            if let Some(else_branch) = else_branch {
+                walk(else_branch, v);
+            }
+        }
+        ASTKind::While { cond, body } => {
+            v.visit_while(node);
+            walk(cond, v);
+            walk(body, v);
+        }
+        ASTKind::Let { name, value } => {
+            v.visit_let(node, name);
+            walk(value, v);
+        }
+        ASTKind::Assign { name, value } => {
+            v.visit_assign(node, name);
+            walk(value, v);
+        }
+        ASTKind::Function { name, params, body } => {
+            v.visit_function(node, name, params);
+            walk(body, v);
+        }
+        ASTKind::Call { callee, args } => {
+            v.visit_call(node);
+            walk(callee, v);
+            for arg in args {
+                walk(arg, v);
+            }
+        }
+        ASTKind::ArrayLiteral(elements) => {
+            v.visit_array(node);
+            for element in elements {
+                walk(element, v);
+            }
+        }
+        ASTKind::RecordLiteral(fields) => {
+            v.visit_record(node);
+            for (_, value) in fields {
+                walk(value, v);
+            }
+        }
+        ASTKind::Index { object, index } => {
+            v.visit_index(node);
+            walk(object, v);
+            walk(index, v);
+        }
+        ASTKind::Member { object, name } => {
+            v.visit_member(node, name);
+            walk(object, v);
+        }
+        ASTKind::Range { start, end } => {
+            v.visit_range(node);
+            if let Some(start) = start {
+                walk(start, v);
+            }
+            if let Some(end) = end {
+                walk(end, v);
+            }
+        }
+        ASTKind::Cast { expr, target } => {
+            v.visit_cast(node, *target);
+            walk(expr, v);
+        }
+        ASTKind::Match { scrutinee, arms } => {
+            v.visit_match(node);
+            walk(scrutinee, v);
+            for (_, body) in arms {
+                walk(body, v);
+            }
+        }
+        ASTKind::Return(value) => {
+            v.visit_return(node);
+            if let Some(value) = value {
+                walk(value, v);
+            }
+        }
+        ASTKind::Import(path) => v.visit_import(node, path),
+        ASTKind::Break | ASTKind::Continue => v.visit_loop_control(node),
+    }
+}
+
+/// Counts every node in a tree.
+#[derive(Default)]
+pub struct NodeCounter {
+    pub count: usize
+}
+
+impl Visitor for NodeCounter {
+    fn visit(&mut self, _node: &AST) {
+        self.count += 1;
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DeclarationKind {
+    Variable,
+    Function
+}
+
+impl std::fmt::Display for DeclarationKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DeclarationKind::Variable => write!(f, "variable"),
+            DeclarationKind::Function => write!(f, "function")
+        }
+    }
+}
+
+/// Collects every `let` and `fn` in a tree, at any depth, in source order. Each span is the whole
+/// declaration. `let _` declares nothing and is skipped.
+#[derive(Default)]
+pub struct DeclarationCollector {
+    pub declarations: Vec<(Symbol, Span, DeclarationKind)>
+}
+
+impl Visitor for DeclarationCollector {
+    fn visit_let(&mut self, node: &AST, name: &str) {
+        if name != "_" {
+            self.declarations.push((Symbol::from(name), node.span().clone(), DeclarationKind::Variable));
+        }
+    }
+
+    fn visit_function(&mut self, node: &AST, name: &str, _params: &[Symbol]) {
+        self.declarations.push((Symbol::from(name), node.span().clone(), DeclarationKind::Function));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use super::*;
+
+    fn count(source: &str) -> usize {
+        let (ast, reports) = crate::parse(Arc::from("test"), source);
+        assert!(reports.is_empty());
+        let mut counter = NodeCounter::default();
+        walk(&ast.unwrap(), &mut counter);
+        counter.count
+    }
+
+    #[test]
+    fn counts_every_node() {
+        // the program block, the let, the binary and both literals
+        assert_eq!(count("let x = 1 + 2;"), 5);
+    }
+
+    #[test]
+    fn counts_nodes_inside_nested_blocks() {
+        // the program block, the if, its condition, the then block and its literal
+        assert_eq!(count("if true { 1 }"), 5);
+    }
+
+    #[test]
+    fn collects_declarations_at_every_depth() {
+        let (ast, _) = crate::parse(Arc::from("test"), "let a = 1;\nfn f(x) { let b = x; b }\nlet _ = 2;");
+        let mut collector = DeclarationCollector::default();
+        walk(&ast.unwrap(), &mut collector);
+        let found: Vec<_> = collector.declarations.iter().map(|(name, span, kind)| (&**name, span.start, *kind)).collect();
+        assert_eq!(found, [("a", 0, DeclarationKind::Variable), ("f", 11, DeclarationKind::Function), ("b", 21, DeclarationKind::Variable)]);
+    }
+}
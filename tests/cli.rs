@@ -0,0 +1,382 @@
+use std::path::PathBuf;
+use std::process::{Command, Output, Stdio};
+use std::io::Write;
+
+/// Write `contents` to a fresh file for one test, returning its path.
+fn source_file(name: &str, contents: &str) -> PathBuf {
+    let path = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join(name);
+    std::fs::write(&path, contents).unwrap();
+    path
+}
+
+/// Run the binary with `args`, feeding it `stdin`, with color forced off.
+fn lox(args: &[&str], stdin: &str) -> Output {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_lox"))
+        .args(args)
+        .env("NO_COLOR", "1")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child.stdin.take().unwrap().write_all(stdin.as_bytes()).unwrap();
+    child.wait_with_output().unwrap()
+}
+
+fn stderr(output: &Output) -> String {
+    String::from_utf8(output.stderr.clone()).unwrap()
+}
+
+#[test]
+fn errors_and_warnings_are_counted_separately() {
+    let path = source_file("counts.nl", "{ let a = 1; let b = 2; }\nprint(missing);\n");
+    let output = lox(&[path.to_str().unwrap()], "");
+    assert!(stderr(&output).contains("Emitted 1 error, 2 warnings."), "{}", stderr(&output));
+}
+
+#[test]
+fn json_error_level_prints_one_object_per_line() {
+    let path = source_file("json.nl", "let x = 0b102;\n");
+    let output = lox(&["--error-level", "json", path.to_str().unwrap()], "");
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let lines: Vec<&str> = stdout.lines().collect();
+    assert_eq!(lines.len(), 1, "{}", stdout);
+    assert!(lines[0].starts_with(r#"{"severity":"error","title":"SyntaxError[E0001]: Invalid Integer Literal""#), "{}", lines[0]);
+    assert!(lines[0].contains(r#""start":8,"end":12,"labels":[{"start":8,"end":12,"#), "{}", lines[0]);
+    assert!(lines[0].contains(r#"{"start":12,"end":13,"message":"Invalid character"}"#), "{}", lines[0]);
+}
+
+#[test]
+fn unit_results_are_not_printed() {
+    let path = source_file("unit.nl", "let x = 1;\n");
+    let output = lox(&[path.to_str().unwrap()], "");
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "");
+}
+
+#[test]
+fn other_results_are_printed() {
+    let path = source_file("result.nl", "let x = 1;\nx + 1\n");
+    let output = lox(&[path.to_str().unwrap()], "");
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "2\n");
+}
+
+#[test]
+fn emit_tokens_stops_before_parsing() {
+    let path = source_file("tokens.nl", "let = ;");
+    let output = lox(&["--emit", "tokens", path.to_str().unwrap()], "");
+    assert!(output.status.success(), "{}", stderr(&output));
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let lines: Vec<&str> = stdout.lines().collect();
+    assert_eq!(lines.len(), 4, "{}", stdout);
+    assert!(lines[0].starts_with("0: 1:1-1:3 Token{Let"), "{}", lines[0]);
+    assert!(lines[3].contains("Token{EOF"), "{}", lines[3]);
+}
+
+#[test]
+fn program_is_read_from_stdin_after_a_dash() {
+    let output = lox(&["-"], "let x = 2;\nx * 3\n");
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "6\n");
+}
+
+#[test]
+fn piped_program_is_read_without_a_filename() {
+    let output = lox(&[], "println(\"piped\");\n");
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "piped\n");
+}
+
+#[test]
+fn stdin_reports_name_it() {
+    let output = lox(&["-"], "let x = ;\n");
+    assert!(stderr(&output).contains("<stdin>"), "{}", stderr(&output));
+}
+
+#[test]
+fn long_expression_chains_are_reported_without_crashing() {
+    let path = source_file("chain.nl", &vec!["1"; 10000].join(" + "));
+    for args in [&["--emit", "ast", path.to_str().unwrap()][..], &[path.to_str().unwrap()][..]] {
+        let output = lox(args, "");
+        assert_eq!(output.status.code(), Some(69), "{}", stderr(&output));
+        assert!(stderr(&output).contains("Expression Nested Too Deeply"));
+    }
+}
+
+#[test]
+fn no_color_flag_turns_off_escape_codes() {
+    let path = source_file("color.nl", "{ let unused = 1; }\nlet x = 0b12;\n");
+    for level in ["compact", "normal"] {
+        let output = Command::new(env!("CARGO_BIN_EXE_lox"))
+            .args(["--no-color", "--error-level", level, path.to_str().unwrap()])
+            .output()
+            .unwrap();
+        assert!(!stderr(&output).contains('\x1b'), "{}", stderr(&output));
+    }
+}
+
+#[test]
+fn exit_code_tells_which_phase_failed() {
+    let cases = [("lex_error.nl", "let x = 0b12;", 64), ("parse_error.nl", "let = 1;", 69), ("runtime_error.nl", "1 + true", 70), ("ok.nl", "1 + 1", 0)];
+    for (name, source, code) in cases {
+        let path = source_file(name, source);
+        let output = lox(&[path.to_str().unwrap()], "");
+        assert_eq!(output.status.code(), Some(code), "{}: {}", name, stderr(&output));
+    }
+}
+
+#[test]
+fn missing_file_is_an_io_error() {
+    let output = lox(&["does/not/exist.nl"], "");
+    assert_eq!(output.status.code(), Some(66));
+}
+
+#[test]
+fn eof_in_an_expression_shows_the_source() {
+    let path = source_file("eof.nl", "let x =");
+    let output = lox(&[path.to_str().unwrap()], "");
+    assert!(stderr(&output).contains("Unexpected EOF"), "{}", stderr(&output));
+    assert!(stderr(&output).contains("1 │ let x ="), "{}", stderr(&output));
+}
+
+#[test]
+fn max_errors_caps_what_is_printed() {
+    let path = source_file("many_lex_errors.nl", "0b2; 0b3; 0b4; 0b5; 0b6;");
+    let output = lox(&["--max-errors", "2", path.to_str().unwrap()], "");
+    let stderr = stderr(&output);
+    assert_eq!(stderr.matches("Invalid Integer Literal").count(), 2, "{}", stderr);
+    assert!(stderr.contains("Emitted 2 errors.\n2 errors shown; more were suppressed."), "{}", stderr);
+}
+
+#[test]
+fn max_errors_stops_the_parser() {
+    let path = source_file("many_parse_errors.nl", "let = 1; let = 2; let = 3; let = 4;");
+    let output = lox(&["--max-errors", "2", path.to_str().unwrap()], "");
+    let stderr = stderr(&output);
+    assert_eq!(stderr.matches("UnexpectedToken").count(), 2, "{}", stderr);
+    assert!(stderr.contains("2 errors shown; more were suppressed."), "{}", stderr);
+}
+
+#[test]
+fn default_max_errors_notes_the_suppressed_ones() {
+    let path = source_file("thirty_parse_errors.nl", &"let = 1;\n".repeat(30));
+    let output = lox(&[path.to_str().unwrap()], "");
+    let stderr = stderr(&output);
+    assert_eq!(stderr.matches("UnexpectedToken").count(), 25, "{}", stderr);
+    assert!(stderr.contains("25 errors shown; more were suppressed."), "{}", stderr);
+}
+
+#[test]
+fn zero_max_errors_prints_every_error() {
+    let path = source_file("every_parse_error.nl", &"let = 1;\n".repeat(30));
+    let output = lox(&["--max-errors", "0", path.to_str().unwrap()], "");
+    let stderr = stderr(&output);
+    assert_eq!(stderr.matches("UnexpectedToken").count(), 30, "{}", stderr);
+    assert!(!stderr.contains("suppressed"), "{}", stderr);
+}
+
+#[test]
+fn debug_token_dump_shows_lines_and_columns() {
+    let path = source_file("debug_tokens.nl", "let x = 1;\nx");
+    let output = lox(&["--debug", "--emit", "tokens", path.to_str().unwrap()], "");
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let lines: Vec<&str> = stdout.lines().collect();
+    assert_eq!(lines.len(), 7, "{}", stdout);
+    assert!(lines[1].starts_with("1: 1:5-1:5 Token{Identifier"), "{}", lines[1]);
+    assert!(lines[5].starts_with("5: 2:1-2:1 Token{Identifier"), "{}", lines[5]);
+    assert!(lines[5].ends_with(", nl=true}"), "{}", lines[5]);
+}
+
+#[test]
+fn info_prints_the_version_target_and_features() {
+    let output = lox(&["info"], "");
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.starts_with("lox 0.0.1\n"), "{}", stdout);
+    assert!(stdout.contains("\ntarget: "), "{}", stdout);
+    assert!(stdout.contains("functions"), "{}", stdout);
+}
+
+#[test]
+fn max_steps_stops_an_infinite_loop() {
+    let path = source_file("forever.nl", "while true { }\n");
+    let output = lox(&["--max-steps", "100", path.to_str().unwrap()], "");
+    assert_eq!(output.status.code(), Some(70));
+    assert!(stderr(&output).contains("Execution Limit Exceeded"), "{}", stderr(&output));
+}
+
+#[test]
+fn deep_recursion_is_an_error_not_a_crash() {
+    let recursion = "fn f(n) { if n == 0 { 0 } else { 1 + f(n - 1) } }\n";
+    let path = source_file("shallow.nl", &format!("{}f(200)\n", recursion));
+    let output = lox(&[path.to_str().unwrap()], "");
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "200\n");
+    let path = source_file("deep.nl", &format!("{}f(2000)\n", recursion));
+    let output = lox(&[path.to_str().unwrap()], "");
+    assert_eq!(output.status.code(), Some(70));
+    assert!(stderr(&output).contains("Call Depth Exceeded"), "{}", stderr(&output));
+}
+
+#[test]
+fn time_reports_every_phase() {
+    let path = source_file("timed.nl", "1 + 1\n");
+    let output = lox(&["--time", path.to_str().unwrap()], "");
+    assert!(output.status.success());
+    let stderr = stderr(&output);
+    let phases: Vec<&str> = stderr.lines().map(|line| line.split(':').next().unwrap().trim()).collect();
+    assert_eq!(phases, ["lex", "parse", "resolve", "run"]);
+}
+
+#[test]
+fn time_is_silenced_with_everything_else() {
+    let path = source_file("timed_silent.nl", "1 + 1\n");
+    let output = lox(&["--time", "--error-level", "silent", path.to_str().unwrap()], "");
+    assert_eq!(stderr(&output), "");
+}
+
+#[test]
+fn dump_reports_writes_warnings_from_a_successful_run() {
+    let path = source_file("dump_warning.nl", "{ let a = 1; }\n");
+    let dump = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join("dump_warning.json");
+    let output = lox(&["--dump-reports", dump.to_str().unwrap(), path.to_str().unwrap()], "");
+    assert!(output.status.success());
+    let json = std::fs::read_to_string(&dump).unwrap();
+    assert_eq!(json.lines().count(), 1, "{}", json);
+    assert!(json.starts_with(r#"{"severity":"warning","title":"NameError[E0006]: Unused variable `a`""#), "{}", json);
+}
+
+#[test]
+fn dump_reports_writes_an_empty_file_for_a_clean_run() {
+    let path = source_file("dump_clean.nl", "1\n");
+    let dump = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join("dump_clean.json");
+    let output = lox(&["--dump-reports", dump.to_str().unwrap(), path.to_str().unwrap()], "");
+    assert!(output.status.success());
+    assert_eq!(std::fs::read_to_string(&dump).unwrap(), "");
+}
+
+#[test]
+fn dump_reports_still_writes_when_the_run_fails() {
+    let path = source_file("dump_error.nl", "print(missing);\n");
+    let dump = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join("dump_error.json");
+    let output = lox(&["--dump-reports", dump.to_str().unwrap(), path.to_str().unwrap()], "");
+    assert!(!output.status.success());
+    assert!(std::fs::read_to_string(&dump).unwrap().contains(r#""severity":"error""#));
+}
+
+#[test]
+fn fmt_prints_the_formatted_source() {
+    let path = source_file("fmt_print.nl", "let x=0xFF;// hex\n");
+    let output = lox(&["fmt", path.to_str().unwrap()], "");
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "let x = 0xFF; // hex\n");
+    assert_eq!(std::fs::read_to_string(&path).unwrap(), "let x=0xFF;// hex\n");
+}
+
+#[test]
+fn fmt_write_keeps_comments_and_literals() {
+    let path = source_file("fmt_write.nl", "// count down\nlet n=1_000u16;\nwhile n>0{n=n-1;// step\n}\n");
+    let output = lox(&["fmt", "--write", "--indent", "4", path.to_str().unwrap()], "");
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "");
+    assert_eq!(std::fs::read_to_string(&path).unwrap(), "// count down\nlet n = 1_000u16;\nwhile n > 0 {\n    n = n - 1; // step\n}\n");
+}
+
+#[test]
+fn fmt_leaves_a_broken_file_alone() {
+    let path = source_file("fmt_broken.nl", "let x = ;\n");
+    let output = lox(&["fmt", "--write", path.to_str().unwrap()], "");
+    assert_eq!(output.status.code(), Some(69));
+    assert_eq!(std::fs::read_to_string(&path).unwrap(), "let x = ;\n");
+}
+
+#[test]
+fn check_is_silent_on_a_valid_file() {
+    let path = source_file("check_ok.nl", "println(\"never run\");\n");
+    let output = lox(&["--check", path.to_str().unwrap()], "");
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(stderr(&output), "");
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "");
+}
+
+#[test]
+fn check_reports_every_parse_error() {
+    let path = source_file("check_broken.nl", "let x = ;\nlet = 2;\n");
+    let output = lox(&["--check", path.to_str().unwrap()], "");
+    assert_eq!(output.status.code(), Some(69));
+    assert!(stderr(&output).contains("Emitted 2 errors."), "{}", stderr(&output));
+}
+
+#[test]
+fn check_does_not_run_the_program() {
+    let path = source_file("check_runtime.nl", "1 / 0\n");
+    let output = lox(&["--check", path.to_str().unwrap()], "");
+    assert_eq!(output.status.code(), Some(0));
+}
+
+#[test]
+fn imported_declarations_are_usable() {
+    let dir = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join("cli_import");
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(dir.join("lib.nl"), "fn double(x) { x * 2 }\n").unwrap();
+    std::fs::write(dir.join("main.nl"), "import \"lib.nl\";\ndouble(21)\n").unwrap();
+    let output = lox(&[dir.join("main.nl").to_str().unwrap()], "");
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "42\n");
+}
+
+#[test]
+fn symbols_lists_every_declaration() {
+    let path = source_file("symbols.nl", "let a = 1;\nfn add(x, y) {\n  let sum = x + y;\n  sum\n}\n");
+    let output = lox(&["symbols", path.to_str().unwrap()], "");
+    assert!(output.status.success());
+    let file = path.to_str().unwrap();
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        format!("a  {0}:1:1  variable\nadd  {0}:2:1  function\nsum  {0}:3:3  variable\n", file)
+    );
+}
+
+#[test]
+fn symbols_ignores_imports() {
+    let path = source_file("symbols_import.nl", "import \"nowhere.nl\";\nlet a = 1;\n");
+    let output = lox(&["symbols", path.to_str().unwrap()], "");
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(String::from_utf8(output.stdout).unwrap().starts_with("a  "));
+}
+
+#[test]
+fn strict_numeric_flag_changes_mixed_arithmetic() {
+    let path = source_file("mixed.nl", "1 + 2.0\n");
+    let output = lox(&[path.to_str().unwrap()], "");
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "3.0\n");
+    let output = lox(&["--strict-numeric", path.to_str().unwrap()], "");
+    assert_eq!(output.status.code(), Some(70));
+    assert!(stderr(&output).contains("Cannot apply `+` to int and float"), "{}", stderr(&output));
+}
+
+#[test]
+fn check_indentation_flag_advises_on_mixed_indentation() {
+    let path = source_file("mixed_indent.nl", "{\n  let a = 1;\n\tprint(a);\n}\n");
+    let output = lox(&["--check-indentation", path.to_str().unwrap()], "");
+    assert!(output.status.success());
+    assert!(stderr(&output).contains("IndentationStyle[E0009]: Mixed Indentation"), "{}", stderr(&output));
+    let output = lox(&[path.to_str().unwrap()], "");
+    assert!(!stderr(&output).contains("Mixed Indentation"), "{}", stderr(&output));
+}
+
+#[test]
+fn explain_prints_a_known_code() {
+    let output = lox(&["explain", "e0005"], "");
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.starts_with("TypeError[E0005]\n\n"), "{}", stdout);
+    assert!(stdout.contains("Erroneous example"), "{}", stdout);
+    assert!(stdout.contains("promoted to a float unless `--strict-numeric` is set"), "{}", stdout);
+}
+
+#[test]
+fn explain_rejects_an_unknown_code() {
+    let output = lox(&["explain", "E9999"], "");
+    assert_eq!(output.status.code(), Some(2));
+    assert_eq!(stderr(&output), "Unknown error code E9999.\n");
+    assert!(output.stdout.is_empty());
+}
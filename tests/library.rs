@@ -0,0 +1,78 @@
+use std::sync::Arc;
+use lox::error::ErrorReport;
+use lox::token::TokenKind;
+
+#[test]
+fn lex_returns_the_tokens() {
+    let (tokens, reports) = lox::lex(Arc::from("test"), "let x");
+    assert!(reports.is_empty());
+    let kinds: Vec<TokenKind> = tokens.unwrap().into_iter().map(|token| token.kind).collect();
+    assert_eq!(kinds, [TokenKind::Let, TokenKind::Identifier, TokenKind::EOF]);
+}
+
+#[test]
+fn parse_returns_no_tree_on_error() {
+    let (ast, reports) = lox::parse(Arc::from("test"), "let = 1;");
+    assert!(ast.is_none());
+    assert!(reports.iter().any(ErrorReport::is_error));
+}
+
+#[test]
+fn eval_returns_the_last_value() {
+    let (value, reports) = lox::eval(Arc::from("test"), "let x = 20; x * 2 + 2");
+    assert!(reports.is_empty());
+    assert_eq!(value.unwrap().repr(), "42");
+}
+
+#[test]
+fn eval_reports_runtime_errors() {
+    let (value, reports) = lox::eval(Arc::from("test"), "1 + true");
+    assert!(value.is_none());
+    assert_eq!(reports.len(), 1);
+}
+
+/// Write `files` into a fresh directory for one test, returning the path of the first.
+fn project(name: &str, files: &[(&str, &str)]) -> std::path::PathBuf {
+    let dir = std::path::PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join(name);
+    std::fs::create_dir_all(&dir).unwrap();
+    for (file, contents) in files {
+        std::fs::write(dir.join(file), contents).unwrap();
+    }
+    dir.join(files[0].0)
+}
+
+#[test]
+fn eval_resolves_imports() {
+    let main = project("eval_import", &[("main.nl", "import \"lib.nl\";\nanswer + 1"), ("lib.nl", "let answer = 41;\n")]);
+    let source = std::fs::read_to_string(&main).unwrap();
+    let (value, reports) = lox::eval(Arc::from(main.to_str().unwrap()), &source);
+    assert!(reports.is_empty(), "{:?}", reports.iter().map(ErrorReport::title).collect::<Vec<_>>());
+    assert_eq!(value.unwrap().repr(), "42");
+}
+
+#[test]
+fn parse_reports_circular_imports() {
+    let main = project("circular_import", &[("a.nl", "import \"b.nl\";\n"), ("b.nl", "import \"a.nl\";\n")]);
+    let source = std::fs::read_to_string(&main).unwrap();
+    let (ast, reports) = lox::parse(Arc::from(main.to_str().unwrap()), &source);
+    assert!(ast.is_none());
+    assert_eq!(reports.len(), 1);
+    assert_eq!(reports[0].title(), "ImportError[E0008]: Circular import of `a.nl`");
+    // the report points into the imported file
+    assert!(reports[0].span().filename.ends_with("b.nl"), "{}", reports[0].span().filename);
+}
+
+#[test]
+fn parse_reports_missing_imports() {
+    let (ast, reports) = lox::parse(Arc::from("test"), "import \"missing.nl\";");
+    assert!(ast.is_none());
+    assert_eq!(reports[0].title(), "ImportError[E0008]: Could not find `missing.nl`");
+}
+
+#[test]
+fn parse_tokens_leaves_imports_alone() {
+    let (tokens, reports) = lox::lex(Arc::from("test"), "import \"missing.nl\";");
+    let (ast, reports) = lox::parse_tokens(&tokens.unwrap(), reports);
+    assert!(reports.is_empty());
+    assert!(ast.is_some());
+}